@@ -0,0 +1,454 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{glob, permissions::Permissions};
+
+// Records queued for the writer at most, further records are dropped until it
+// catches up, or wait for it if they must not be dropped
+const QUEUE_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Api {
+    #[serde(rename = "kuksa.val.v1")]
+    KuksaValV1,
+    #[serde(rename = "kuksa.val.v2")]
+    KuksaValV2,
+    #[serde(rename = "sdv.databroker.v1")]
+    SdvDatabrokerV1,
+    #[serde(rename = "viss")]
+    Viss,
+    #[serde(rename = "mqtt")]
+    Mqtt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    // Request to actuate an actuator, i.e. to set its target value
+    Actuate,
+    // Current value published by a provider
+    Publish,
+    // Registration of an entry or change of its metadata
+    Metadata,
+    // Rejected credentials
+    Authorization,
+    // Records dropped as the audit log could not keep up, their number as value
+    Dropped,
+}
+
+/// An audited event, written to the audit log as a single line of JSON.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    timestamp: String,
+    event: Event,
+    api: Option<Api>,
+    subject: Option<String>,
+    issuer: Option<String>,
+    path: Option<String>,
+    value: Option<String>,
+    result: String,
+}
+
+impl Record {
+    pub fn new(event: Event, api: Option<Api>) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            event,
+            api,
+            subject: None,
+            issuer: None,
+            path: None,
+            value: None,
+            result: "ok".to_owned(),
+        }
+    }
+
+    /// Identify the caller by the subject and issuer of its permissions.
+    pub fn caller(mut self, permissions: &Permissions) -> Self {
+        self.subject = permissions.subject().map(|subject| subject.to_owned());
+        self.issuer = permissions.issuer().map(|issuer| issuer.to_owned());
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn value(mut self, value: impl ToString) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    pub fn error(mut self, error: impl Into<String>) -> Self {
+        self.result = error.into();
+        self
+    }
+
+    // The record as a line of the log
+    fn line(&self) -> Option<String> {
+        match serde_json::to_string(self) {
+            Ok(mut line) => {
+                line.push('\n');
+                Some(line)
+            }
+            Err(err) => {
+                warn!("Failed to serialize audit record: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Append-only log of actuations, publishes, metadata changes and rejected
+/// credentials, rotated when it grows beyond a maximum size.
+///
+/// When rotated, the log file is renamed with the suffix `.1`, replacing the
+/// previous `.1` file which is renamed to `.2` and so on, up to the number of
+/// rotated files to keep.
+///
+/// Records are written by a dedicated thread, so recording them does not block
+/// on the file system. If the writer falls behind by more than a bounded
+/// number of records, further publishes and metadata changes are dropped, and
+/// their number is recorded once the writer catches up. Actuations and
+/// rejected credentials are never dropped, recording them waits for the writer
+/// instead.
+pub struct AuditLog {
+    // None once dropped, which stops the writer
+    sender: Option<mpsc::SyncSender<Message>>,
+    writer: Option<thread::JoinHandle<()>>,
+    // Records dropped since the writer last caught up, and in total
+    dropped: Arc<AtomicU64>,
+    dropped_total: AtomicU64,
+    // Publishes are only recorded for the signals matching these
+    publish_patterns: Vec<glob::Matcher>,
+}
+
+enum Message {
+    Line(String),
+    // Acknowledged once the preceding lines are written
+    Flush(mpsc::Sender<()>),
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl AuditLog {
+    pub fn open(path: impl Into<PathBuf>, max_size: u64, max_files: usize) -> io::Result<Self> {
+        Self::open_with_queue_size(path, max_size, max_files, QUEUE_SIZE)
+    }
+
+    fn open_with_queue_size(
+        path: impl Into<PathBuf>,
+        max_size: u64,
+        max_files: usize,
+        queue_size: usize,
+    ) -> io::Result<Self> {
+        let mut file = LogFile::open(path.into())?;
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer_dropped = dropped.clone();
+        let writer = thread::Builder::new()
+            .name("audit-log".to_owned())
+            .spawn(move || {
+                let mut write = |line: &str| {
+                    if let Err(err) = file.append(line.as_bytes(), max_size, max_files) {
+                        warn!("Failed to write audit log {}: {}", file.path.display(), err);
+                    }
+                };
+                for message in receiver {
+                    if let Message::Line(line) = &message {
+                        write(line);
+                    }
+                    let dropped = writer_dropped.swap(0, Ordering::Relaxed);
+                    if dropped > 0 {
+                        warn!(
+                            "Dropped {} audit records, the audit log could not keep up",
+                            dropped
+                        );
+                        if let Some(line) = Record::new(Event::Dropped, None).value(dropped).line()
+                        {
+                            write(&line);
+                        }
+                    }
+                    if let Message::Flush(done) = message {
+                        let _ = done.send(());
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            dropped,
+            dropped_total: AtomicU64::new(0),
+            publish_patterns: Vec::new(),
+        })
+    }
+
+    /// Also record the values published to signals matching any of `patterns`.
+    pub fn publish_patterns(mut self, patterns: Vec<glob::Matcher>) -> Self {
+        self.publish_patterns = patterns;
+        self
+    }
+
+    pub fn records_publish(&self, path: &str) -> bool {
+        // Patterns match the path with '/' as separator, like Metadata::glob_path
+        let glob_path = path.replace('.', "/");
+        self.publish_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&glob_path))
+    }
+
+    /// Queue a record to be appended to the log. Failing to write it is
+    /// logged, but not reported to the caller.
+    ///
+    /// Waits for room in the queue if the record is an actuation or rejected
+    /// credentials, other records are dropped if the queue is full.
+    pub fn record(&self, record: Record) {
+        let Some(line) = record.line() else {
+            return;
+        };
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let message = Message::Line(line);
+        let result = match record.event {
+            Event::Actuate | Event::Authorization => sender
+                .send(message)
+                .map_err(|mpsc::SendError(message)| mpsc::TrySendError::Disconnected(message)),
+            Event::Publish | Event::Metadata | Event::Dropped => sender.try_send(message),
+        };
+        match result {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.dropped_total.fetch_add(1, Ordering::Relaxed);
+                // Warned once until the writer catches up
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Audit log queue full, dropping records");
+                }
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                warn!("Failed to queue audit record, the audit log writer stopped");
+            }
+        }
+    }
+
+    /// Number of records dropped, as the writer could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped_total.load(Ordering::Relaxed)
+    }
+
+    /// Wait until the records queued so far are written.
+    pub fn flush(&self) {
+        if let Some(sender) = &self.sender {
+            let (done, written) = mpsc::channel();
+            if sender.send(Message::Flush(done)).is_ok() {
+                let _ = written.recv();
+            }
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    // Write the queued records before closing the log
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn append(&mut self, line: &[u8], max_size: u64, max_files: usize) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > max_size {
+            self.rotate(max_files)?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, max_files: usize) -> io::Result<()> {
+        if max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{index}"));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::PermissionBuilder;
+
+    fn read_records(path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .expect("audit log should be readable")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("record should be JSON"))
+            .collect()
+    }
+
+    #[test]
+    fn test_record() {
+        let path = std::env::temp_dir().join(format!("audit-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let permissions = PermissionBuilder::new()
+            .subject("trunk-app")
+            .issuer("local")
+            .build()
+            .expect("permissions should build");
+        let audit_log = AuditLog::open(&path, 1024 * 1024, 1).expect("audit log should open");
+        audit_log.record(
+            Record::new(Event::Actuate, Some(Api::KuksaValV2))
+                .caller(&permissions)
+                .path("Vehicle.Body.Trunk.Rear.IsOpen")
+                .value(true),
+        );
+        audit_log
+            .record(Record::new(Event::Authorization, Some(Api::Viss)).error("Invalid auth token"));
+        audit_log.flush();
+
+        let records = read_records(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["event"], "actuate");
+        assert_eq!(records[0]["api"], "kuksa.val.v2");
+        assert_eq!(records[0]["subject"], "trunk-app");
+        assert_eq!(records[0]["issuer"], "local");
+        assert_eq!(records[0]["path"], "Vehicle.Body.Trunk.Rear.IsOpen");
+        assert_eq!(records[0]["value"], "true");
+        assert_eq!(records[0]["result"], "ok");
+        assert_eq!(records[1]["event"], "authorization");
+        assert_eq!(records[1]["subject"], serde_json::Value::Null);
+        assert_eq!(records[1]["result"], "Invalid auth token");
+
+        fs::remove_file(&path).expect("audit log should be removable");
+    }
+
+    #[test]
+    fn test_rotate() {
+        let path = std::env::temp_dir().join(format!("audit-rotate-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // Room for a single record per file
+        let audit_log = AuditLog::open(&path, 200, 2).expect("audit log should open");
+        for value in 0..4 {
+            audit_log.record(
+                Record::new(Event::Publish, Some(Api::SdvDatabrokerV1))
+                    .path("Vehicle.Speed")
+                    .value(value),
+            );
+        }
+        audit_log.flush();
+
+        assert_eq!(read_records(&path)[0]["value"], "3");
+        assert_eq!(read_records(&rotated_path(&path, 1))[0]["value"], "2");
+        assert_eq!(read_records(&rotated_path(&path, 2))[0]["value"], "1");
+        assert!(!rotated_path(&path, 3).exists());
+
+        for file in [path.clone(), rotated_path(&path, 1), rotated_path(&path, 2)] {
+            fs::remove_file(file).expect("audit log should be removable");
+        }
+    }
+
+    #[test]
+    fn test_dropped() {
+        let path = std::env::temp_dir().join(format!("audit-dropped-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // Publishes are dropped rather than queued without bound, actuations
+        // are never dropped
+        let audit_log = AuditLog::open_with_queue_size(&path, 1024 * 1024, 1, 1)
+            .expect("audit log should open");
+        for value in 0..1000 {
+            audit_log.record(Record::new(Event::Publish, None).value(value));
+            audit_log.record(Record::new(Event::Actuate, None).value(value));
+        }
+        audit_log.flush();
+
+        let records = read_records(&path);
+        fs::remove_file(&path).expect("audit log should be removable");
+        let events = |event: &str| -> Vec<&serde_json::Value> {
+            records
+                .iter()
+                .filter(|record| record["event"] == event)
+                .collect()
+        };
+        assert_eq!(events("actuate").len(), 1000);
+        // The number of dropped records is recorded in the log itself
+        let dropped: u64 = events("dropped")
+            .into_iter()
+            .map(|record| {
+                record["value"]
+                    .as_str()
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .expect("dropped record should have a count")
+            })
+            .sum();
+        assert_eq!(dropped, audit_log.dropped());
+        assert_eq!(events("publish").len() as u64 + dropped, 1000);
+    }
+
+    #[test]
+    fn test_records_publish() {
+        let path = std::env::temp_dir().join(format!("audit-publish-{}.log", std::process::id()));
+        let audit_log = AuditLog::open(&path, 1024, 1)
+            .expect("audit log should open")
+            .publish_patterns(vec![
+                glob::Matcher::new("Vehicle.Body.**").expect("pattern should be valid")
+            ]);
+        assert!(audit_log.records_publish("Vehicle.Body.Trunk.Rear.IsOpen"));
+        assert!(!audit_log.records_publish("Vehicle.Speed"));
+        fs::remove_file(&path).expect("audit log should be removable");
+    }
+}
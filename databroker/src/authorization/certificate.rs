@@ -0,0 +1,158 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use simple_asn1::{from_der, ASN1Block, ASN1Class, BigUint, OID};
+
+const COMMON_NAME: [u64; 4] = [2, 5, 4, 3];
+const SUBJECT_ALT_NAME: [u64; 4] = [2, 5, 29, 17];
+
+// Context specific tags of the GeneralName choices used as identities
+const RFC822_NAME: u8 = 1;
+const DNS_NAME: u8 = 2;
+const URI: u8 = 6;
+
+/// Names identifying the subject of a DER encoded X.509 certificate.
+///
+/// These are the common names of the subject followed by the email, DNS and
+/// URI subject alternative names. Returns `None` if the certificate cannot
+/// be parsed.
+pub fn subject_names(der: &[u8]) -> Option<Vec<String>> {
+    let certificate = match from_der(der).ok()?.into_iter().next()? {
+        ASN1Block::Sequence(_, certificate) => certificate,
+        _ => return None,
+    };
+    let tbs_certificate = match certificate.into_iter().next()? {
+        ASN1Block::Sequence(_, tbs_certificate) => tbs_certificate,
+        _ => return None,
+    };
+
+    // Skip the optional version, serial number, signature, issuer and validity
+    let skip = match tbs_certificate.first()? {
+        ASN1Block::Explicit(ASN1Class::ContextSpecific, _, tag, _)
+            if *tag == BigUint::from(0u8) =>
+        {
+            5
+        }
+        _ => 4,
+    };
+    let mut fields = tbs_certificate.into_iter().skip(skip);
+
+    let mut names = match fields.next()? {
+        ASN1Block::Sequence(_, subject) => common_names(subject),
+        _ => return None,
+    };
+
+    for field in fields {
+        if let ASN1Block::Explicit(ASN1Class::ContextSpecific, _, tag, extensions) = field {
+            if tag == BigUint::from(3u8) {
+                if let ASN1Block::Sequence(_, extensions) = *extensions {
+                    names.extend(subject_alt_names(extensions));
+                }
+            }
+        }
+    }
+
+    Some(names)
+}
+
+fn is_oid(oid: &OID, expected: &[u64]) -> bool {
+    oid.as_vec::<u64>()
+        .map(|components| components == expected)
+        .unwrap_or(false)
+}
+
+fn common_names(subject: Vec<ASN1Block>) -> Vec<String> {
+    subject
+        .into_iter()
+        .filter_map(|rdn| match rdn {
+            ASN1Block::Set(_, attributes) => Some(attributes),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|attribute| match attribute {
+            ASN1Block::Sequence(_, attribute) => match attribute.as_slice() {
+                [ASN1Block::ObjectIdentifier(_, oid), ASN1Block::UTF8String(_, name)
+                | ASN1Block::PrintableString(_, name)
+                | ASN1Block::TeletexString(_, name)
+                | ASN1Block::IA5String(_, name)
+                | ASN1Block::UniversalString(_, name)
+                | ASN1Block::BMPString(_, name)]
+                    if is_oid(oid, &COMMON_NAME) =>
+                {
+                    Some(name.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn subject_alt_names(extensions: Vec<ASN1Block>) -> Vec<String> {
+    extensions
+        .into_iter()
+        .filter_map(|extension| match extension {
+            ASN1Block::Sequence(_, extension) => match extension.as_slice() {
+                // The "critical" flag is optional
+                [ASN1Block::ObjectIdentifier(_, oid), .., ASN1Block::OctetString(_, value)]
+                    if is_oid(oid, &SUBJECT_ALT_NAME) =>
+                {
+                    from_der(value).ok()
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|general_names| match general_names {
+            ASN1Block::Sequence(_, general_names) => Some(general_names),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|general_name| match general_name {
+            ASN1Block::Unknown(ASN1Class::ContextSpecific, false, _, tag, value)
+                if [RFC822_NAME, DNS_NAME, URI]
+                    .iter()
+                    .any(|expected| tag == BigUint::from(*expected)) =>
+            {
+                String::from_utf8(value).ok()
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_names() {
+        let der = pem::parse(include_str!("../../../certificates/Client.pem"))
+            .expect("certificate should be PEM encoded")
+            .into_contents();
+        assert_eq!(
+            subject_names(&der),
+            Some(vec![
+                "Client".to_owned(),
+                "Client".to_owned(),
+                "localhost".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_subject_names_invalid() {
+        assert_eq!(subject_names(b"not a certificate"), None);
+    }
+}
@@ -13,6 +13,8 @@
 
 #[derive(Debug)]
 pub struct Scope {
+    // Deny instead of grant the action, e.g. "!read:Vehicle.Cabin"
+    pub deny: bool,
     pub action: Action,
     pub path: Option<String>,
 }
@@ -23,6 +25,8 @@ pub enum Action {
     Actuate,
     Provide,
     Create,
+    Metadata,
+    Subscribe,
 }
 
 #[derive(Debug)]
@@ -34,6 +38,7 @@ pub fn parse_whitespace_separated(scope: &str) -> Result<Vec<Scope>, Error> {
     let regex = regex::Regex::new(
         r"(?x)
         ^
+        (?P<deny>!)? # match optional deny prefix
         (?P<action>([^:]*)) # match action
 
         (?::
@@ -69,6 +74,8 @@ pub fn parse_whitespace_separated(scope: &str) -> Result<Vec<Scope>, Error> {
                         "actuate" => Action::Actuate,
                         "provide" => Action::Provide,
                         "create" => Action::Create,
+                        "metadata" => Action::Metadata,
+                        "subscribe" => Action::Subscribe,
                         _ => {
                             // Unknown action
                             return Err(Error::ParseError);
@@ -81,7 +88,9 @@ pub fn parse_whitespace_separated(scope: &str) -> Result<Vec<Scope>, Error> {
                 };
                 let path = captures.name("path").map(|path| path.as_str().to_owned());
 
-                Scope { action, path }
+                let deny = captures.name("deny").is_some();
+
+                Scope { deny, action, path }
             }
             None => {
                 // Capture groups couldn't be produced
@@ -110,6 +119,42 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_scope_deny_read_vehicle_test() {
+        match parse_whitespace_separated("read !read:Vehicle.Test") {
+            Ok(scopes) => {
+                assert_eq!(scopes.len(), 2);
+                assert!(!scopes[0].deny);
+                assert!(scopes[1].deny);
+                assert!(matches!(scopes[1].action, Action::Read));
+                assert_eq!(scopes[1].path.as_deref(), Some("Vehicle.Test"));
+            }
+            Err(_) => panic!("should not error"),
+        }
+    }
+
+    #[test]
+    fn test_scope_metadata_and_subscribe() {
+        match parse_whitespace_separated("metadata:Vehicle !subscribe:Vehicle.Speed") {
+            Ok(scopes) => {
+                assert_eq!(scopes.len(), 2);
+                assert!(matches!(scopes[0].action, Action::Metadata));
+                assert_eq!(scopes[0].path.as_deref(), Some("Vehicle"));
+                assert!(scopes[1].deny);
+                assert!(matches!(scopes[1].action, Action::Subscribe));
+            }
+            Err(_) => panic!("should not error"),
+        }
+    }
+
+    #[test]
+    fn test_scope_deny_twice() {
+        match parse_whitespace_separated("!!read:Vehicle.Test") {
+            Ok(_) => panic!("repeated deny prefix should result in error"),
+            Err(Error::ParseError) => {}
+        }
+    }
+
     #[test]
     fn test_scope_read_no_path() {
         match parse_whitespace_separated("read:") {
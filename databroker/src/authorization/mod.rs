@@ -11,27 +11,137 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
+use std::convert::TryFrom;
+
 use thiserror::Error;
 
+use crate::permissions::{self, Permissions};
+
+#[cfg(feature = "tls")]
+pub mod certificate;
 pub mod jwt;
+pub mod policy;
 
 #[derive(Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum Authorization {
     Disabled,
-    Enabled { token_decoder: jwt::Decoder },
+    Enabled {
+        // Validates access tokens, if clients may authenticate with them
+        token_decoder: Option<jwt::Decoder>,
+        // Grants permissions to clients authenticated by API key, TLS client
+        // certificate or unix socket peer credentials
+        policy: Option<policy::Policy>,
+    },
 }
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Invalid public key")]
     InvalidPublicKey,
+    #[error("Invalid API key")]
+    InvalidApiKey,
+    #[error("Invalid auth token: {0}")]
+    InvalidToken(jwt::Error),
+    #[error("Invalid client certificate")]
+    InvalidCertificate,
+    #[error("Client certificate not authorized")]
+    UnauthorizedCertificate,
+    #[error("Unix socket peer not authorized")]
+    UnauthorizedPeer,
 }
 
 impl Authorization {
     pub fn new(public_key: String) -> Result<Authorization, Error> {
         Ok(Authorization::Enabled {
-            token_decoder: jwt::Decoder::new(public_key).map_err(|_| Error::InvalidPublicKey)?,
+            token_decoder: Some(
+                jwt::Decoder::new(public_key).map_err(|_| Error::InvalidPublicKey)?,
+            ),
+            policy: None,
         })
     }
+
+    /// Changes whenever the policy or the keys verifying access tokens are
+    /// reloaded, after which permissions resolved before may be stale.
+    pub fn generation(&self) -> u64 {
+        match self {
+            Authorization::Disabled => 0,
+            Authorization::Enabled {
+                token_decoder,
+                policy,
+            } => {
+                token_decoder.as_ref().map_or(0, jwt::Decoder::generation)
+                    + policy.as_ref().map_or(0, policy::Policy::generation)
+            }
+        }
+    }
+
+    /// Validate a token and resolve the permissions it grants. The token is
+    /// either an API key of a client of the policy or an access token.
+    ///
+    /// Everything is permitted if authorization is disabled.
+    pub fn permissions_from_token(&self, token: &str) -> Result<Permissions, Error> {
+        match self {
+            Authorization::Disabled => Ok(permissions::ALLOW_ALL.clone()),
+            Authorization::Enabled {
+                token_decoder,
+                policy,
+            } => {
+                if let Some(permissions) = policy
+                    .as_ref()
+                    .and_then(|policy| policy.permissions_for_api_key(token))
+                {
+                    return Ok(permissions);
+                }
+                let permissions = match token_decoder {
+                    Some(token_decoder) => token_decoder
+                        .decode(token)
+                        .and_then(Permissions::try_from)
+                        .map_err(Error::InvalidToken)?,
+                    None => return Err(Error::InvalidApiKey),
+                };
+                Ok(match policy {
+                    Some(policy) => policy.transform(permissions),
+                    None => permissions,
+                })
+            }
+        }
+    }
+
+    /// Resolve the permissions the policy grants the client presenting the
+    /// (DER encoded) TLS client certificate.
+    ///
+    /// Everything is permitted if authorization is disabled.
+    #[cfg(feature = "tls")]
+    pub fn permissions_from_certificate(&self, certificate: &[u8]) -> Result<Permissions, Error> {
+        match self {
+            Authorization::Disabled => Ok(permissions::ALLOW_ALL.clone()),
+            Authorization::Enabled { policy, .. } => {
+                let names =
+                    certificate::subject_names(certificate).ok_or(Error::InvalidCertificate)?;
+                policy
+                    .as_ref()
+                    .and_then(|policy| policy.permissions_for_certificate(&names))
+                    .ok_or(Error::UnauthorizedCertificate)
+            }
+        }
+    }
+
+    /// Resolve the permissions the policy grants the process with the given
+    /// user and group id connected via unix socket.
+    ///
+    /// Everything is permitted if authorization is disabled.
+    pub fn permissions_from_peer_credentials(
+        &self,
+        uid: u32,
+        gid: u32,
+    ) -> Result<Permissions, Error> {
+        match self {
+            Authorization::Disabled => Ok(permissions::ALLOW_ALL.clone()),
+            Authorization::Enabled { policy, .. } => policy
+                .as_ref()
+                .and_then(|policy| policy.permissions_for_peer_credentials(uid, gid))
+                .ok_or(Error::UnauthorizedPeer),
+        }
+    }
 }
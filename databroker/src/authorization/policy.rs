@@ -0,0 +1,606 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    glob,
+    permissions::{Permission, PermissionBuilder, Permissions},
+    transformation::{Transformation, Transformations},
+};
+
+/// Local policy granting permissions to named clients, identified by an API
+/// key, by their TLS client certificate or by the credentials of their process
+/// when connected via unix socket.
+///
+/// A policy file is a JSON document (TOML is not supported) of the form:
+///
+/// ```json
+/// {
+///   "clients": [
+///     {
+///       "name": "dashboard",
+///       "api_keys": ["c2VjcmV0IGtleQ"],
+///       "certificate_names": ["dashboard.example.com"],
+///       "uids": [1001],
+///       "gids": [],
+///       "read": ["Vehicle.**"],
+///       "actuate": ["Vehicle.Cabin.Light.*"],
+///       "provide": [],
+///       "create": [],
+///       "metadata": ["Vehicle"],
+///       "subscribe": ["!Vehicle.Speed"]
+///     }
+///   ],
+///   "transformations": [
+///     {
+///       "subjects": ["dashboard"],
+///       "paths": ["Vehicle.CurrentLocation.*"],
+///       "transformation": { "round": 2 }
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Paths are glob patterns, with `*` granting access to everything. Patterns
+/// prefixed with `!` deny access, overriding the granted permissions. The name
+/// of a client becomes the subject of the permissions granted to it.
+///
+/// Transformations apply to the values read by the clients, or the holders of
+/// access tokens, with one of the listed subjects.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    clients: Arc<RwLock<Vec<Client>>>,
+    transformations: Arc<RwLock<Vec<TransformationEntry>>>,
+    // Number of reloads, to tell when permissions resolved before are stale
+    generation: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct Client {
+    api_keys: Vec<String>,
+    certificate_names: Vec<String>,
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+    permissions: Permissions,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to read policy file: {0}")]
+    ReadError(#[from] std::io::Error),
+    #[error("Failed to parse policy: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("Invalid path pattern '{pattern}' for client '{client}'")]
+    InvalidPattern { client: String, pattern: String },
+    #[error("Invalid permissions for client '{0}'")]
+    InvalidPermissions(String),
+    #[error("Invalid transformation of '{0}'")]
+    InvalidTransformation(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    clients: Vec<ClientEntry>,
+    #[serde(default)]
+    transformations: Vec<TransformationEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TransformationEntry {
+    subjects: Vec<String>,
+    paths: Vec<String>,
+    transformation: Transformation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientEntry {
+    name: String,
+    #[serde(default)]
+    api_keys: Vec<String>,
+    #[serde(default)]
+    certificate_names: Vec<String>,
+    #[serde(default)]
+    uids: Vec<u32>,
+    #[serde(default)]
+    gids: Vec<u32>,
+    #[serde(default)]
+    read: Vec<String>,
+    #[serde(default)]
+    actuate: Vec<String>,
+    #[serde(default)]
+    provide: Vec<String>,
+    #[serde(default)]
+    create: Vec<String>,
+    #[serde(default)]
+    metadata: Vec<String>,
+    #[serde(default)]
+    subscribe: Vec<String>,
+}
+
+impl Policy {
+    pub fn from_json(json: &str) -> Result<Policy, Error> {
+        let (clients, transformations) = parse(json)?;
+        Ok(Policy {
+            clients: Arc::new(RwLock::new(clients)),
+            transformations: Arc::new(RwLock::new(transformations)),
+            generation: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Policy, Error> {
+        Policy::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Replace the clients and transformations with the ones of another
+    /// policy.
+    ///
+    /// The current ones are kept if the policy is invalid.
+    pub fn reload(&self, json: &str) -> Result<(), Error> {
+        let (clients, transformations) = parse(json)?;
+        match self.clients.write() {
+            Ok(mut current_clients) => *current_clients = clients,
+            Err(poisoned) => *poisoned.into_inner() = clients,
+        }
+        match self.transformations.write() {
+            Ok(mut current) => *current = transformations,
+            Err(poisoned) => *poisoned.into_inner() = transformations,
+        }
+        self.generation.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Changes whenever the policy is reloaded.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Reload the policy from a file whenever its content changes, checking
+    /// it every `interval`.
+    pub fn watch_file(
+        &self,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let policy = self.clone();
+        let path = path.into();
+        let mut current = std::fs::read_to_string(&path).ok();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let json = match std::fs::read_to_string(&path) {
+                    Ok(json) => json,
+                    Err(err) => {
+                        warn!("Failed to read policy file: {err}");
+                        continue;
+                    }
+                };
+                if current.as_ref() == Some(&json) {
+                    continue;
+                }
+                match policy.reload(&json) {
+                    Ok(()) => info!("Reloaded policy file {:?}", path),
+                    Err(err) => warn!("Keeping current policy, invalid policy file: {err}"),
+                }
+                current = Some(json);
+            }
+        })
+    }
+
+    /// Permissions of the client with the given API key.
+    pub fn permissions_for_api_key(&self, api_key: &str) -> Option<Permissions> {
+        // Compared in constant time, not to reveal how much of a key matches
+        self.find_client(|client| {
+            client.api_keys.iter().any(|key| {
+                ring::constant_time::verify_slices_are_equal(key.as_bytes(), api_key.as_bytes())
+                    .is_ok()
+            })
+        })
+    }
+
+    /// Permissions of the first client identified by any of the subject
+    /// names of its TLS client certificate.
+    pub fn permissions_for_certificate(&self, certificate_names: &[String]) -> Option<Permissions> {
+        self.find_client(|client| {
+            client
+                .certificate_names
+                .iter()
+                .any(|name| certificate_names.contains(name))
+        })
+    }
+
+    /// Permissions of the first client with the user id of a unix socket
+    /// peer or, if there is none, the first client with its group id.
+    pub fn permissions_for_peer_credentials(&self, uid: u32, gid: u32) -> Option<Permissions> {
+        self.find_client(|client| client.uids.contains(&uid))
+            .or_else(|| self.find_client(|client| client.gids.contains(&gid)))
+    }
+
+    fn find_client(&self, predicate: impl Fn(&Client) -> bool) -> Option<Permissions> {
+        let clients = match self.clients.read() {
+            Ok(clients) => clients,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        clients
+            .iter()
+            .find(|client| predicate(client))
+            .map(|client| self.transform(client.permissions.clone()))
+    }
+
+    /// Attach the transformations of the subject of the permissions to them.
+    pub fn transform(&self, permissions: Permissions) -> Permissions {
+        let Some(subject) = permissions.subject() else {
+            return permissions;
+        };
+        let entries = match self.transformations.read() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let rules: Vec<(glob::Matcher, Transformation)> = entries
+            .iter()
+            .filter(|entry| entry.subjects.iter().any(|name| name == subject))
+            .flat_map(|entry| {
+                entry.paths.iter().filter_map(|path| {
+                    // Validated when parsed
+                    glob::Matcher::new(path)
+                        .ok()
+                        .map(|matcher| (matcher, entry.transformation.clone()))
+                })
+            })
+            .collect();
+        if rules.is_empty() {
+            permissions
+        } else {
+            permissions.with_transformations(Transformations::new(rules))
+        }
+    }
+
+    /// The delays of the values of all subjects.
+    ///
+    /// The broker keeps the values delayed by these, a reload that adds or
+    /// lengthens a delay only takes effect on restart.
+    pub fn delays(&self) -> Transformations {
+        let entries = match self.transformations.read() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let rules: Vec<(glob::Matcher, Transformation)> = entries
+            .iter()
+            .filter(|entry| matches!(entry.transformation, Transformation::Delay(_)))
+            .flat_map(|entry| {
+                entry.paths.iter().filter_map(|path| {
+                    // Validated when parsed
+                    glob::Matcher::new(path)
+                        .ok()
+                        .map(|matcher| (matcher, entry.transformation.clone()))
+                })
+            })
+            .collect();
+        Transformations::new(rules)
+    }
+}
+
+fn parse(json: &str) -> Result<(Vec<Client>, Vec<TransformationEntry>), Error> {
+    let policy_file: PolicyFile = serde_json::from_str(json)?;
+    let clients = policy_file
+        .clients
+        .into_iter()
+        .map(Client::try_from)
+        .collect::<Result<_, _>>()?;
+    for entry in &policy_file.transformations {
+        for path in &entry.paths {
+            let valid = glob::Matcher::new(path).is_ok()
+                && match entry.transformation {
+                    Transformation::Quantise(step) => step > 0.0,
+                    _ => true,
+                };
+            if !valid {
+                return Err(Error::InvalidTransformation(path.clone()));
+            }
+        }
+    }
+    Ok((clients, policy_file.transformations))
+}
+
+impl TryFrom<ClientEntry> for Client {
+    type Error = Error;
+
+    fn try_from(entry: ClientEntry) -> Result<Self, Self::Error> {
+        let mut permissions = PermissionBuilder::new().subject(entry.name.clone());
+        for pattern in entry.read {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_read_permission(permission),
+                (true, permission) => permissions.add_read_denial(permission),
+            };
+        }
+        for pattern in entry.actuate {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_actuate_permission(permission),
+                (true, permission) => permissions.add_actuate_denial(permission),
+            };
+        }
+        for pattern in entry.provide {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_provide_permission(permission),
+                (true, permission) => permissions.add_provide_denial(permission),
+            };
+        }
+        for pattern in entry.create {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_create_permission(permission),
+                (true, permission) => permissions.add_create_denial(permission),
+            };
+        }
+        for pattern in entry.metadata {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_metadata_permission(permission),
+                (true, permission) => permissions.add_metadata_denial(permission),
+            };
+        }
+        for pattern in entry.subscribe {
+            permissions = match permission(&entry.name, pattern)? {
+                (false, permission) => permissions.add_subscribe_permission(permission),
+                (true, permission) => permissions.add_subscribe_denial(permission),
+            };
+        }
+        Ok(Client {
+            api_keys: entry.api_keys,
+            certificate_names: entry.certificate_names,
+            uids: entry.uids,
+            gids: entry.gids,
+            permissions: permissions
+                .build()
+                .map_err(|_| Error::InvalidPermissions(entry.name))?,
+        })
+    }
+}
+
+// Parse a path pattern into whether it denies access and the permission
+fn permission(client: &str, pattern: String) -> Result<(bool, Permission), Error> {
+    let (deny, glob) = match pattern.strip_prefix('!') {
+        Some(glob) => (true, glob),
+        None => (false, pattern.as_str()),
+    };
+    if glob == "*" {
+        Ok((deny, Permission::All))
+    } else if glob::is_valid_pattern(glob) {
+        Ok((deny, Permission::Glob(glob.to_owned())))
+    } else {
+        Err(Error::InvalidPattern {
+            client: client.to_owned(),
+            pattern,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DataValue;
+
+    static POLICY: &str = r#"{
+        "clients": [
+            {
+                "name": "dashboard",
+                "api_keys": ["dashboard-key"],
+                "certificate_names": ["dashboard.example.com", "Dashboard"],
+                "gids": [100],
+                "read": ["Vehicle.Speed", "Vehicle.Cabin.**", "!Vehicle.Cabin.Infotainment"],
+                "actuate": ["Vehicle.Cabin.Light.*"]
+            },
+            {
+                "name": "admin",
+                "certificate_names": ["admin.example.com"],
+                "uids": [0],
+                "read": ["*"],
+                "actuate": ["*"],
+                "provide": ["*"],
+                "create": ["*"]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_permissions_for_certificate() {
+        let policy = Policy::from_json(POLICY).expect("policy should be valid");
+
+        let permissions = policy
+            .permissions_for_certificate(&["Dashboard".to_owned()])
+            .expect("client should be known");
+        assert_eq!(permissions.subject(), Some("dashboard"));
+        assert_eq!(permissions.expires_at(), None);
+        assert!(permissions.can_read("Vehicle.Speed").is_ok());
+        assert!(permissions
+            .can_read("Vehicle.Cabin.Door.Row1.IsOpen")
+            .is_ok());
+        assert!(permissions.can_read("Vehicle.Width").is_err());
+        assert!(permissions
+            .can_read("Vehicle.Cabin.Infotainment.Media.Volume")
+            .is_err());
+        assert!(permissions
+            .can_write_actuator_target("Vehicle.Cabin.Light.IsDomeOn")
+            .is_ok());
+        assert!(permissions.can_write_datapoint("Vehicle.Speed").is_err());
+        assert!(permissions.can_create("Vehicle.Speed").is_err());
+
+        let permissions = policy
+            .permissions_for_certificate(&["other".to_owned(), "admin.example.com".to_owned()])
+            .expect("client should be known");
+        assert_eq!(permissions.subject(), Some("admin"));
+        assert!(permissions.can_read("Vehicle.Width").is_ok());
+        assert!(permissions.can_write_datapoint("Vehicle.Speed").is_ok());
+        assert!(permissions.can_create("Vehicle.Speed").is_ok());
+
+        assert!(policy
+            .permissions_for_certificate(&["unknown.example.com".to_owned()])
+            .is_none());
+    }
+
+    #[test]
+    fn test_permissions_for_peer_credentials() {
+        let policy = Policy::from_json(POLICY).expect("policy should be valid");
+
+        let permissions = policy
+            .permissions_for_peer_credentials(1000, 100)
+            .expect("group should be known");
+        assert_eq!(permissions.subject(), Some("dashboard"));
+
+        // The user id takes precedence over the group id
+        let permissions = policy
+            .permissions_for_peer_credentials(0, 100)
+            .expect("user should be known");
+        assert_eq!(permissions.subject(), Some("admin"));
+
+        assert!(policy
+            .permissions_for_peer_credentials(1000, 1000)
+            .is_none());
+    }
+
+    #[test]
+    fn test_permissions_for_api_key() {
+        let policy = Policy::from_json(POLICY).expect("policy should be valid");
+
+        let permissions = policy
+            .permissions_for_api_key("dashboard-key")
+            .expect("API key should be known");
+        assert_eq!(permissions.subject(), Some("dashboard"));
+        assert!(policy.permissions_for_api_key("other-key").is_none());
+    }
+
+    #[test]
+    fn test_reload() {
+        let policy = Policy::from_json(POLICY).expect("policy should be valid");
+
+        policy
+            .reload(r#"{"clients": [{"name": "other", "api_keys": ["other-key"]}]}"#)
+            .expect("policy should be valid");
+        assert!(policy.permissions_for_api_key("dashboard-key").is_none());
+        assert!(policy.permissions_for_api_key("other-key").is_some());
+
+        // An invalid policy does not replace the current one
+        assert!(policy.reload(r#"{"clients": [{"api_keys": []}]}"#).is_err());
+        assert!(policy.permissions_for_api_key("other-key").is_some());
+    }
+
+    #[test]
+    fn test_transformations() {
+        let policy = Policy::from_json(
+            r#"{
+                "clients": [{"name": "fleet", "api_keys": ["fleet-key"], "read": ["*"]}],
+                "transformations": [
+                    {
+                        "subjects": ["fleet", "navigation"],
+                        "paths": ["Vehicle.CurrentLocation.*"],
+                        "transformation": {"round": 1}
+                    },
+                    {
+                        "subjects": ["fleet"],
+                        "paths": ["Vehicle.Cabin.**"],
+                        "transformation": "redact"
+                    }
+                ]
+            }"#,
+        )
+        .expect("policy should be valid");
+
+        let permissions = policy
+            .permissions_for_api_key("fleet-key")
+            .expect("API key should be known");
+        let transformations = permissions.transformations();
+        assert_eq!(
+            transformations.apply(
+                "Vehicle.CurrentLocation.Latitude",
+                DataValue::Double(48.137)
+            ),
+            DataValue::Double(48.1)
+        );
+        assert_eq!(
+            transformations.apply("Vehicle.Cabin.Door.Row1.IsOpen", DataValue::Bool(true)),
+            DataValue::NotAvailable
+        );
+        assert!(!transformations.applies_to("Vehicle.Speed"));
+
+        // Holders of access tokens are matched by their subject as well
+        let permissions = policy.transform(
+            PermissionBuilder::new()
+                .subject("navigation")
+                .build()
+                .expect("permissions should build"),
+        );
+        assert!(permissions
+            .transformations()
+            .applies_to("Vehicle.CurrentLocation.Longitude"));
+        assert!(!permissions
+            .transformations()
+            .applies_to("Vehicle.Cabin.Door.Row1.IsOpen"));
+
+        assert!(Policy::from_json(
+            r#"{"transformations": [{"subjects": ["fleet"], "paths": ["Vehicle.Speed"], "transformation": {"quantise": 0}}]}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_file() {
+        let path = std::env::temp_dir().join(format!("policy-{}.json", std::process::id()));
+        std::fs::write(&path, POLICY).unwrap();
+        let policy = Policy::from_file(&path).expect("policy should be valid");
+        let watcher = policy.watch_file(&path, Duration::from_millis(10));
+
+        std::fs::write(
+            &path,
+            r#"{"clients": [{"name": "other", "api_keys": ["other-key"]}]}"#,
+        )
+        .unwrap();
+        let mut reloaded = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if policy.permissions_for_api_key("other-key").is_some() {
+                reloaded = true;
+                break;
+            }
+        }
+        watcher.abort();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(reloaded);
+        assert!(policy.permissions_for_api_key("dashboard-key").is_none());
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        assert!(matches!(
+            Policy::from_json(r#"{"clients": [{"name": "a", "read": ["Vehicle..Speed"]}]}"#),
+            Err(Error::InvalidPattern { .. })
+        ));
+        assert!(matches!(
+            Policy::from_json(r#"{"clients": [{"name": "a", "write": ["Vehicle"]}]}"#),
+            Err(Error::ParseError(_))
+        ));
+    }
+}
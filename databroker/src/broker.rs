@@ -11,7 +11,9 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
+use crate::audit::{self, AuditLog};
 use crate::permissions::{PermissionError, Permissions};
+use crate::transformation::Transformations;
 pub use crate::types;
 
 use crate::query;
@@ -22,7 +24,8 @@ use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::{Stream, StreamExt};
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::Arc;
@@ -35,8 +38,11 @@ use tracing::{debug, info, warn};
 use crate::glob;
 
 const MAX_SUBSCRIBE_BUFFER_SIZE: usize = 1000;
+const DEFAULT_HISTORY_SIZE: usize = 100;
 
-#[derive(Debug)]
+pub type SubscriptionId = u64;
+
+#[derive(Debug, Clone)]
 pub enum ActuationError {
     NotFound,
     WrongType,
@@ -118,6 +124,24 @@ pub struct Database {
     next_id: AtomicI32,
     path_to_id: HashMap<String, i32>,
     entries: HashMap<i32, Entry>,
+    history: History,
+    // The descriptions of the branches of the tree of entries, by path
+    branches: HashMap<String, String>,
+}
+
+// The past values of the signals whose history is recorded, bounded to a
+// number of values per signal
+struct History {
+    // Signals matching these are recorded from their registration on
+    patterns: Vec<glob::Matcher>,
+    size: usize,
+    // Signals delayed by these are recorded as well, keeping the values
+    // needed to serve delayed reads beyond the size
+    delays: Transformations,
+    retention: HashMap<i32, std::time::Duration>,
+    // The number of subscribers other signals are recorded for
+    subscribers: HashMap<i32, usize>,
+    datapoints: HashMap<i32, VecDeque<Datapoint>>,
 }
 
 #[derive(Default)]
@@ -125,6 +149,9 @@ pub struct Subscriptions {
     actuation_subscriptions: Vec<ActuationSubscription>,
     query_subscriptions: Vec<QuerySubscription>,
     change_subscriptions: Vec<ChangeSubscription>,
+    actuation_event_subscriptions: Vec<ActuationEventSubscription>,
+    provider_status_subscriptions: Vec<ProviderStatusSubscription>,
+    next_subscription_id: SubscriptionId,
 }
 
 #[derive(Debug, Clone)]
@@ -153,12 +180,14 @@ pub struct EntryUpdates {
 #[derive(Debug)]
 pub enum QueryError {
     CompilationError(String),
+    PermissionDenied(String),
     InternalError,
 }
 
 #[derive(Debug)]
 pub enum SubscriptionError {
     NotFound,
+    PermissionDenied,
     InvalidInput,
     InvalidBufferSize,
     InternalError,
@@ -171,6 +200,7 @@ pub struct DataBroker {
     version: String,
     commit_sha: String,
     shutdown_trigger: broadcast::Sender<()>,
+    audit_log: Option<Arc<AuditLog>>,
 }
 
 #[async_trait::async_trait]
@@ -188,12 +218,47 @@ pub struct ActuationChange {
     pub data_value: DataValue,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderStatus {
+    Available,
+    Unavailable,
+}
+
 pub struct ActuationSubscription {
+    // Assigned when added to Subscriptions
+    id: SubscriptionId,
     vss_ids: Vec<i32>,
     actuation_provider: Box<dyn ActuationProvider + Send + Sync + 'static>,
     permissions: Permissions,
 }
 
+#[derive(Debug, Clone)]
+pub struct ActuationEvent {
+    pub id: i32,
+    pub path: String,
+    pub data_value: DataValue,
+    // Subject of the permissions used to request the actuation, if known
+    pub requester: Option<String>,
+    pub ts: SystemTime,
+    // Result of forwarding the actuation request to the provider
+    pub result: Result<(), (ActuationError, String)>,
+}
+
+pub struct ActuationEventSubscription {
+    vss_ids: HashSet<i32>,
+    sender: broadcast::Sender<Vec<ActuationEvent>>,
+    permissions: Permissions,
+    // Events of delayed values, started with the first of them
+    delayed:
+        std::sync::OnceLock<mpsc::UnboundedSender<(tokio::time::Instant, Vec<ActuationEvent>)>>,
+}
+
+pub struct ProviderStatusSubscription {
+    vss_ids: HashSet<i32>,
+    sender: broadcast::Sender<HashMap<i32, ProviderStatus>>,
+    permissions: Permissions,
+}
+
 pub struct QuerySubscription {
     query: query::CompiledQuery,
     sender: mpsc::Sender<QueryResponse>,
@@ -201,14 +266,32 @@ pub struct QuerySubscription {
 }
 
 pub struct ChangeSubscription {
+    // Assigned when added to Subscriptions
+    id: SubscriptionId,
     entries: HashMap<i32, HashSet<Field>>,
     sender: broadcast::Sender<EntryUpdates>,
     permissions: Permissions,
+    // Notifications of delayed values, started with the first of them
+    delayed: std::sync::OnceLock<mpsc::UnboundedSender<(tokio::time::Instant, EntryUpdates)>>,
 }
 
 #[derive(Debug)]
 pub struct NotificationError {}
 
+// An entry to register as part of a batch, see AuthorizedAccess::add_entries
+#[derive(Debug, Clone)]
+pub struct EntryRegistration {
+    pub path: String,
+    pub data_type: DataType,
+    pub change_type: ChangeType,
+    pub entry_type: EntryType,
+    pub description: String,
+    pub min: Option<types::DataValue>,
+    pub max: Option<types::DataValue>,
+    pub allowed: Option<types::DataValue>,
+    pub unit: Option<String>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct EntryUpdate {
     pub path: Option<String>,
@@ -233,7 +316,70 @@ pub struct EntryUpdate {
     pub unit: Option<String>,
 }
 
+impl Datapoint {
+    // The datapoint as read by a client whose values are transformed. Delayed
+    // values are replaced by the latest past value that is old enough, if any.
+    fn transformed(
+        &self,
+        path: &str,
+        transformations: &Transformations,
+        past: Option<&VecDeque<Datapoint>>,
+    ) -> Datapoint {
+        let now = SystemTime::now();
+        let datapoint = match transformations.delay(path) {
+            Some(delay) if self.ts + delay > now => {
+                match past
+                    .into_iter()
+                    .flatten()
+                    .rev()
+                    .find(|datapoint| datapoint.ts + delay <= now)
+                {
+                    Some(datapoint) => datapoint,
+                    None => {
+                        return Datapoint {
+                            ts: self.ts,
+                            source_ts: self.source_ts,
+                            value: DataValue::NotAvailable,
+                        }
+                    }
+                }
+            }
+            _ => self,
+        };
+        Datapoint {
+            ts: datapoint.ts,
+            source_ts: datapoint.source_ts,
+            value: transformations.apply(path, datapoint.value.clone()),
+        }
+    }
+}
+
 impl Entry {
+    // The entry as read by a client whose values are transformed
+    fn transformed<'a>(
+        &'a self,
+        transformations: &Transformations,
+        history: &History,
+    ) -> Cow<'a, Entry> {
+        let path = &self.metadata.path;
+        if !transformations.applies_to(path) {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(Entry {
+            datapoint: self.datapoint.transformed(
+                path,
+                transformations,
+                history.datapoints.get(&self.metadata.id),
+            ),
+            lag_datapoint: self.lag_datapoint.transformed(path, transformations, None),
+            actuator_target: self
+                .actuator_target
+                .as_ref()
+                .map(|datapoint| datapoint.transformed(path, transformations, None)),
+            metadata: self.metadata.clone(),
+        })
+    }
+
     #[cfg_attr(feature="otel",tracing::instrument(name="entry_diff", skip(self, update), fields(timestamp=chrono::Utc::now().to_string())))]
     pub fn diff(&self, mut update: EntryUpdate) -> EntryUpdate {
         if let Some(datapoint) = &update.datapoint {
@@ -748,17 +894,58 @@ pub enum SuccessfulUpdate {
 }
 
 impl Subscriptions {
-    pub fn add_actuation_subscription(&mut self, subscription: ActuationSubscription) {
+    pub fn add_actuation_subscription(
+        &mut self,
+        mut subscription: ActuationSubscription,
+    ) -> SubscriptionId {
+        self.next_subscription_id += 1;
+        subscription.id = self.next_subscription_id;
+        self.notify_provider_status(&subscription.vss_ids, ProviderStatus::Available);
         self.actuation_subscriptions.push(subscription);
+        self.next_subscription_id
     }
 
     pub fn add_query_subscription(&mut self, subscription: QuerySubscription) {
         self.query_subscriptions.push(subscription)
     }
 
+    pub fn add_actuation_event_subscription(&mut self, subscription: ActuationEventSubscription) {
+        self.actuation_event_subscriptions.push(subscription)
+    }
+
+    pub fn add_provider_status_subscription(&mut self, subscription: ProviderStatusSubscription) {
+        self.provider_status_subscriptions.push(subscription)
+    }
+
+    pub fn provider_status(&self, vss_id: i32) -> ProviderStatus {
+        let provided = self.actuation_subscriptions.iter().any(|sub| {
+            sub.vss_ids.contains(&vss_id)
+                && sub.actuation_provider.is_available()
+                && !sub.permissions.is_expired()
+        });
+        if provided {
+            ProviderStatus::Available
+        } else {
+            ProviderStatus::Unavailable
+        }
+    }
+
+    fn notify_provider_status(&self, vss_ids: &[i32], status: ProviderStatus) {
+        for sub in &self.provider_status_subscriptions {
+            // Closed subscriptions are removed by cleanup
+            let _ = sub.notify(vss_ids, status);
+        }
+    }
+
     #[cfg_attr(feature="otel", tracing::instrument(name="subscriptions_add_change_subscription",skip(self, subscription), fields(timestamp=chrono::Utc::now().to_string())))]
-    pub fn add_change_subscription(&mut self, subscription: ChangeSubscription) {
-        self.change_subscriptions.push(subscription)
+    pub fn add_change_subscription(
+        &mut self,
+        mut subscription: ChangeSubscription,
+    ) -> SubscriptionId {
+        self.next_subscription_id += 1;
+        subscription.id = self.next_subscription_id;
+        self.change_subscriptions.push(subscription);
+        self.next_subscription_id
     }
 
     #[cfg_attr(
@@ -805,10 +992,28 @@ impl Subscriptions {
         }
     }
 
+    pub fn notify_actuation_events(
+        &self,
+        events: &[ActuationEvent],
+    ) -> Result<(), NotificationError> {
+        let mut error = None;
+        for sub in &self.actuation_event_subscriptions {
+            if let Err(err) = sub.notify(events) {
+                error = Some(err);
+            }
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
     pub fn clear(&mut self) {
         self.actuation_subscriptions.clear();
         self.query_subscriptions.clear();
         self.change_subscriptions.clear();
+        self.actuation_event_subscriptions.clear();
+        self.provider_status_subscriptions.clear();
     }
 
     #[cfg_attr(feature="otel", tracing::instrument(name="subscriptions_cleanup", skip(self), fields(timestamp=chrono::Utc::now().to_string())))]
@@ -833,17 +1038,47 @@ impl Subscriptions {
             }
         });
 
+        self.actuation_event_subscriptions.retain(|sub| {
+            if sub.sender.receiver_count() == 0 {
+                info!("Subscriber gone: removing actuation event subscription");
+                false
+            } else if sub.permissions.is_expired() {
+                info!("Permissions of Subscriber expired: removing actuation event subscription");
+                false
+            } else {
+                true
+            }
+        });
+
+        self.provider_status_subscriptions.retain(|sub| {
+            if sub.sender.receiver_count() == 0 {
+                info!("Subscriber gone: removing provider status subscription");
+                false
+            } else if sub.permissions.is_expired() {
+                info!("Permissions of Subscriber expired: removing provider status subscription");
+                false
+            } else {
+                true
+            }
+        });
+
+        let mut unprovided_vss_ids = Vec::new();
         self.actuation_subscriptions.retain(|sub| {
             if !sub.actuation_provider.is_available() {
                 info!("Provider gone: removing provided actuation");
+                unprovided_vss_ids.extend(&sub.vss_ids);
                 false
             } else if sub.permissions.is_expired() {
                 info!("Permissions of Provider expired: removing provided actuation");
+                unprovided_vss_ids.extend(&sub.vss_ids);
                 false
             } else {
                 true
             }
         });
+        if !unprovided_vss_ids.is_empty() {
+            self.notify_provider_status(&unprovided_vss_ids, ProviderStatus::Unavailable);
+        }
     }
 }
 
@@ -876,7 +1111,7 @@ impl ChangeSubscription {
                         for (id, changed_fields) in changed {
                             if let Some(fields) = self.entries.get(id) {
                                 if !fields.is_disjoint(changed_fields) {
-                                    match db_read.get_entry_by_id(*id) {
+                                    match db_read.get_subscribed_entry_by_id(*id) {
                                         Ok(entry) => {
                                             let mut update = EntryUpdate::default();
                                             let mut notify_fields = HashSet::new();
@@ -885,14 +1120,24 @@ impl ChangeSubscription {
                                             if changed_fields.contains(&Field::Datapoint)
                                                 && fields.contains(&Field::Datapoint)
                                             {
-                                                update.datapoint = Some(entry.datapoint.clone());
+                                                update.datapoint = Some(self.transformed(
+                                                    &entry.metadata.path,
+                                                    &entry.datapoint,
+                                                ));
                                                 notify_fields.insert(Field::Datapoint);
                                             }
                                             if changed_fields.contains(&Field::ActuatorTarget)
                                                 && fields.contains(&Field::ActuatorTarget)
                                             {
                                                 update.actuator_target =
-                                                    Some(entry.actuator_target.clone());
+                                                    Some(entry.actuator_target.as_ref().map(
+                                                        |datapoint| {
+                                                            self.transformed(
+                                                                &entry.metadata.path,
+                                                                datapoint,
+                                                            )
+                                                        },
+                                                    ));
                                                 notify_fields.insert(Field::ActuatorTarget);
                                             }
                                             // fill unit field always
@@ -919,13 +1164,7 @@ impl ChangeSubscription {
                     if notifications.updates.is_empty() {
                         Ok(())
                     } else {
-                        match self.sender.send(notifications) {
-                            Ok(_number_of_receivers) => Ok(()),
-                            Err(err) => {
-                                debug!("Send error for entry{}: ", err);
-                                Err(NotificationError {})
-                            }
-                        }
+                        self.send(notifications)
                     }
                 } else {
                     Ok(())
@@ -936,18 +1175,23 @@ impl ChangeSubscription {
                     let mut notifications = EntryUpdates::default();
 
                     for (id, fields) in &self.entries {
-                        match db_read.get_entry_by_id(*id) {
+                        match db_read.get_subscribed_entry_by_id(*id) {
                             Ok(entry) => {
                                 let mut update = EntryUpdate::default();
                                 let mut notify_fields = HashSet::new();
                                 // TODO: Perhaps make path optional
                                 update.path = Some(entry.metadata.path.clone());
                                 if fields.contains(&Field::Datapoint) {
-                                    update.datapoint = Some(entry.datapoint.clone());
+                                    update.datapoint = Some(
+                                        self.transformed(&entry.metadata.path, &entry.datapoint),
+                                    );
                                     notify_fields.insert(Field::Datapoint);
                                 }
                                 if fields.contains(&Field::ActuatorTarget) {
-                                    update.actuator_target = Some(entry.actuator_target.clone());
+                                    update.actuator_target =
+                                        Some(entry.actuator_target.as_ref().map(|datapoint| {
+                                            self.transformed(&entry.metadata.path, datapoint)
+                                        }));
                                     notify_fields.insert(Field::ActuatorTarget);
                                 }
                                 notifications.updates.push(ChangeNotification {
@@ -963,15 +1207,171 @@ impl ChangeSubscription {
                     }
                     notifications
                 };
-                match self.sender.send(notifications) {
-                    Ok(_number_of_receivers) => Ok(()),
-                    Err(err) => {
-                        debug!("Send error for entry{}: ", err);
-                        Err(NotificationError {})
+                self.send(notifications)
+            }
+        }
+    }
+
+    // A datapoint as notified to the subscriber. Delayed values are not
+    // withheld, but sent late.
+    fn transformed(&self, path: &str, datapoint: &Datapoint) -> Datapoint {
+        Datapoint {
+            ts: datapoint.ts,
+            source_ts: datapoint.source_ts,
+            value: self
+                .permissions
+                .transformations()
+                .apply(path, datapoint.value.clone()),
+        }
+    }
+
+    fn send(&self, notifications: EntryUpdates) -> Result<(), NotificationError> {
+        let transformations = self.permissions.transformations();
+        let notifications = if transformations.is_empty() {
+            notifications
+        } else {
+            // Hold back the notifications of delayed values, queueing them
+            // to be sent once their delay has passed
+            let mut immediate = EntryUpdates::default();
+            let mut delayed = HashMap::<std::time::Duration, EntryUpdates>::new();
+            for notification in notifications.updates {
+                match notification
+                    .update
+                    .path
+                    .as_deref()
+                    .and_then(|path| transformations.delay(path))
+                {
+                    Some(delay) => delayed.entry(delay).or_default().updates.push(notification),
+                    None => immediate.updates.push(notification),
+                }
+            }
+            for (delay, notifications) in delayed {
+                let due = tokio::time::Instant::now() + delay;
+                let queue = self
+                    .delayed
+                    .get_or_init(|| spawn_delay_queue(self.sender.clone()));
+                if queue.send((due, notifications)).is_err() {
+                    debug!("Delayed notifications of subscription {} dropped", self.id);
+                }
+            }
+            if immediate.updates.is_empty() {
+                return if self.sender.receiver_count() > 0 {
+                    Ok(())
+                } else {
+                    Err(NotificationError {})
+                };
+            }
+            immediate
+        };
+        match self.sender.send(notifications) {
+            Ok(_number_of_receivers) => Ok(()),
+            Err(err) => {
+                debug!("Send error for entry{}: ", err);
+                Err(NotificationError {})
+            }
+        }
+    }
+}
+
+// Send queued notifications through `sender` once they are due, in the order
+// they are due. The task ends when the subscription is dropped or has no
+// subscriber left.
+fn spawn_delay_queue<T: Send + 'static>(
+    sender: broadcast::Sender<T>,
+) -> mpsc::UnboundedSender<(tokio::time::Instant, T)> {
+    let (queue_sender, mut queue_receiver) = mpsc::unbounded_channel::<(tokio::time::Instant, T)>();
+    tokio::spawn(async move {
+        let mut queue = VecDeque::new();
+        loop {
+            let next_due = queue
+                .front()
+                .map(|(due, _): &(tokio::time::Instant, T)| *due);
+            tokio::select! {
+                received = queue_receiver.recv() => match received {
+                    Some((due, notifications)) => {
+                        // Appended unless the values have different delays
+                        let index = queue.partition_point(|(queued, _)| *queued <= due);
+                        queue.insert(index, (due, notifications));
+                    }
+                    None => break,
+                },
+                () = tokio::time::sleep_until(next_due.unwrap_or_else(tokio::time::Instant::now)),
+                    if next_due.is_some() =>
+                {
+                    if let Some((_, notifications)) = queue.pop_front() {
+                        if sender.send(notifications).is_err() {
+                            break;
+                        }
                     }
                 }
             }
         }
+    });
+    queue_sender
+}
+
+impl ActuationEventSubscription {
+    fn notify(&self, events: &[ActuationEvent]) -> Result<(), NotificationError> {
+        if self.permissions.is_expired() {
+            debug!("notify: token expired, closing actuation event subscription channel");
+            return Err(NotificationError {});
+        }
+        // Values are transformed as for reads, events of delayed values are
+        // queued to be sent once their delay has passed
+        let transformations = self.permissions.transformations();
+        let mut immediate = Vec::new();
+        let mut delayed = HashMap::<std::time::Duration, Vec<ActuationEvent>>::new();
+        for event in events.iter().filter(|event| {
+            self.vss_ids.contains(&event.id) && self.permissions.can_subscribe(&event.path).is_ok()
+        }) {
+            let event = ActuationEvent {
+                data_value: transformations.apply(&event.path, event.data_value.clone()),
+                ..event.clone()
+            };
+            match transformations.delay(&event.path) {
+                Some(delay) => delayed.entry(delay).or_default().push(event),
+                None => immediate.push(event),
+            }
+        }
+        for (delay, events) in delayed {
+            let due = tokio::time::Instant::now() + delay;
+            let queue = self
+                .delayed
+                .get_or_init(|| spawn_delay_queue(self.sender.clone()));
+            if queue.send((due, events)).is_err() {
+                debug!("Delayed actuation events dropped");
+            }
+        }
+        if immediate.is_empty() {
+            return Ok(());
+        }
+        match self.sender.send(immediate) {
+            Ok(_number_of_receivers) => Ok(()),
+            Err(err) => {
+                debug!("Send error for actuation events: {}", err);
+                Err(NotificationError {})
+            }
+        }
+    }
+}
+
+impl ProviderStatusSubscription {
+    fn notify(&self, vss_ids: &[i32], status: ProviderStatus) -> Result<(), NotificationError> {
+        let statuses: HashMap<i32, ProviderStatus> = vss_ids
+            .iter()
+            .filter(|vss_id| self.vss_ids.contains(vss_id))
+            .map(|vss_id| (*vss_id, status))
+            .collect();
+        if statuses.is_empty() {
+            return Ok(());
+        }
+        match self.sender.send(statuses) {
+            Ok(_number_of_receivers) => Ok(()),
+            Err(err) => {
+                debug!("Send error for provider status: {}", err);
+                Err(NotificationError {})
+            }
+        }
     }
 }
 
@@ -983,8 +1383,9 @@ impl QuerySubscription {
         db: &DatabaseReadAccess,
         input: &mut query::ExecutionInputImpl,
     ) {
-        match db.get_entry_by_path(name) {
+        match db.get_subscribed_entry_by_path(name) {
             Ok(entry) => {
+                let entry = entry.transformed(self.permissions.transformations(), &db.db.history);
                 input.add(
                     name.to_owned(),
                     ExecutionInputImplData {
@@ -1130,6 +1531,8 @@ pub struct DatabaseWriteAccess<'a, 'b> {
 
 pub enum EntryReadAccess<'a> {
     Entry(&'a Entry),
+    // Entry with the values as transformed for the client
+    Transformed(Box<Entry>),
     Err(&'a Metadata, ReadError),
 }
 
@@ -1138,6 +1541,7 @@ impl EntryReadAccess<'_> {
     pub fn datapoint(&self) -> Result<&Datapoint, ReadError> {
         match self {
             Self::Entry(entry) => Ok(&entry.datapoint),
+            Self::Transformed(entry) => Ok(&entry.datapoint),
             Self::Err(_, err) => Err(err.clone()),
         }
     }
@@ -1145,6 +1549,7 @@ impl EntryReadAccess<'_> {
     pub fn actuator_target(&self) -> Result<&Option<Datapoint>, ReadError> {
         match self {
             Self::Entry(entry) => Ok(&entry.actuator_target),
+            Self::Transformed(entry) => Ok(&entry.actuator_target),
             Self::Err(_, err) => Err(err.clone()),
         }
     }
@@ -1153,15 +1558,19 @@ impl EntryReadAccess<'_> {
     pub fn metadata(&self) -> &Metadata {
         match self {
             Self::Entry(entry) => &entry.metadata,
+            Self::Transformed(entry) => &entry.metadata,
             Self::Err(metadata, _) => metadata,
         }
     }
 }
 
 impl<'a> EntryReadAccess<'a> {
-    fn new(entry: &'a Entry, permissions: &Permissions) -> Self {
+    fn new(entry: &'a Entry, permissions: &Permissions, history: &History) -> Self {
         match permissions.can_read(&entry.metadata.path) {
-            Ok(()) => Self::Entry(entry),
+            Ok(()) => match entry.transformed(permissions.transformations(), history) {
+                Cow::Borrowed(entry) => Self::Entry(entry),
+                Cow::Owned(entry) => Self::Transformed(Box::new(entry)),
+            },
             Err(PermissionError::Denied) => Self::Err(&entry.metadata, ReadError::PermissionDenied),
             Err(PermissionError::Expired) => {
                 Self::Err(&entry.metadata, ReadError::PermissionExpired)
@@ -1172,6 +1581,7 @@ impl<'a> EntryReadAccess<'a> {
 
 pub struct EntryReadIterator<'a, 'b> {
     inner: std::collections::hash_map::Values<'a, i32, Entry>,
+    history: &'a History,
     permissions: &'b Permissions,
 }
 
@@ -1182,7 +1592,7 @@ impl<'a> Iterator for EntryReadIterator<'a, '_> {
     fn next(&mut self) -> Option<Self::Item> {
         self.inner
             .next()
-            .map(|entry| EntryReadAccess::new(entry, self.permissions))
+            .map(|entry| EntryReadAccess::new(entry, self.permissions, self.history))
     }
 
     #[inline]
@@ -1211,6 +1621,42 @@ impl DatabaseReadAccess<'_, '_> {
         }
     }
 
+    /// Get an entry to notify a subscriber of, i.e. if it may be subscribed to.
+    pub fn get_subscribed_entry_by_id(&self, id: i32) -> Result<&Entry, ReadError> {
+        match self.db.entries.get(&id) {
+            Some(entry) => match self.permissions.can_subscribe(&entry.metadata.path) {
+                Ok(_) => Ok(entry),
+                Err(PermissionError::Denied) => Err(ReadError::PermissionDenied),
+                Err(PermissionError::Expired) => Err(ReadError::PermissionExpired),
+            },
+            None => Err(ReadError::NotFound),
+        }
+    }
+
+    pub fn get_subscribed_entry_by_path(&self, path: impl AsRef<str>) -> Result<&Entry, ReadError> {
+        match self.db.path_to_id.get(path.as_ref()) {
+            Some(id) => self.get_subscribed_entry_by_id(*id),
+            None => Err(ReadError::NotFound),
+        }
+    }
+
+    /// Check that all the given entries may be subscribed to.
+    fn can_subscribe<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a i32>,
+    ) -> Result<(), SubscriptionError> {
+        for id in ids {
+            match self.get_subscribed_entry_by_id(*id) {
+                Ok(_) => {}
+                Err(ReadError::NotFound) => return Err(SubscriptionError::NotFound),
+                Err(ReadError::PermissionDenied | ReadError::PermissionExpired) => {
+                    return Err(SubscriptionError::PermissionDenied)
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[cfg_attr(feature="otel", tracing::instrument(name="database_read_access_get_metadata_by_id", skip(self, id), fields(timestamp=chrono::Utc::now().to_string())))]
     pub fn get_metadata_by_id(&self, id: i32) -> Option<&Metadata> {
         self.db.entries.get(&id).map(|entry| &entry.metadata)
@@ -1226,6 +1672,7 @@ impl DatabaseReadAccess<'_, '_> {
     pub fn iter_entries(&self) -> EntryReadIterator {
         EntryReadIterator {
             inner: self.db.entries.values(),
+            history: &self.db.history,
             permissions: self.permissions,
         }
     }
@@ -1303,6 +1750,9 @@ impl DatabaseWriteAccess<'_, '_> {
                 match entry.validate(&update) {
                     Ok(_) => {
                         let changed_fields = entry.apply(update);
+                        if changed_fields.contains(&Field::Datapoint) {
+                            self.db.history.record(id, &entry.datapoint);
+                        }
                         Ok(changed_fields)
                     }
                     Err(err) => Err(err),
@@ -1312,6 +1762,26 @@ impl DatabaseWriteAccess<'_, '_> {
         }
     }
 
+    pub fn add_branch(
+        &mut self,
+        path: String,
+        description: String,
+    ) -> Result<(), RegistrationError> {
+        if !glob::is_valid_path(path.as_str()) {
+            return Err(RegistrationError::ValidationError);
+        }
+
+        self.permissions
+            .can_create(&path)
+            .map_err(|err| match err {
+                PermissionError::Denied => RegistrationError::PermissionDenied,
+                PermissionError::Expired => RegistrationError::PermissionExpired,
+            })?;
+
+        self.db.branches.insert(path, description);
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add(
         &mut self,
@@ -1389,12 +1859,112 @@ impl DatabaseWriteAccess<'_, '_> {
 
         new_entry.metadata.id = id;
 
+        let history = &mut self.db.history;
+        let delay = history.delays.delay(&new_entry.metadata.path);
+        if let Some(delay) = delay {
+            history.retention.insert(id, delay);
+        }
+        if delay.is_some()
+            || history
+                .patterns
+                .iter()
+                .any(|pattern| pattern.is_match(&new_entry.metadata.glob_path))
+        {
+            history.datapoints.insert(id, VecDeque::new());
+        }
+
         // Add entry (mapped by id)
         self.db.entries.insert(id, new_entry);
 
         // Return the id
         Ok(id)
     }
+
+    /// Add all entries, or none of them if any of them fails to be added.
+    /// Returns the ids in the order of the entries, or the path of the entry
+    /// that failed.
+    pub fn add_all(
+        &mut self,
+        registrations: Vec<EntryRegistration>,
+    ) -> Result<Vec<i32>, (String, RegistrationError)> {
+        let mut ids = Vec::with_capacity(registrations.len());
+        let mut added = Vec::new();
+        for registration in registrations {
+            let exists = self.db.path_to_id.contains_key(&registration.path);
+            let path = registration.path.clone();
+            match self.add(
+                registration.path,
+                registration.data_type,
+                registration.change_type,
+                registration.entry_type,
+                registration.description,
+                registration.min,
+                registration.max,
+                registration.allowed,
+                None,
+                registration.unit,
+            ) {
+                Ok(id) => {
+                    if !exists {
+                        added.push(id);
+                    }
+                    ids.push(id);
+                }
+                Err(err) => {
+                    // Nothing can have observed the added entries, as the
+                    // database stayed locked
+                    for id in added {
+                        self.remove_added(id);
+                    }
+                    return Err((path, err));
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    fn remove_added(&mut self, id: i32) {
+        if let Some(entry) = self.db.entries.remove(&id) {
+            self.db.path_to_id.remove(&entry.metadata.path);
+        }
+        self.db.history.datapoints.remove(&id);
+        self.db.history.retention.remove(&id);
+        self.db.history.subscribers.remove(&id);
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            size: DEFAULT_HISTORY_SIZE,
+            delays: Transformations::default(),
+            retention: HashMap::new(),
+            subscribers: HashMap::new(),
+            datapoints: HashMap::new(),
+        }
+    }
+}
+
+impl History {
+    fn record(&mut self, id: i32, datapoint: &Datapoint) {
+        if let Some(datapoints) = self.datapoints.get_mut(&id) {
+            datapoints.push_back(datapoint.clone());
+            // Values are dropped once the next one is old enough to be read
+            // in their place
+            let retention = self.retention.get(&id);
+            let now = SystemTime::now();
+            while datapoints.len() > self.size
+                && retention.is_none_or(|retention| {
+                    datapoints
+                        .get(1)
+                        .is_some_and(|next| next.ts + *retention <= now)
+                })
+            {
+                datapoints.pop_front();
+            }
+        }
+    }
 }
 
 impl Database {
@@ -1403,6 +1973,8 @@ impl Database {
             next_id: Default::default(),
             path_to_id: Default::default(),
             entries: Default::default(),
+            history: Default::default(),
+            branches: Default::default(),
         }
     }
 
@@ -1441,9 +2013,96 @@ impl query::CompilationInput for DatabaseReadAccess<'_, '_> {
 pub struct AuthorizedAccess<'a, 'b> {
     broker: &'a DataBroker,
     permissions: &'b Permissions,
+    // The API the access is made through, recorded in the audit log
+    api: Option<audit::Api>,
 }
 
 impl AuthorizedAccess<'_, '_> {
+    pub fn with_api(mut self, api: audit::Api) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    fn audit_record(&self, event: audit::Event) -> audit::Record {
+        audit::Record::new(event, self.api).caller(self.permissions)
+    }
+
+    // Records of the audited events of an update, i.e. an actuation, a
+    // publish to an audited signal or a metadata change
+    fn audit_update_records(
+        &self,
+        audit_log: &AuditLog,
+        path: &str,
+        update: &EntryUpdate,
+    ) -> Vec<audit::Record> {
+        let mut records = Vec::new();
+        if let Some(actuator_target) = &update.actuator_target {
+            let record = self.audit_record(audit::Event::Actuate).path(path);
+            records.push(match actuator_target {
+                Some(datapoint) => record.value(&datapoint.value),
+                None => record,
+            });
+        }
+        if let Some(datapoint) = &update.datapoint {
+            if audit_log.records_publish(path) {
+                records.push(
+                    self.audit_record(audit::Event::Publish)
+                        .path(path)
+                        .value(&datapoint.value),
+                );
+            }
+        }
+        if update.path.is_some()
+            || update.entry_type.is_some()
+            || update.data_type.is_some()
+            || update.description.is_some()
+            || update.allowed.is_some()
+            || update.min.is_some()
+            || update.max.is_some()
+            || update.unit.is_some()
+        {
+            records.push(self.audit_record(audit::Event::Metadata).path(path));
+        }
+        records
+    }
+
+    // Records of actuations with their result, if an audit log is enabled
+    fn audit_actuation_records(
+        &self,
+        actuation_changes: &[ActuationChange],
+        result: &Result<(), (ActuationError, String)>,
+        db_read: &DatabaseReadAccess,
+    ) -> Vec<audit::Record> {
+        if self.broker.audit_log().is_none() {
+            return Vec::new();
+        }
+        actuation_changes
+            .iter()
+            .map(|change| {
+                let record = self
+                    .audit_record(audit::Event::Actuate)
+                    .value(&change.data_value);
+                let record = match db_read.get_metadata_by_id(change.id) {
+                    Some(metadata) => record.path(&metadata.path),
+                    None => record,
+                };
+                match result {
+                    Ok(()) => record,
+                    Err((_, message)) => record.error(message.as_str()),
+                }
+            })
+            .collect()
+    }
+
+    // Queue records for the audit log, once the database is no longer locked
+    fn audit(&self, records: Vec<audit::Record>) {
+        if let Some(audit_log) = self.broker.audit_log() {
+            for record in records {
+                audit_log.record(record);
+            }
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn add_entry(
         &self,
@@ -1457,7 +2116,12 @@ impl AuthorizedAccess<'_, '_> {
         allowed: Option<types::DataValue>,
         unit: Option<String>,
     ) -> Result<i32, RegistrationError> {
-        self.broker
+        let record = self
+            .broker
+            .audit_log()
+            .map(|_| self.audit_record(audit::Event::Metadata).path(&name));
+        let result = self
+            .broker
             .database
             .write()
             .await
@@ -1473,47 +2137,218 @@ impl AuthorizedAccess<'_, '_> {
                 allowed,
                 None,
                 unit,
-            )
+            );
+        if let (Some(audit_log), Some(record)) = (self.broker.audit_log(), record) {
+            audit_log.record(match &result {
+                Ok(_) => record,
+                Err(err) => record.error(format!("{err:?}")),
+            });
+        }
+        result
     }
 
-    pub async fn with_read_lock<T>(&self, f: impl FnOnce(&DatabaseReadAccess) -> T) -> T {
-        f(&self
+    /// Register several entries at once. Either all of them are added, or
+    /// none of them if any of them fails to be added, in which case the path
+    /// of the failing entry is returned.
+    pub async fn add_entries(
+        &self,
+        registrations: Vec<EntryRegistration>,
+    ) -> Result<Vec<i32>, (String, RegistrationError)> {
+        let records: Vec<_> = match self.broker.audit_log() {
+            Some(_) => registrations
+                .iter()
+                .map(|registration| {
+                    self.audit_record(audit::Event::Metadata)
+                        .path(&registration.path)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let result = self
             .broker
             .database
-            .read()
+            .write()
             .await
-            .authorized_read_access(self.permissions))
+            .authorized_write_access(self.permissions)
+            .add_all(registrations);
+        self.audit(
+            records
+                .into_iter()
+                .map(|record| match &result {
+                    Ok(_) => record,
+                    Err((path, err)) => record.error(format!("{err:?} registering {path}")),
+                })
+                .collect(),
+        );
+        result
     }
 
-    #[cfg_attr(feature="otel", tracing::instrument(name="authorized_access_get_id_by_path", skip(self, name) fields(timestamp=chrono::Utc::now().to_string())))]
-    pub async fn get_id_by_path(&self, name: &str) -> Option<i32> {
-        self.broker
+    /// Describe a branch of the tree of entries.
+    pub async fn add_branch(
+        &self,
+        path: String,
+        description: String,
+    ) -> Result<(), RegistrationError> {
+        let record = self
+            .broker
+            .audit_log()
+            .map(|_| self.audit_record(audit::Event::Metadata).path(&path));
+        let result = self
+            .broker
             .database
-            .read()
+            .write()
             .await
-            .authorized_read_access(self.permissions)
-            .get_metadata_by_path(name)
-            .map(|metadata| metadata.id)
+            .authorized_write_access(self.permissions)
+            .add_branch(path, description);
+        if let (Some(audit_log), Some(record)) = (self.broker.audit_log(), record) {
+            audit_log.record(match &result {
+                Ok(_) => record,
+                Err(err) => record.error(format!("{err:?}")),
+            });
+        }
+        result
     }
 
-    pub async fn get_datapoint(&self, id: i32) -> Result<Datapoint, ReadError> {
+    /// The descriptions of the branches at and below a path, by path.
+    pub async fn get_branch_descriptions(&self, path: &str) -> HashMap<String, String> {
+        let below = format!("{path}.");
         self.broker
             .database
             .read()
             .await
-            .authorized_read_access(self.permissions)
-            .get_entry_by_id(id)
-            .map(|entry| entry.datapoint.clone())
+            .branches
+            .iter()
+            .filter(|(branch, _)| *branch == path || branch.starts_with(&below))
+            .map(|(branch, description)| (branch.clone(), description.clone()))
+            .collect()
     }
 
-    pub async fn get_datapoint_by_path(&self, name: &str) -> Result<Datapoint, ReadError> {
+    pub async fn with_read_lock<T>(&self, f: impl FnOnce(&DatabaseReadAccess) -> T) -> T {
+        f(&self
+            .broker
+            .database
+            .read()
+            .await
+            .authorized_read_access(self.permissions))
+    }
+
+    #[cfg_attr(feature="otel", tracing::instrument(name="authorized_access_get_id_by_path", skip(self, name) fields(timestamp=chrono::Utc::now().to_string())))]
+    pub async fn get_id_by_path(&self, name: &str) -> Option<i32> {
         self.broker
             .database
             .read()
             .await
             .authorized_read_access(self.permissions)
+            .get_metadata_by_path(name)
+            .map(|metadata| metadata.id)
+    }
+
+    pub async fn get_datapoint(&self, id: i32) -> Result<Datapoint, ReadError> {
+        let db = self.broker.database.read().await;
+        db.authorized_read_access(self.permissions)
+            .get_entry_by_id(id)
+            .map(|entry| {
+                entry.datapoint.transformed(
+                    &entry.metadata.path,
+                    self.permissions.transformations(),
+                    db.history.datapoints.get(&entry.metadata.id),
+                )
+            })
+    }
+
+    /// Get the values of a signal since a point in time, oldest first.
+    ///
+    /// Unless its history is recorded, only the current value of the signal
+    /// is known.
+    pub async fn get_history_by_path(
+        &self,
+        path: &str,
+        since: SystemTime,
+    ) -> Result<Vec<Datapoint>, ReadError> {
+        let db = self.broker.database.read().await;
+        let read_access = db.authorized_read_access(self.permissions);
+        let entry = read_access.get_entry_by_path(path)?;
+        let path = &entry.metadata.path;
+        let transformations = self.permissions.transformations();
+        // Delayed values are left out until they are old enough
+        let delay = transformations.delay(path);
+        let datapoints = match db.history.datapoints.get(&entry.metadata.id) {
+            Some(datapoints) => datapoints.iter().collect(),
+            None => vec![&entry.datapoint],
+        };
+        Ok(datapoints
+            .into_iter()
+            .filter(|datapoint| {
+                datapoint.ts >= since
+                    && delay.is_none_or(|delay| datapoint.ts + delay <= SystemTime::now())
+            })
+            .map(|datapoint| datapoint.transformed(path, transformations, None))
+            .collect())
+    }
+
+    /// Start recording the history of signals for a subscriber, if not
+    /// recorded already.
+    ///
+    /// Recording continues until every subscriber it was started for has
+    /// called `stop_recording_history`.
+    pub async fn record_history(&self, ids: impl IntoIterator<Item = i32>) {
+        let mut db = self.broker.database.write().await;
+        for id in ids {
+            let Some(entry) = db.entries.get(&id) else {
+                continue;
+            };
+            let datapoint = entry.datapoint.clone();
+            *db.history.subscribers.entry(id).or_default() += 1;
+            db.history.datapoints.entry(id).or_insert_with(|| {
+                let mut datapoints = VecDeque::new();
+                if datapoint.value != DataValue::NotAvailable {
+                    datapoints.push_back(datapoint);
+                }
+                datapoints
+            });
+        }
+    }
+
+    /// Stop recording the history of signals for a subscriber.
+    ///
+    /// The history is dropped once no subscriber is left, unless the signal
+    /// is recorded regardless of subscribers.
+    pub async fn stop_recording_history(&self, ids: impl IntoIterator<Item = i32>) {
+        let mut db = self.broker.database.write().await;
+        let db = &mut *db;
+        for id in ids {
+            let Some(subscribers) = db.history.subscribers.get_mut(&id) else {
+                continue;
+            };
+            *subscribers -= 1;
+            if *subscribers > 0 {
+                continue;
+            }
+            db.history.subscribers.remove(&id);
+            let recorded = db.history.retention.contains_key(&id)
+                || db.entries.get(&id).is_some_and(|entry| {
+                    db.history
+                        .patterns
+                        .iter()
+                        .any(|pattern| pattern.is_match(&entry.metadata.glob_path))
+                });
+            if !recorded {
+                db.history.datapoints.remove(&id);
+            }
+        }
+    }
+
+    pub async fn get_datapoint_by_path(&self, name: &str) -> Result<Datapoint, ReadError> {
+        let db = self.broker.database.read().await;
+        db.authorized_read_access(self.permissions)
             .get_entry_by_path(name)
-            .map(|entry| entry.datapoint.clone())
+            .map(|entry| {
+                entry.datapoint.transformed(
+                    &entry.metadata.path,
+                    self.permissions.transformations(),
+                    db.history.datapoints.get(&entry.metadata.id),
+                )
+            })
     }
 
     #[cfg_attr(feature="otel", tracing::instrument(name="authorized_access_get_metadata", skip(self, id), fields(timestamp=chrono::Utc::now().to_string())))]
@@ -1538,23 +2373,25 @@ impl AuthorizedAccess<'_, '_> {
     }
 
     pub async fn get_entry_by_path(&self, path: &str) -> Result<Entry, ReadError> {
-        self.broker
-            .database
-            .read()
-            .await
-            .authorized_read_access(self.permissions)
+        let db = self.broker.database.read().await;
+        db.authorized_read_access(self.permissions)
             .get_entry_by_path(path)
-            .cloned()
+            .map(|entry| {
+                entry
+                    .transformed(self.permissions.transformations(), &db.history)
+                    .into_owned()
+            })
     }
 
     pub async fn get_entry_by_id(&self, id: i32) -> Result<Entry, ReadError> {
-        self.broker
-            .database
-            .read()
-            .await
-            .authorized_read_access(self.permissions)
+        let db = self.broker.database.read().await;
+        db.authorized_read_access(self.permissions)
             .get_entry_by_id(id)
-            .cloned()
+            .map(|entry| {
+                entry
+                    .transformed(self.permissions.transformations(), &db.history)
+                    .into_owned()
+            })
     }
 
     #[cfg_attr(feature="otel", tracing::instrument(name="authorized_access_for_each_entry", skip(self, f), fields(timestamp=chrono::Utc::now().to_string())))]
@@ -1602,13 +2439,25 @@ impl AuthorizedAccess<'_, '_> {
         let mut db = self.broker.database.write().await;
         let mut db_write = db.authorized_write_access(self.permissions);
         let mut lag_updates: HashMap<String, ()> = HashMap::new();
+        let mut audit_records = Vec::new();
 
         let cleanup_needed = {
             let changed = {
                 let mut changed = HashMap::<i32, HashSet<Field>>::new();
                 for (id, update) in updates {
                     debug!("setting id {} to {:?}", id, update);
-                    match db_write.update(id, update) {
+                    let records = match (self.broker.audit_log(), db_write.db.entries.get(&id)) {
+                        (Some(audit_log), Some(entry)) => {
+                            self.audit_update_records(audit_log, &entry.metadata.path, &update)
+                        }
+                        _ => Vec::new(),
+                    };
+                    let result = db_write.update(id, update);
+                    audit_records.extend(records.into_iter().map(|record| match &result {
+                        Ok(_) => record,
+                        Err(err) => record.error(format!("{err:?}")),
+                    }));
+                    match result {
                         Ok(changed_fields) => {
                             if !changed_fields.is_empty() {
                                 changed.insert(id, changed_fields);
@@ -1644,6 +2493,8 @@ impl AuthorizedAccess<'_, '_> {
             }
         };
 
+        self.audit(audit_records);
+
         if !lag_updates.is_empty() {
             let mut db = self.broker.database.write().await;
             let mut db_write = db.authorized_write_access(self.permissions);
@@ -1675,22 +2526,22 @@ impl AuthorizedAccess<'_, '_> {
             return Err(SubscriptionError::InvalidInput);
         }
 
-        let channel_capacity = if let Some(cap) = buffer_size {
-            if cap > MAX_SUBSCRIBE_BUFFER_SIZE {
-                return Err(SubscriptionError::InvalidBufferSize);
-            }
-            // Requested capacity for old messages plus 1 for latest
-            cap + 1
-        } else {
-            // Just latest message
-            1
-        };
+        let channel_capacity = subscription_channel_capacity(buffer_size)?;
+
+        self.broker
+            .database
+            .read()
+            .await
+            .authorized_read_access(self.permissions)
+            .can_subscribe(valid_entries.keys())?;
 
         let (sender, receiver) = broadcast::channel(channel_capacity);
         let subscription = ChangeSubscription {
+            id: 0,
             entries: valid_entries,
             sender,
             permissions: self.permissions.clone(),
+            delayed: std::sync::OnceLock::new(),
         };
 
         {
@@ -1707,17 +2558,84 @@ impl AuthorizedAccess<'_, '_> {
             .await
             .add_change_subscription(subscription);
 
-        let stream = BroadcastStream::new(receiver).filter_map(move |result| match result {
-            Ok(message) => Some(message),
-            Err(err) => {
-                warn!(
-                    "Slow subscriber with capacity {} lagged and missed signal updates: {}",
-                    channel_capacity, err
-                );
-                None
-            }
-        });
-        Ok(stream)
+        Ok(subscription_stream(receiver, channel_capacity))
+    }
+
+    /// Create a change subscription which initially has no subscribed entries.
+    ///
+    /// Entries are added to and removed from the subscription afterwards using
+    /// `modify_subscription` with the returned id.
+    pub async fn subscribe_modifiable(
+        &self,
+        buffer_size: Option<usize>,
+    ) -> Result<(SubscriptionId, impl Stream<Item = EntryUpdates>), SubscriptionError> {
+        let channel_capacity = subscription_channel_capacity(buffer_size)?;
+
+        let (sender, receiver) = broadcast::channel(channel_capacity);
+        let subscription = ChangeSubscription {
+            id: 0,
+            entries: HashMap::new(),
+            sender,
+            permissions: self.permissions.clone(),
+            delayed: std::sync::OnceLock::new(),
+        };
+
+        let subscription_id = self
+            .broker
+            .subscriptions
+            .write()
+            .await
+            .add_change_subscription(subscription);
+
+        Ok((
+            subscription_id,
+            subscription_stream(receiver, channel_capacity),
+        ))
+    }
+
+    /// Add entries to and remove entries from an existing change subscription.
+    ///
+    /// Only the current values of the added entries are sent to the subscriber,
+    /// entries already subscribed to are not resent.
+    pub async fn modify_subscription(
+        &self,
+        subscription_id: SubscriptionId,
+        added_entries: HashMap<i32, HashSet<Field>>,
+        removed_entries: HashSet<i32>,
+    ) -> Result<(), SubscriptionError> {
+        // Lock the database before the subscriptions, same as when notifying
+        // subscribers in update_entries
+        let db = self.broker.database.read().await;
+        db.authorized_read_access(self.permissions)
+            .can_subscribe(added_entries.keys())?;
+        let mut subscriptions = self.broker.subscriptions.write().await;
+
+        let subscription = subscriptions
+            .change_subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.id == subscription_id)
+            .ok_or(SubscriptionError::NotFound)?;
+
+        for id in &removed_entries {
+            subscription.entries.remove(id);
+        }
+        for (id, fields) in &added_entries {
+            subscription
+                .entries
+                .entry(*id)
+                .or_default()
+                .extend(fields.iter().cloned());
+        }
+
+        if !added_entries.is_empty()
+            && subscription
+                .notify(Some(&added_entries), &db)
+                .await
+                .is_err()
+        {
+            warn!("Failed to create initial notification");
+        }
+        Ok(())
     }
 
     pub async fn subscribe_query(
@@ -1731,6 +2649,13 @@ impl AuthorizedAccess<'_, '_> {
 
         match compiled_query {
             Ok(compiled_query) => {
+                if let Some(path) = query_input_paths(&compiled_query)
+                    .into_iter()
+                    .find(|path| db_read_access.get_subscribed_entry_by_path(path).is_err())
+                {
+                    return Err(QueryError::PermissionDenied(path.to_owned()));
+                }
+
                 let (sender, receiver) = mpsc::channel(10);
 
                 let subscription = QuerySubscription {
@@ -1757,11 +2682,103 @@ impl AuthorizedAccess<'_, '_> {
         }
     }
 
+    /// Subscribe to the actuation requests of the given actuators.
+    ///
+    /// Events are only delivered for actuators readable with the permissions of
+    /// the subscriber, with their values transformed as for reads. Events of
+    /// delayed values are sent late.
+    pub async fn subscribe_actuation_events(
+        &self,
+        vss_ids: HashSet<i32>,
+        buffer_size: Option<usize>,
+    ) -> Result<impl Stream<Item = Vec<ActuationEvent>>, SubscriptionError> {
+        if vss_ids.is_empty() {
+            return Err(SubscriptionError::InvalidInput);
+        }
+
+        let channel_capacity = subscription_channel_capacity(buffer_size)?;
+
+        self.broker
+            .database
+            .read()
+            .await
+            .authorized_read_access(self.permissions)
+            .can_subscribe(&vss_ids)?;
+
+        let (sender, receiver) = broadcast::channel(channel_capacity);
+        let subscription = ActuationEventSubscription {
+            vss_ids,
+            sender,
+            permissions: self.permissions.clone(),
+            delayed: std::sync::OnceLock::new(),
+        };
+
+        self.broker
+            .subscriptions
+            .write()
+            .await
+            .add_actuation_event_subscription(subscription);
+
+        Ok(subscription_stream(receiver, channel_capacity))
+    }
+
+    /// Get the ids of all actuators which currently have an available provider.
+    pub async fn get_provided_actuators(&self) -> HashSet<i32> {
+        self.broker
+            .subscriptions
+            .read()
+            .await
+            .actuation_subscriptions
+            .iter()
+            .filter(|sub| sub.actuation_provider.is_available() && !sub.permissions.is_expired())
+            .flat_map(|sub| sub.vss_ids.clone())
+            .collect()
+    }
+
+    /// Subscribe to the provider status of the given actuators.
+    ///
+    /// The current status of all actuators is sent immediately, changes are sent
+    /// when a provider registers or when it is removed.
+    pub async fn subscribe_provider_status(
+        &self,
+        vss_ids: HashSet<i32>,
+        buffer_size: Option<usize>,
+    ) -> Result<impl Stream<Item = HashMap<i32, ProviderStatus>>, SubscriptionError> {
+        if vss_ids.is_empty() {
+            return Err(SubscriptionError::InvalidInput);
+        }
+
+        let channel_capacity = subscription_channel_capacity(buffer_size)?;
+
+        let (sender, receiver) = broadcast::channel(channel_capacity);
+
+        {
+            let mut subscriptions = self.broker.subscriptions.write().await;
+
+            // Send the current status in an initial notification
+            let statuses = vss_ids
+                .iter()
+                .map(|vss_id| (*vss_id, subscriptions.provider_status(*vss_id)))
+                .collect();
+            if sender.send(statuses).is_err() {
+                warn!("Failed to create initial notification");
+            }
+
+            subscriptions.add_provider_status_subscription(ProviderStatusSubscription {
+                vss_ids,
+                sender,
+                permissions: self.permissions.clone(),
+            });
+        }
+
+        Ok(subscription_stream(receiver, channel_capacity))
+    }
+
     pub async fn provide_actuation(
         &self,
         vss_ids: Vec<i32>,
         actuation_provider: Box<dyn ActuationProvider + Send + Sync + 'static>,
-    ) -> Result<(), (ActuationError, String)> {
+    ) -> Result<SubscriptionId, (ActuationError, String)> {
         for vss_id in vss_ids.clone() {
             self.can_write_actuator_target(&vss_id).await?;
         }
@@ -1788,16 +2805,56 @@ impl AuthorizedAccess<'_, '_> {
         }
 
         let actuation_subscription: ActuationSubscription = ActuationSubscription {
+            id: 0,
             vss_ids,
             actuation_provider,
             permissions: self.permissions.clone(),
         };
-        self.broker
+        let subscription_id = self
+            .broker
             .subscriptions
             .write()
             .await
             .add_actuation_subscription(actuation_subscription);
 
+        Ok(subscription_id)
+    }
+
+    /// Replace the permissions of the given change subscriptions and provided
+    /// actuations with the permissions of this access, e.g. when the token of
+    /// a long-lived stream is refreshed.
+    ///
+    /// The permissions of the provided actuations must still allow actuating
+    /// them, otherwise no permissions are replaced.
+    pub async fn refresh_permissions(
+        &self,
+        subscription_ids: &HashSet<SubscriptionId>,
+    ) -> Result<(), (ActuationError, String)> {
+        let provided_vss_ids: Vec<i32> = self
+            .broker
+            .subscriptions
+            .read()
+            .await
+            .actuation_subscriptions
+            .iter()
+            .filter(|subscription| subscription_ids.contains(&subscription.id))
+            .flat_map(|subscription| subscription.vss_ids.clone())
+            .collect();
+        for vss_id in &provided_vss_ids {
+            self.can_write_actuator_target(vss_id).await?;
+        }
+
+        let mut subscriptions = self.broker.subscriptions.write().await;
+        for subscription in subscriptions.change_subscriptions.iter_mut() {
+            if subscription_ids.contains(&subscription.id) {
+                subscription.permissions = self.permissions.clone();
+            }
+        }
+        for subscription in subscriptions.actuation_subscriptions.iter_mut() {
+            if subscription_ids.contains(&subscription.id) {
+                subscription.permissions = self.permissions.clone();
+            }
+        }
         Ok(())
     }
 
@@ -1824,9 +2881,30 @@ impl AuthorizedAccess<'_, '_> {
         actuation_changes_per_vss_id
     }
 
+    /// Whether an actuation provider is registered for the actuator.
+    pub async fn has_actuation_provider(&self, vss_id: i32) -> bool {
+        self.broker
+            .subscriptions
+            .read()
+            .await
+            .actuation_subscriptions
+            .iter()
+            .any(|subscription| subscription.vss_ids.contains(&vss_id))
+    }
+
     pub async fn batch_actuate(
         &self,
         actuation_changes: Vec<ActuationChange>,
+    ) -> Result<(), (ActuationError, String)> {
+        let result = self.forward_batch_actuate(actuation_changes.clone()).await;
+        self.notify_actuation_events(&actuation_changes, &result)
+            .await;
+        result
+    }
+
+    async fn forward_batch_actuate(
+        &self,
+        actuation_changes: Vec<ActuationChange>,
     ) -> Result<(), (ActuationError, String)> {
         let read_subscription_guard = self.broker.subscriptions.read().await;
         let actuation_subscriptions = &read_subscription_guard.actuation_subscriptions;
@@ -1883,6 +2961,23 @@ impl AuthorizedAccess<'_, '_> {
         &self,
         vss_id: &i32,
         data_value: &DataValue,
+    ) -> Result<(), (ActuationError, String)> {
+        let result = self.forward_actuate(vss_id, data_value).await;
+        self.notify_actuation_events(
+            &[ActuationChange {
+                id: *vss_id,
+                data_value: data_value.clone(),
+            }],
+            &result,
+        )
+        .await;
+        result
+    }
+
+    async fn forward_actuate(
+        &self,
+        vss_id: &i32,
+        data_value: &DataValue,
     ) -> Result<(), (ActuationError, String)> {
         let vss_id = *vss_id;
 
@@ -1925,6 +3020,53 @@ impl AuthorizedAccess<'_, '_> {
         }
     }
 
+    // Inform actuation event subscribers about an actuation request and its result
+    async fn notify_actuation_events(
+        &self,
+        actuation_changes: &[ActuationChange],
+        result: &Result<(), (ActuationError, String)>,
+    ) {
+        let ts = SystemTime::now();
+        let (events, audit_records): (Vec<ActuationEvent>, _) = {
+            let db = self.broker.database.read().await;
+            let db_read = db.authorized_read_access(self.permissions);
+            let audit_records = self.audit_actuation_records(actuation_changes, result, &db_read);
+            let events = actuation_changes
+                .iter()
+                .filter_map(|change| {
+                    db_read
+                        .get_metadata_by_id(change.id)
+                        .map(|metadata| ActuationEvent {
+                            id: change.id,
+                            path: metadata.path.clone(),
+                            data_value: change.data_value.clone(),
+                            requester: self.permissions.subject().map(|subject| subject.to_owned()),
+                            ts,
+                            result: result.clone(),
+                        })
+                })
+                .collect();
+            (events, audit_records)
+        };
+        self.audit(audit_records);
+        if events.is_empty() {
+            return;
+        }
+
+        let cleanup_needed = self
+            .broker
+            .subscriptions
+            .read()
+            .await
+            .notify_actuation_events(&events)
+            .is_err();
+
+        // Cleanup closed subscriptions
+        if cleanup_needed {
+            self.broker.subscriptions.write().await.cleanup();
+        }
+    }
+
     async fn can_write_actuator_target(
         &self,
         vss_id: &i32,
@@ -2050,6 +3192,44 @@ impl AuthorizedAccess<'_, '_> {
     }
 }
 
+// Paths of all the values a query (including its subqueries) is executed on
+fn query_input_paths(query: &CompiledQuery) -> Vec<&String> {
+    query
+        .input_spec
+        .iter()
+        .chain(query.subquery.iter().flat_map(query_input_paths))
+        .collect()
+}
+
+fn subscription_channel_capacity(buffer_size: Option<usize>) -> Result<usize, SubscriptionError> {
+    if let Some(cap) = buffer_size {
+        if cap > MAX_SUBSCRIBE_BUFFER_SIZE {
+            return Err(SubscriptionError::InvalidBufferSize);
+        }
+        // Requested capacity for old messages plus 1 for latest
+        Ok(cap + 1)
+    } else {
+        // Just latest message
+        Ok(1)
+    }
+}
+
+fn subscription_stream<T: Clone + Send + 'static>(
+    receiver: broadcast::Receiver<T>,
+    channel_capacity: usize,
+) -> impl Stream<Item = T> {
+    BroadcastStream::new(receiver).filter_map(move |result| match result {
+        Ok(message) => Some(message),
+        Err(err) => {
+            warn!(
+                "Slow subscriber with capacity {} lagged and missed signal updates: {}",
+                channel_capacity, err
+            );
+            None
+        }
+    })
+}
+
 impl DataBroker {
     pub fn new(version: impl Into<String>, commit_sha: impl Into<String>) -> Self {
         let (shutdown_trigger, _) = broadcast::channel::<()>(1);
@@ -2060,9 +3240,43 @@ impl DataBroker {
             version: version.into(),
             commit_sha: commit_sha.into(),
             shutdown_trigger,
+            audit_log: None,
         }
     }
 
+    /// Record actuations, publishes and metadata changes in `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(Arc::new(audit_log));
+        self
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit_log.as_deref()
+    }
+
+    /// Record the history of the signals matching `patterns`, keeping up to
+    /// `size` values per signal. Must be called before any entries are added.
+    pub fn with_history(mut self, patterns: Vec<glob::Matcher>, size: usize) -> Self {
+        let history = &mut self.database_mut().history;
+        history.patterns = patterns;
+        history.size = size;
+        self
+    }
+
+    /// Keep the past values of the signals delayed by `delays` for as long
+    /// as they are delayed, so that delayed reads get the latest value old
+    /// enough instead of none. Must be called before any entries are added.
+    pub fn with_delays(mut self, delays: Transformations) -> Self {
+        self.database_mut().history.delays = delays;
+        self
+    }
+
+    fn database_mut(&mut self) -> &mut Database {
+        Arc::get_mut(&mut self.database)
+            .expect("the database is not shared before the broker is started")
+            .get_mut()
+    }
+
     #[cfg_attr(feature="otel", tracing::instrument(name="data_broker_authorized_access",skip(self, permissions), fields(timestamp=chrono::Utc::now().to_string())))]
     pub fn authorized_access<'a, 'b>(
         &'a self,
@@ -2071,6 +3285,7 @@ impl DataBroker {
         AuthorizedAccess {
             broker: self,
             permissions,
+            api: None,
         }
     }
 
@@ -2121,6 +3336,7 @@ impl Default for DataBroker {
 /// Public test module to allow other files to reuse helper functions
 pub mod tests {
     use crate::permissions;
+    use crate::transformation::Transformation;
 
     use super::*;
     use tokio_stream::StreamExt;
@@ -4425,6 +5641,664 @@ pub mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_subscribe_permission_denied() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.datapoint1", 10, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .add_subscribe_denial(permissions::Permission::Glob("test.datapoint1".to_owned()))
+            .build()
+            .expect("permissions should build");
+        let access = broker.authorized_access(&permissions);
+
+        // Polling is still allowed
+        access
+            .get_datapoint(id1)
+            .await
+            .expect("reading should succeed");
+
+        match access
+            .subscribe(
+                HashMap::from([(id1, HashSet::from([Field::Datapoint]))]),
+                None,
+            )
+            .await
+        {
+            Err(SubscriptionError::PermissionDenied) => {}
+            _ => {
+                panic!("expected it to fail with PermissionDenied");
+            }
+        }
+
+        match access.subscribe_query("SELECT test.datapoint1").await {
+            Err(QueryError::PermissionDenied(path)) => assert_eq!(path, "test.datapoint1"),
+            _ => {
+                panic!("expected it to fail with PermissionDenied");
+            }
+        }
+
+        // Subscribe permissions do not grant reading the values
+        let permissions = permissions::PermissionBuilder::new()
+            .add_subscribe_permission(permissions::Permission::All)
+            .add_read_permission(permissions::Permission::All)
+            .add_read_denial(permissions::Permission::Glob("test.datapoint1".to_owned()))
+            .build()
+            .expect("permissions should build");
+        let access = broker.authorized_access(&permissions);
+
+        match access
+            .subscribe(
+                HashMap::from([(id1, HashSet::from([Field::Datapoint]))]),
+                None,
+            )
+            .await
+        {
+            Err(SubscriptionError::PermissionDenied) => {}
+            _ => {
+                panic!("expected it to fail with PermissionDenied");
+            }
+        }
+
+        match access.subscribe_query("SELECT test.datapoint1").await {
+            Err(QueryError::PermissionDenied(path)) => assert_eq!(path, "test.datapoint1"),
+            _ => {
+                panic!("expected it to fail with PermissionDenied");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transformed_reads() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.datapoint1", 17, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        let id2 = helper_add_int32(&broker, "test.datapoint2", 20, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        let id3 = helper_add_int32(&broker, "test.datapoint3", 30, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .build()
+            .expect("permissions should build")
+            .with_transformations(Transformations::new(vec![
+                (
+                    glob::Matcher::new("test.datapoint1").expect("pattern should be valid"),
+                    Transformation::Quantise(5.0),
+                ),
+                (
+                    glob::Matcher::new("test.datapoint2").expect("pattern should be valid"),
+                    Transformation::Redact,
+                ),
+                (
+                    glob::Matcher::new("test.datapoint3").expect("pattern should be valid"),
+                    Transformation::Delay(60),
+                ),
+            ]));
+        let broker = broker.authorized_access(&permissions);
+
+        for (id, value) in [
+            (id1, DataValue::Int32(15)),
+            (id2, DataValue::NotAvailable),
+            // Not old enough yet
+            (id3, DataValue::NotAvailable),
+        ] {
+            let datapoint = broker
+                .get_datapoint(id)
+                .await
+                .expect("reading should succeed");
+            assert_eq!(datapoint.value, value);
+        }
+
+        let values = broker
+            .map_entries(|entry| {
+                (
+                    entry.metadata().path.clone(),
+                    entry
+                        .datapoint()
+                        .map(|datapoint| datapoint.value.clone())
+                        .ok(),
+                )
+            })
+            .await;
+        assert!(values.contains(&("test.datapoint1".to_owned(), Some(DataValue::Int32(15)))));
+    }
+
+    #[tokio::test]
+    async fn test_delayed_reads() {
+        let delay = |path| {
+            Transformations::new(vec![(
+                glob::Matcher::new(path).expect("pattern should be valid"),
+                Transformation::Delay(60),
+            )])
+        };
+        let broker = DataBroker::default()
+            .with_history(Vec::new(), 1)
+            .with_delays(delay("test.delayed"));
+        let now = std::time::SystemTime::now();
+        let ago = |secs| now - std::time::Duration::from_secs(secs);
+
+        let id = helper_add_int32(&broker, "test.delayed", 1, ago(120))
+            .await
+            .expect("Register datapoint should succeed");
+        for (value, ts) in [(2, ago(90)), (3, ago(30)), (4, now)] {
+            broker
+                .authorized_access(&permissions::ALLOW_ALL)
+                .update_entries([(
+                    id,
+                    EntryUpdate {
+                        datapoint: Some(Datapoint {
+                            ts,
+                            source_ts: None,
+                            value: DataValue::Int32(value),
+                        }),
+                        ..Default::default()
+                    },
+                )])
+                .await
+                .expect("update should succeed");
+        }
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .build()
+            .expect("permissions should build")
+            .with_transformations(delay("test.delayed"));
+        let broker = broker.authorized_access(&permissions);
+
+        // The latest value old enough is read, kept beyond the history size
+        let datapoint = broker
+            .get_datapoint(id)
+            .await
+            .expect("reading should succeed");
+        assert_eq!(datapoint.value, DataValue::Int32(2));
+        assert_eq!(datapoint.ts, ago(90));
+        let entry = broker
+            .get_entry_by_path("test.delayed")
+            .await
+            .expect("reading should succeed");
+        assert_eq!(entry.datapoint.value, DataValue::Int32(2));
+    }
+
+    #[tokio::test]
+    async fn test_delayed_notifications() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.immediate", 1, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        let id2 = helper_add_int32(&broker, "test.delayed", 1, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .build()
+            .expect("permissions should build")
+            .with_transformations(Transformations::new(vec![(
+                glob::Matcher::new("test.delayed").expect("pattern should be valid"),
+                Transformation::Delay(1),
+            )]));
+        let mut stream = broker
+            .authorized_access(&permissions)
+            .subscribe(
+                HashMap::from([
+                    (id1, HashSet::from([Field::Datapoint])),
+                    (id2, HashSet::from([Field::Datapoint])),
+                ]),
+                Some(10),
+            )
+            .await
+            .expect("subscription should succeed");
+
+        let update = |id, value| {
+            (
+                id,
+                EntryUpdate {
+                    datapoint: Some(Datapoint {
+                        ts: timestamp,
+                        source_ts: None,
+                        value: DataValue::Int32(value),
+                    }),
+                    ..Default::default()
+                },
+            )
+        };
+        for value in [2, 3] {
+            broker
+                .authorized_access(&permissions::ALLOW_ALL)
+                .update_entries([update(id1, value), update(id2, value)])
+                .await
+                .expect("update should succeed");
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..6 {
+            let notifications =
+                tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+                    .await
+                    .expect("notification should be sent")
+                    .expect("stream should be open");
+            for notification in notifications.updates {
+                received.push((
+                    notification.id,
+                    notification
+                        .update
+                        .datapoint
+                        .map(|datapoint| datapoint.value),
+                ));
+            }
+        }
+        // Delayed values are sent late, in order
+        assert_eq!(
+            received,
+            [(id1, 1), (id1, 2), (id1, 3), (id2, 1), (id2, 2), (id2, 3)]
+                .map(|(id, value)| (id, Some(DataValue::Int32(value))))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transformed_actuation_events() {
+        let broker = DataBroker::default();
+        let access = broker.authorized_access(&permissions::ALLOW_ALL);
+        let mut ids = Vec::new();
+        for path in ["test.redacted", "test.delayed"] {
+            let id = access
+                .add_entry(
+                    path.to_owned(),
+                    DataType::Int32,
+                    ChangeType::OnChange,
+                    EntryType::Actuator,
+                    "Test actuator".to_owned(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .expect("Register datapoint should succeed");
+            ids.push(id);
+        }
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .build()
+            .expect("permissions should build")
+            .with_transformations(Transformations::new(vec![
+                (
+                    glob::Matcher::new("test.redacted").expect("pattern should be valid"),
+                    Transformation::Redact,
+                ),
+                (
+                    glob::Matcher::new("test.delayed").expect("pattern should be valid"),
+                    Transformation::Delay(1),
+                ),
+            ]));
+        let mut stream = broker
+            .authorized_access(&permissions)
+            .subscribe_actuation_events(ids.iter().copied().collect(), Some(10))
+            .await
+            .expect("subscription should succeed");
+
+        // Without a provider the actuations fail, but are notified anyway
+        for id in [ids[1], ids[0]] {
+            access
+                .actuate(&id, &DataValue::Int32(42))
+                .await
+                .expect_err("actuation should fail without a provider");
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let events = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+                .await
+                .expect("event should be sent")
+                .expect("stream should be open");
+            received.extend(events.into_iter().map(|event| (event.id, event.data_value)));
+        }
+        // The delayed event is sent late
+        assert_eq!(
+            received,
+            [
+                (ids[0], DataValue::NotAvailable),
+                (ids[1], DataValue::Int32(42))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let broker = DataBroker::default().with_history(
+            vec![glob::Matcher::new("test.recorded").expect("pattern should be valid")],
+            3,
+        );
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.recorded", 1, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        let id2 = helper_add_int32(&broker, "test.other", 1, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let broker = broker.authorized_access(&permissions::ALLOW_ALL);
+        let update = |id, value, secs| {
+            (
+                id,
+                EntryUpdate {
+                    datapoint: Some(Datapoint {
+                        ts: timestamp + std::time::Duration::from_secs(secs),
+                        source_ts: None,
+                        value: DataValue::Int32(value),
+                    }),
+                    ..Default::default()
+                },
+            )
+        };
+        let history = |path: &'static str, since| {
+            let broker = &broker;
+            async move {
+                broker
+                    .get_history_by_path(path, since)
+                    .await
+                    .expect("reading history should succeed")
+                    .into_iter()
+                    .map(|datapoint| datapoint.value)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        for value in 2..5 {
+            broker
+                .update_entries([update(id1, value, value as u64), update(id2, value, 0)])
+                .await
+                .expect("update should succeed");
+        }
+        // Bounded to the most recent values
+        assert_eq!(
+            history("test.recorded", timestamp).await,
+            [2, 3, 4].map(DataValue::Int32)
+        );
+        assert_eq!(
+            history(
+                "test.recorded",
+                timestamp + std::time::Duration::from_secs(3)
+            )
+            .await,
+            [3, 4].map(DataValue::Int32)
+        );
+        // Only the current value is known unless recorded
+        assert_eq!(
+            history("test.other", timestamp).await,
+            [DataValue::Int32(4)]
+        );
+
+        broker.record_history([id2]).await;
+        broker
+            .update_entries([update(id2, 5, 1)])
+            .await
+            .expect("update should succeed");
+        assert_eq!(
+            history("test.other", timestamp).await,
+            [4, 5].map(DataValue::Int32)
+        );
+
+        // Recorded until the last subscriber stops
+        broker.record_history([id1, id2]).await;
+        broker.stop_recording_history([id1, id2]).await;
+        assert_eq!(
+            history("test.other", timestamp).await,
+            [4, 5].map(DataValue::Int32)
+        );
+        broker.stop_recording_history([id1, id2]).await;
+        assert_eq!(
+            history("test.other", timestamp).await,
+            [DataValue::Int32(5)]
+        );
+        assert_eq!(
+            history("test.recorded", timestamp).await,
+            [2, 3, 4].map(DataValue::Int32)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_subscription() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.datapoint1", 10, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        let id2 = helper_add_int32(&broker, "test.datapoint2", 20, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let broker = broker.authorized_access(&permissions::ALLOW_ALL);
+
+        let (subscription_id, stream) = broker
+            .subscribe_modifiable(Some(10))
+            .await
+            .expect("subscription should succeed");
+        tokio::pin!(stream);
+
+        broker
+            .modify_subscription(
+                subscription_id,
+                HashMap::from([(id1, HashSet::from([Field::Datapoint]))]),
+                HashSet::new(),
+            )
+            .await
+            .expect("adding entry should succeed");
+
+        // Only the added entry is sent initially
+        match stream.next().await {
+            Some(entry) => {
+                assert_eq!(entry.updates.len(), 1);
+                assert_eq!(entry.updates[0].id, id1);
+                assert_eq!(
+                    entry.updates[0].update.datapoint.as_ref().unwrap().value,
+                    DataValue::Int32(10)
+                );
+            }
+            None => {
+                panic!("did not expect stream end")
+            }
+        }
+
+        broker
+            .modify_subscription(
+                subscription_id,
+                HashMap::from([(id2, HashSet::from([Field::Datapoint]))]),
+                HashSet::from([id1]),
+            )
+            .await
+            .expect("modifying subscription should succeed");
+
+        // Only the newly added entry is sent, id1 is no longer subscribed
+        match stream.next().await {
+            Some(entry) => {
+                assert_eq!(entry.updates.len(), 1);
+                assert_eq!(entry.updates[0].id, id2);
+                assert_eq!(
+                    entry.updates[0].update.datapoint.as_ref().unwrap().value,
+                    DataValue::Int32(20)
+                );
+            }
+            None => {
+                panic!("did not expect stream end")
+            }
+        }
+
+        let update = |value| EntryUpdate {
+            path: None,
+            datapoint: Some(Datapoint {
+                ts: SystemTime::now(),
+                source_ts: None,
+                value: DataValue::Int32(value),
+            }),
+            actuator_target: None,
+            entry_type: None,
+            data_type: None,
+            description: None,
+            allowed: None,
+            min: None,
+            max: None,
+            unit: None,
+        };
+        broker
+            .update_entries([(id1, update(11)), (id2, update(21))])
+            .await
+            .expect("setting datapoints");
+
+        match stream.next().await {
+            Some(entry) => {
+                assert_eq!(entry.updates.len(), 1);
+                assert_eq!(entry.updates[0].id, id2);
+                assert_eq!(
+                    entry.updates[0].update.datapoint.as_ref().unwrap().value,
+                    DataValue::Int32(21)
+                );
+            }
+            None => {
+                panic!("did not expect stream end")
+            }
+        }
+
+        match broker
+            .modify_subscription(subscription_id + 1, HashMap::new(), HashSet::new())
+            .await
+        {
+            Err(SubscriptionError::NotFound) => {}
+            _ => {
+                panic!("expected it to fail with NotFound");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let path = std::env::temp_dir().join(format!("broker-audit-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit_log = AuditLog::open(&path, 1024 * 1024, 1)
+            .expect("audit log should open")
+            .publish_patterns(vec![
+                glob::Matcher::new("test.audited").expect("pattern should be valid")
+            ]);
+        let broker = DataBroker::default().with_audit_log(audit_log);
+        let timestamp = std::time::SystemTime::now();
+
+        let id = helper_add_int32(&broker, "test.audited", 10, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+        helper_add_int32(&broker, "test.other", 20, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let permissions = permissions::PermissionBuilder::new()
+            .subject("app")
+            .issuer("test")
+            .build()
+            .expect("permissions should build");
+        broker
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV2)
+            .actuate(&id, &DataValue::Int32(30))
+            .await
+            .expect_err("actuation should be denied");
+        broker.audit_log().unwrap().flush();
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .expect("audit log should be readable")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("record should be JSON"))
+            .collect();
+        std::fs::remove_file(&path).expect("audit log should be removable");
+
+        let events: Vec<(&str, &str)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["event"].as_str().unwrap(),
+                    record["path"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("metadata", "test.audited"),
+                ("publish", "test.audited"),
+                ("metadata", "test.other"),
+                ("actuate", "test.audited"),
+            ]
+        );
+        assert_eq!(records[1]["value"], "10");
+        assert_eq!(records[1]["result"], "ok");
+        assert_eq!(records[3]["subject"], "app");
+        assert_eq!(records[3]["issuer"], "test");
+        assert_eq!(records[3]["api"], "kuksa.val.v2");
+        assert_eq!(records[3]["value"], "30");
+        assert_ne!(records[3]["result"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_permissions() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let id1 = helper_add_int32(&broker, "test.datapoint1", 10, timestamp)
+            .await
+            .expect("Register datapoint should succeed");
+
+        let expired_permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .expires_at(timestamp - std::time::Duration::from_secs(1))
+            .build()
+            .expect("permissions should build");
+        let refreshed_permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::All)
+            .expires_at(timestamp + std::time::Duration::from_secs(3600))
+            .build()
+            .expect("permissions should build");
+
+        let (subscription_id, _stream) = broker
+            .authorized_access(&expired_permissions)
+            .subscribe_modifiable(Some(10))
+            .await
+            .expect("subscription should succeed");
+
+        broker
+            .authorized_access(&refreshed_permissions)
+            .refresh_permissions(&HashSet::from([subscription_id]))
+            .await
+            .expect("refreshing permissions should succeed");
+
+        // The subscription is kept since its permissions are no longer expired
+        broker.subscriptions.write().await.cleanup();
+
+        broker
+            .authorized_access(&refreshed_permissions)
+            .modify_subscription(
+                subscription_id,
+                HashMap::from([(id1, HashSet::from([Field::Datapoint]))]),
+                HashSet::new(),
+            )
+            .await
+            .expect("subscription should still exist");
+    }
+
     #[tokio::test]
     async fn test_metadata_for_each() {
         let db = DataBroker::default();
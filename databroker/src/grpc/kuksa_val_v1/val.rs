@@ -27,11 +27,13 @@ use tonic::{Response, Status, Streaming};
 use tracing::debug;
 use tracing::info;
 
+use crate::audit;
 use crate::broker;
 use crate::broker::ReadError;
 use crate::broker::SubscriptionError;
 use crate::broker::{AuthorizedAccess, EntryReadAccess};
 use crate::glob::Matcher;
+use crate::grpc::terminate_on_expiry;
 use crate::permissions::Permissions;
 use crate::types::{DataType, DataValue};
 
@@ -131,7 +133,10 @@ impl proto::val_server::Val for broker::DataBroker {
                                 // Update the `is_match` to indicate a valid and used request path.
                                 *is_match = true;
                                 if view_fields.contains(&proto::Field::Metadata) {
-                                    result_fields.extend(view_fields.clone());
+                                    match permissions.can_read_metadata(&entry.metadata().path) {
+                                        Ok(()) => result_fields.extend(view_fields.clone()),
+                                        Err(_) => *op_error = Some(ReadError::PermissionDenied),
+                                    }
                                 }
                                 if view_fields.contains(&proto::Field::ActuatorTarget)
                                     || view_fields.contains(&proto::Field::Value)
@@ -174,7 +179,14 @@ impl proto::val_server::Val for broker::DataBroker {
                                         // Update the `is_match` to indicate a valid and used request path.
                                         *is_match = true;
                                         if view_fields.contains(&proto::Field::Metadata) {
-                                            result_fields.extend(view_fields.clone());
+                                            match permissions
+                                                .can_read_metadata(&entry.metadata().path)
+                                            {
+                                                Ok(()) => result_fields.extend(view_fields.clone()),
+                                                Err(_) => {
+                                                    *op_error = Some(ReadError::PermissionDenied)
+                                                }
+                                            }
                                         }
                                         if view_fields.contains(&proto::Field::ActuatorTarget)
                                             || view_fields.contains(&proto::Field::Value)
@@ -278,7 +290,9 @@ impl proto::val_server::Val for broker::DataBroker {
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
 
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV1);
 
         let entry_updates = request.into_inner().updates;
 
@@ -361,7 +375,9 @@ impl proto::val_server::Val for broker::DataBroker {
         tokio::spawn(async move {
             info!("Update Stream opened");
             let permissions = permissions;
-            let broker = broker.authorized_access(&permissions);
+            let broker = broker
+                .authorized_access(&permissions)
+                .with_api(audit::Api::KuksaValV1);
             loop {
                 select! {
                     message = stream.message() => {
@@ -563,9 +579,8 @@ impl proto::val_server::Val for broker::DataBroker {
                                 })
                                 .or_insert(fields.clone());
 
-                            match entry.datapoint() {
-                                Ok(_) => {}
-                                Err(_) => permission_error = true,
+                            if permissions.can_subscribe(&entry.metadata().path).is_err() {
+                                permission_error = true;
                             }
                         }
                     })
@@ -589,9 +604,11 @@ impl proto::val_server::Val for broker::DataBroker {
                                             })
                                             .or_insert(fields.clone());
 
-                                        match entry.datapoint() {
-                                            Ok(_) => {}
-                                            Err(_) => permission_error = true,
+                                        if permissions
+                                            .can_subscribe(&entry.metadata().path)
+                                            .is_err()
+                                        {
+                                            permission_error = true;
                                         }
                                     }
                                 })
@@ -613,11 +630,21 @@ impl proto::val_server::Val for broker::DataBroker {
         match broker.subscribe(entries, None).await {
             Ok(stream) => {
                 let stream = convert_to_proto_stream(stream);
+                let stream = terminate_on_expiry(stream, &permissions, |expires_at| {
+                    Some(proto::SubscribeResponse {
+                        updates: Vec::new(),
+                        token_expires_at: Some(expires_at.into()),
+                    })
+                });
                 Ok(tonic::Response::new(Box::pin(stream)))
             }
             Err(SubscriptionError::NotFound) => {
                 Err(tonic::Status::new(tonic::Code::NotFound, "Path not found"))
             }
+            Err(SubscriptionError::PermissionDenied) => Err(tonic::Status::new(
+                tonic::Code::PermissionDenied,
+                "Permission denied",
+            )),
             Err(SubscriptionError::InvalidInput) => Err(tonic::Status::new(
                 tonic::Code::InvalidArgument,
                 "No valid path specified",
@@ -762,7 +789,10 @@ fn convert_to_proto_stream(
                     .collect(),
             });
         }
-        let response = proto::SubscribeResponse { updates };
+        let response = proto::SubscribeResponse {
+            updates,
+            token_expires_at: None,
+        };
         Ok(response)
     })
 }
@@ -229,6 +229,17 @@ impl From<&broker::Metadata> for proto::Metadata {
             allowed_values: transform_allowed(&metadata.allowed),
             min: transform_min_max(&metadata.min),
             max: transform_min_max(&metadata.max),
+            // Depends on registered providers, set separately for actuators
+            provider_status: proto::ProviderStatus::Unspecified as i32,
+        }
+    }
+}
+
+impl From<broker::ProviderStatus> for proto::ProviderStatus {
+    fn from(from: broker::ProviderStatus) -> Self {
+        match from {
+            broker::ProviderStatus::Available => proto::ProviderStatus::Available,
+            broker::ProviderStatus::Unavailable => proto::ProviderStatus::Unavailable,
         }
     }
 }
@@ -312,6 +323,43 @@ impl From<&broker::UpdateError> for proto::Error {
     }
 }
 
+impl From<&broker::ActuationEvent> for proto::ActuationEvent {
+    fn from(event: &broker::ActuationEvent) -> Self {
+        proto::ActuationEvent {
+            signal_id: event.id,
+            path: event.path.clone(),
+            value: Some(proto::Value::from(event.data_value.clone())),
+            requester: event.requester.clone().unwrap_or_default(),
+            timestamp: Some(event.ts.into()),
+            error: match &event.result {
+                Ok(()) => None,
+                Err((error, message)) => Some(status_to_proto_error(
+                    &error.to_tonic_status(message.clone()),
+                )),
+            },
+        }
+    }
+}
+
+// Used to return errors as messages in streams instead of closing the stream call
+pub fn status_to_proto_error(status: &tonic::Status) -> proto::Error {
+    let code = match status.code() {
+        tonic::Code::Ok => proto::ErrorCode::Ok,
+        tonic::Code::InvalidArgument => proto::ErrorCode::InvalidArgument,
+        tonic::Code::NotFound => proto::ErrorCode::NotFound,
+        tonic::Code::PermissionDenied => proto::ErrorCode::PermissionDenied,
+        tonic::Code::Unauthenticated => proto::ErrorCode::Unauthenticated,
+        tonic::Code::Unavailable => proto::ErrorCode::Unavailable,
+        tonic::Code::AlreadyExists => proto::ErrorCode::AlreadyExists,
+        tonic::Code::DataLoss => proto::ErrorCode::DataLoss,
+        _ => proto::ErrorCode::Internal,
+    };
+    proto::Error {
+        code: code.into(),
+        message: status.message().to_string(),
+    }
+}
+
 impl From<broker::DataType> for proto::DataType {
     fn from(from: broker::DataType) -> Self {
         match from {
@@ -343,6 +391,61 @@ impl From<broker::DataType> for proto::DataType {
     }
 }
 
+impl TryFrom<proto::DataType> for broker::DataType {
+    type Error = tonic::Status;
+
+    fn try_from(from: proto::DataType) -> Result<Self, Self::Error> {
+        match from {
+            proto::DataType::String => Ok(broker::DataType::String),
+            proto::DataType::Boolean => Ok(broker::DataType::Bool),
+            proto::DataType::Int8 => Ok(broker::DataType::Int8),
+            proto::DataType::Int16 => Ok(broker::DataType::Int16),
+            proto::DataType::Int32 => Ok(broker::DataType::Int32),
+            proto::DataType::Int64 => Ok(broker::DataType::Int64),
+            proto::DataType::Uint8 => Ok(broker::DataType::Uint8),
+            proto::DataType::Uint16 => Ok(broker::DataType::Uint16),
+            proto::DataType::Uint32 => Ok(broker::DataType::Uint32),
+            proto::DataType::Uint64 => Ok(broker::DataType::Uint64),
+            proto::DataType::Float => Ok(broker::DataType::Float),
+            proto::DataType::Double => Ok(broker::DataType::Double),
+            proto::DataType::StringArray => Ok(broker::DataType::StringArray),
+            proto::DataType::BooleanArray => Ok(broker::DataType::BoolArray),
+            proto::DataType::Int8Array => Ok(broker::DataType::Int8Array),
+            proto::DataType::Int16Array => Ok(broker::DataType::Int16Array),
+            proto::DataType::Int32Array => Ok(broker::DataType::Int32Array),
+            proto::DataType::Int64Array => Ok(broker::DataType::Int64Array),
+            proto::DataType::Uint8Array => Ok(broker::DataType::Uint8Array),
+            proto::DataType::Uint16Array => Ok(broker::DataType::Uint16Array),
+            proto::DataType::Uint32Array => Ok(broker::DataType::Uint32Array),
+            proto::DataType::Uint64Array => Ok(broker::DataType::Uint64Array),
+            proto::DataType::FloatArray => Ok(broker::DataType::FloatArray),
+            proto::DataType::DoubleArray => Ok(broker::DataType::DoubleArray),
+            proto::DataType::Unspecified => {
+                Err(tonic::Status::invalid_argument("Data type not specified"))
+            }
+            _ => Err(tonic::Status::invalid_argument(format!(
+                "Unsupported data type {}",
+                from.as_str_name()
+            ))),
+        }
+    }
+}
+
+impl TryFrom<proto::EntryType> for broker::EntryType {
+    type Error = tonic::Status;
+
+    fn try_from(from: proto::EntryType) -> Result<Self, Self::Error> {
+        match from {
+            proto::EntryType::Sensor => Ok(broker::EntryType::Sensor),
+            proto::EntryType::Attribute => Ok(broker::EntryType::Attribute),
+            proto::EntryType::Actuator => Ok(broker::EntryType::Actuator),
+            proto::EntryType::Unspecified => {
+                Err(tonic::Status::invalid_argument("Entry type not specified"))
+            }
+        }
+    }
+}
+
 impl From<broker::EntryType> for proto::EntryType {
     fn from(from: broker::EntryType) -> Self {
         match from {
@@ -13,19 +13,25 @@
 
 use std::{collections::HashMap, pin::Pin};
 
+use super::conversions::status_to_proto_error;
+use crate::grpc::terminate_on_expiry;
 use crate::{
+    audit,
+    authorization::Authorization,
     broker::{
         self, ActuationChange, ActuationProvider, AuthorizedAccess, ReadError, SubscriptionError,
+        SubscriptionId,
     },
-    glob::Matcher,
-    permissions::Permissions,
+    glob::{self, Matcher},
+    permissions::{self, ExpiryEvent, PermissionError, Permissions},
     types::DataValue,
 };
 
 use databroker_proto::kuksa::val::v2::{
-    self as proto,
+    self as proto, open_consumer_stream_request, open_consumer_stream_response,
     open_provider_stream_request::Action::{
         BatchActuateStreamResponse, ProvideActuationRequest, PublishValuesRequest,
+        RefreshTokenRequest, RegisterSignalsRequest, StreamOptionsRequest,
     },
     open_provider_stream_response, OpenProviderStreamResponse, PublishValuesResponse,
 };
@@ -40,6 +46,8 @@ use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tracing::debug;
 
 const MAX_REQUEST_PATH_LENGTH: usize = 1000;
+// Number of signal update messages buffered for slow consumers on OpenConsumerStream
+const CONSUMER_STREAM_BUFFER_SIZE: usize = 100;
 
 pub struct Provider {
     sender: mpsc::Sender<Result<OpenProviderStreamResponse, tonic::Status>>,
@@ -253,9 +261,18 @@ impl proto::val_server::Val for broker::DataBroker {
         {
             Ok(stream) => {
                 let stream = convert_to_proto_stream(stream, size);
+                let stream = terminate_on_expiry(stream, &permissions, |expires_at| {
+                    Some(proto::SubscribeResponse {
+                        entries: HashMap::new(),
+                        token_expiry_warning: Some(token_expiry_warning(expires_at)),
+                    })
+                });
                 Ok(tonic::Response::new(Box::pin(stream)))
             }
             Err(SubscriptionError::NotFound) => Err(tonic::Status::not_found("Path not found")),
+            Err(SubscriptionError::PermissionDenied) => {
+                Err(tonic::Status::permission_denied("Permission denied"))
+            }
             Err(SubscriptionError::InvalidInput) => Err(tonic::Status::invalid_argument(
                 "No valid id or path specified",
             )),
@@ -326,11 +343,20 @@ impl proto::val_server::Val for broker::DataBroker {
         {
             Ok(stream) => {
                 let stream = convert_to_proto_stream_id(stream, size);
+                let stream = terminate_on_expiry(stream, &permissions, |expires_at| {
+                    Some(proto::SubscribeByIdResponse {
+                        entries: HashMap::new(),
+                        token_expiry_warning: Some(token_expiry_warning(expires_at)),
+                    })
+                });
                 Ok(tonic::Response::new(Box::pin(stream)))
             }
             Err(SubscriptionError::NotFound) => {
                 Err(tonic::Status::new(tonic::Code::NotFound, "Path not found"))
             }
+            Err(SubscriptionError::PermissionDenied) => {
+                Err(tonic::Status::permission_denied("Permission denied"))
+            }
             Err(SubscriptionError::InvalidInput) => Err(tonic::Status::new(
                 tonic::Code::InvalidArgument,
                 "No valid id or path specified",
@@ -369,7 +395,9 @@ impl proto::val_server::Val for broker::DataBroker {
             .get::<Permissions>()
             .ok_or(tonic::Status::unauthenticated("Unauthenticated"))?
             .clone();
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV2);
 
         let actuator_request = request.into_inner();
         let value = actuator_request
@@ -437,7 +465,9 @@ impl proto::val_server::Val for broker::DataBroker {
             }
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV2);
         let actuate_requests = request.into_inner().actuate_requests;
 
         let mut actuation_changes: Vec<ActuationChange> = vec![];
@@ -478,9 +508,151 @@ impl proto::val_server::Val for broker::DataBroker {
         }
     }
 
+    type SubscribeActuationsStream = Pin<
+        Box<
+            dyn Stream<Item = Result<proto::SubscribeActuationsResponse, tonic::Status>>
+                + Send
+                + Sync
+                + 'static,
+        >,
+    >;
+    // Returns (GRPC error code):
+    //   NOT_FOUND if any of the signals are non-existant or no signal matches a pattern.
+    //   UNAUTHENTICATED if no credentials provided or credentials has expired
+    //   PERMISSION_DENIED if read access is denied for any of the signals.
+    //   INVALID_ARGUMENT if the request is empty, a path or pattern is invalid or
+    //   buffer_size exceeds the maximum permitted
+    //
+    async fn subscribe_actuations(
+        &self,
+        request: tonic::Request<proto::SubscribeActuationsRequest>,
+    ) -> Result<tonic::Response<Self::SubscribeActuationsStream>, tonic::Status> {
+        debug!(?request);
+        let permissions = match request.extensions().get::<Permissions>() {
+            Some(permissions) => {
+                debug!(?permissions);
+                permissions.clone()
+            }
+            None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
+        };
+
+        let request = request.into_inner();
+
+        let broker = self.authorized_access(&permissions);
+
+        let vss_ids = resolve_signals(&broker, request.signal_ids, &request.patterns, true).await?;
+
+        match broker
+            .subscribe_actuation_events(
+                vss_ids.into_iter().collect(),
+                Some(request.buffer_size as usize),
+            )
+            .await
+        {
+            Ok(stream) => {
+                let stream = stream
+                    .map(|events| proto::SubscribeActuationsResponse {
+                        events: events.iter().map(proto::ActuationEvent::from).collect(),
+                    })
+                    .map(Ok);
+                // The responses have no field to warn the client with
+                let stream = terminate_on_expiry(stream, &permissions, |_| None);
+                Ok(tonic::Response::new(Box::pin(stream)))
+            }
+            Err(SubscriptionError::NotFound) => Err(tonic::Status::not_found("Path not found")),
+            Err(SubscriptionError::PermissionDenied) => {
+                Err(tonic::Status::permission_denied("Permission denied"))
+            }
+            Err(SubscriptionError::InvalidInput) => Err(tonic::Status::invalid_argument(
+                "No valid id or path specified",
+            )),
+            Err(SubscriptionError::InternalError) => Err(tonic::Status::internal("Internal Error")),
+            Err(SubscriptionError::InvalidBufferSize) => Err(tonic::Status::new(
+                tonic::Code::InvalidArgument,
+                "Subscription buffer_size max allowed value is 1000",
+            )),
+        }
+    }
+
+    type SubscribeProviderStatusStream = Pin<
+        Box<
+            dyn Stream<Item = Result<proto::SubscribeProviderStatusResponse, tonic::Status>>
+                + Send
+                + Sync
+                + 'static,
+        >,
+    >;
+    // Returns (GRPC error code):
+    //   NOT_FOUND if any of the signals are non-existant or no signal matches a pattern.
+    //   UNAUTHENTICATED if no credentials provided or credentials has expired
+    //   PERMISSION_DENIED if read access is denied for any of the signals.
+    //   INVALID_ARGUMENT if the request contains no actuator, a path or pattern is invalid
+    //   or buffer_size exceeds the maximum permitted
+    //
+    async fn subscribe_provider_status(
+        &self,
+        request: tonic::Request<proto::SubscribeProviderStatusRequest>,
+    ) -> Result<tonic::Response<Self::SubscribeProviderStatusStream>, tonic::Status> {
+        debug!(?request);
+        let permissions = match request.extensions().get::<Permissions>() {
+            Some(permissions) => {
+                debug!(?permissions);
+                permissions.clone()
+            }
+            None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
+        };
+
+        let request = request.into_inner();
+
+        let broker = self.authorized_access(&permissions);
+
+        let mut vss_ids = HashSet::new();
+        for vss_id in resolve_signals(&broker, request.signal_ids, &request.patterns, true).await? {
+            if let Some(metadata) = broker.get_metadata(vss_id).await {
+                if metadata.entry_type == broker::EntryType::Actuator {
+                    vss_ids.insert(vss_id);
+                }
+            }
+        }
+
+        match broker
+            .subscribe_provider_status(vss_ids, Some(request.buffer_size as usize))
+            .await
+        {
+            Ok(stream) => {
+                let stream = stream
+                    .map(|statuses| proto::SubscribeProviderStatusResponse {
+                        statuses: statuses
+                            .into_iter()
+                            .map(|(vss_id, status)| {
+                                (vss_id, proto::ProviderStatus::from(status) as i32)
+                            })
+                            .collect(),
+                    })
+                    .map(Ok);
+                // The responses have no field to warn the client with
+                let stream = terminate_on_expiry(stream, &permissions, |_| None);
+                Ok(tonic::Response::new(Box::pin(stream)))
+            }
+            Err(SubscriptionError::NotFound) => Err(tonic::Status::not_found("Path not found")),
+            Err(SubscriptionError::PermissionDenied) => {
+                Err(tonic::Status::permission_denied("Permission denied"))
+            }
+            Err(SubscriptionError::InvalidInput) => {
+                Err(tonic::Status::invalid_argument("No actuator specified"))
+            }
+            Err(SubscriptionError::InternalError) => Err(tonic::Status::internal("Internal Error")),
+            Err(SubscriptionError::InvalidBufferSize) => Err(tonic::Status::new(
+                tonic::Code::InvalidArgument,
+                "Subscription buffer_size max allowed value is 1000",
+            )),
+        }
+    }
+
     // Returns (GRPC error code):
     //   NOT_FOUND if the specified root branch does not exist.
     //   UNAUTHENTICATED if no credentials provided or credentials has expired
+    //   PERMISSION_DENIED if access to the metadata of all matching signals is denied
     //   INVALID_ARGUMENT if the provided path or wildcard is wrong.
     //
     async fn list_metadata(
@@ -501,16 +673,35 @@ impl proto::val_server::Val for broker::DataBroker {
 
         match Matcher::new(&metadata_request.root) {
             Ok(matcher) => {
+                let provided_actuators = broker.get_provided_actuators().await;
                 let mut metadata_response = Vec::new();
+                let mut permission_denied = false;
                 broker
                     .for_each_entry(|entry| {
                         let entry_metadata = &entry.metadata();
-                        if matcher.is_match(&entry_metadata.glob_path) {
-                            metadata_response.push(proto::Metadata::from(*entry_metadata));
+                        if !matcher.is_match(&entry_metadata.glob_path) {
+                            return;
+                        }
+                        if permissions.can_read_metadata(&entry_metadata.path).is_err() {
+                            permission_denied = true;
+                        } else {
+                            let mut metadata = proto::Metadata::from(*entry_metadata);
+                            if entry_metadata.entry_type == broker::EntryType::Actuator {
+                                let provider_status =
+                                    if provided_actuators.contains(&entry_metadata.id) {
+                                        proto::ProviderStatus::Available
+                                    } else {
+                                        proto::ProviderStatus::Unavailable
+                                    };
+                                metadata.set_provider_status(provider_status);
+                            }
+                            metadata_response.push(metadata);
                         }
                     })
                     .await;
-                if metadata_response.is_empty() {
+                if metadata_response.is_empty() && permission_denied {
+                    Err(tonic::Status::permission_denied("Permission denied"))
+                } else if metadata_response.is_empty() {
                     Err(tonic::Status::not_found(
                         "Specified root branch does not exist",
                     ))
@@ -549,7 +740,9 @@ impl proto::val_server::Val for broker::DataBroker {
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
 
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV2);
 
         let request = request.into_inner();
 
@@ -590,6 +783,33 @@ impl proto::val_server::Val for broker::DataBroker {
         }
     }
 
+    // Returns (GRPC error code):
+    //   PERMISSION_DENIED if create access is denied for any of the signals.
+    //   UNAUTHENTICATED if no credentials provided or credentials has expired
+    //   INVALID_ARGUMENT if the request is empty, a path is invalid, the data type or
+    //   entry type is not specified or the allowed values do not match the data type
+    //
+    async fn register_signals(
+        &self,
+        request: tonic::Request<proto::RegisterSignalsRequest>,
+    ) -> Result<tonic::Response<proto::RegisterSignalsResponse>, tonic::Status> {
+        debug!(?request);
+        let permissions = match request.extensions().get::<Permissions>() {
+            Some(permissions) => {
+                debug!(?permissions);
+                permissions.clone()
+            }
+            None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
+        };
+
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::KuksaValV2);
+
+        let response = register_signals(&broker, &permissions, request.into_inner()).await?;
+        Ok(tonic::Response::new(response))
+    }
+
     type OpenProviderStreamStream =
         ReceiverStream<Result<proto::OpenProviderStreamResponse, tonic::Status>>;
 
@@ -631,20 +851,26 @@ impl proto::val_server::Val for broker::DataBroker {
             }
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
+        let authorization = request.extensions().get::<Authorization>().cloned();
 
         let mut stream = request.into_inner();
 
         let mut shutdown_trigger = self.get_shutdown_trigger();
 
         // Copy (to move into task below)
-        let broker = self.clone();
+        let data_broker = self.clone();
         // Create stream (to be returned)
         let (response_stream_sender, response_stream_receiver) = mpsc::channel(10);
 
         // Listening on stream
         tokio::spawn(async move {
-            let permissions = permissions;
-            let broker = broker.authorized_access(&permissions);
+            let mut permissions = permissions;
+            // Provided actuations, whose permissions are replaced when the token is refreshed
+            let mut subscription_ids = HashSet::new();
+            // Stream options can only be set once, before values are published
+            let mut options_locked = false;
+            let mut acknowledge_publish_values = false;
+            let mut expiry_warned = false;
             loop {
                 select! {
                     message = stream.message() => {
@@ -652,19 +878,77 @@ impl proto::val_server::Val for broker::DataBroker {
                             Ok(request) => {
                                 match request {
                                     Some(req) => {
+                                        let broker = data_broker.authorized_access(&permissions).with_api(audit::Api::KuksaValV2);
                                         match req.action {
                                             Some(ProvideActuationRequest(provided_actuation)) => {
-                                                let response = provide_actuation(&broker, &provided_actuation, response_stream_sender.clone()).await;
+                                                let response = provide_actuation(&broker, &provided_actuation, response_stream_sender.clone())
+                                                    .await
+                                                    .map(|(subscription_id, response)| {
+                                                        subscription_ids.insert(subscription_id);
+                                                        response
+                                                    });
+                                                if let Err(err) = response_stream_sender.send(response).await
+                                                {
+                                                    debug!("Failed to send response: {}", err)
+                                                }
+                                            },
+                                            Some(RegisterSignalsRequest(register_signals_request)) => {
+                                                let response = register_signals(&broker, &permissions, register_signals_request)
+                                                    .await
+                                                    .map(|response| OpenProviderStreamResponse {
+                                                        action: Some(open_provider_stream_response::Action::RegisterSignalsResponse(response)),
+                                                    });
+                                                if let Err(err) = response_stream_sender.send(response).await
+                                                {
+                                                    debug!("Failed to send response: {}", err)
+                                                }
+                                            },
+                                            Some(StreamOptionsRequest(stream_options_request)) => {
+                                                let response = if options_locked {
+                                                    Err(tonic::Status::failed_precondition(
+                                                        "Stream options can only be set once, before publishing values",
+                                                    ))
+                                                } else {
+                                                    options_locked = true;
+                                                    acknowledge_publish_values = stream_options_request.acknowledge_publish_values;
+                                                    Ok(OpenProviderStreamResponse {
+                                                        action: Some(open_provider_stream_response::Action::StreamOptionsResponse(
+                                                            proto::StreamOptionsResponse {
+                                                                acknowledge_publish_values,
+                                                            },
+                                                        )),
+                                                    })
+                                                };
                                                 if let Err(err) = response_stream_sender.send(response).await
                                                 {
                                                     debug!("Failed to send response: {}", err)
                                                 }
                                             },
+                                            Some(RefreshTokenRequest(refresh_token_request)) => {
+                                                let request_id = refresh_token_request.request_id;
+                                                let result = refresh_token(&data_broker, authorization.as_ref(), &permissions, &subscription_ids, refresh_token_request).await;
+                                                let response = refresh_token_response(request_id, &result);
+                                                if let Ok(refreshed_permissions) = result {
+                                                    permissions = refreshed_permissions;
+                                                    expiry_warned = false;
+                                                }
+                                                let response = OpenProviderStreamResponse {
+                                                    action: Some(open_provider_stream_response::Action::RefreshTokenResponse(response)),
+                                                };
+                                                if let Err(err) = response_stream_sender.send(Ok(response)).await
+                                                {
+                                                    debug!("Failed to send response: {}", err)
+                                                }
+                                            },
                                             Some(PublishValuesRequest(publish_values_request)) => {
+                                                options_locked = true;
                                                 let response = publish_values(&broker, &publish_values_request).await;
-                                                if let Some(value) = response {
+                                                if acknowledge_publish_values || !response.status.is_empty() {
+                                                    let value = OpenProviderStreamResponse {
+                                                        action: Some(open_provider_stream_response::Action::PublishValuesResponse(response)),
+                                                    };
                                                     if let Err(err) = response_stream_sender.send(Ok(value)).await {
-                                                        debug!("Failed to send error response: {}", err);
+                                                        debug!("Failed to send publish values response: {}", err);
                                                     }
                                                 }
                                             },
@@ -710,6 +994,29 @@ impl proto::val_server::Val for broker::DataBroker {
                             },
                         }
                     },
+                    event = permissions::expiry_event(permissions.expires_at(), expiry_warned) => {
+                        let response = match event {
+                            ExpiryEvent::ExpiresSoon(expires_at) => {
+                                expiry_warned = true;
+                                Ok(OpenProviderStreamResponse {
+                                    action: Some(open_provider_stream_response::Action::TokenExpiryWarning(
+                                        token_expiry_warning(expires_at),
+                                    )),
+                                })
+                            }
+                            ExpiryEvent::Expired => {
+                                debug!("provider: permissions expired");
+                                Err(tonic::Status::unauthenticated("Permissions expired"))
+                            }
+                        };
+                        let expired = response.is_err();
+                        if let Err(err) = response_stream_sender.send(response).await {
+                            debug!("Failed to send response: {}", err)
+                        }
+                        if expired {
+                            break;
+                        }
+                    },
                     _ = shutdown_trigger.recv() => {
                         debug!("provider: shutdown received");
                         break;
@@ -723,6 +1030,157 @@ impl proto::val_server::Val for broker::DataBroker {
         )))
     }
 
+    type OpenConsumerStreamStream =
+        ReceiverStream<Result<proto::OpenConsumerStreamResponse, tonic::Status>>;
+
+    // Returns (GRPC error code) and closes the stream call:
+    //   UNAUTHENTICATED if no credentials provided
+    //
+    // Errors of individual requests are returned as messages in the stream,
+    // using the same error codes as the corresponding unary calls.
+    //
+    async fn open_consumer_stream(
+        &self,
+        request: tonic::Request<tonic::Streaming<proto::OpenConsumerStreamRequest>>,
+    ) -> Result<tonic::Response<Self::OpenConsumerStreamStream>, tonic::Status> {
+        debug!(?request);
+        let permissions = match request.extensions().get::<Permissions>() {
+            Some(permissions) => {
+                debug!(?permissions);
+                permissions.clone()
+            }
+            None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
+        };
+        let authorization = request.extensions().get::<Authorization>().cloned();
+
+        let mut stream = request.into_inner();
+
+        let mut shutdown_trigger = self.get_shutdown_trigger();
+
+        // Copy (to move into task below)
+        let broker = self.clone();
+        // Create stream (to be returned)
+        let (response_stream_sender, response_stream_receiver) = mpsc::channel(10);
+
+        tokio::spawn(async move {
+            let mut permissions = permissions;
+
+            let (subscription_id, updates) = match broker
+                .authorized_access(&permissions)
+                .subscribe_modifiable(Some(CONSUMER_STREAM_BUFFER_SIZE))
+                .await
+            {
+                Ok(subscription) => subscription,
+                Err(err) => {
+                    debug!("consumer: failed to create subscription: {:?}", err);
+                    let _ = response_stream_sender
+                        .send(Err(tonic::Status::internal("Internal Error")))
+                        .await;
+                    return;
+                }
+            };
+            let updates = convert_to_signal_updates_stream(updates);
+            tokio::pin!(updates);
+
+            // The consumer may close its side of the stream and still receive
+            // updates of the subscribed signals.
+            let mut requests_closed = false;
+            let mut expiry_warned = false;
+            loop {
+                select! {
+                    message = stream.message(), if !requests_closed => {
+                        match message {
+                            Ok(Some(req)) => {
+                                let authorized_access = broker.authorized_access(&permissions);
+                                let response = match req.action {
+                                    Some(open_consumer_stream_request::Action::AddSignalsRequest(request)) => {
+                                        Some(add_signals(&authorized_access, subscription_id, request).await)
+                                    },
+                                    Some(open_consumer_stream_request::Action::RemoveSignalsRequest(request)) => {
+                                        Some(remove_signals(&authorized_access, subscription_id, request).await)
+                                    },
+                                    Some(open_consumer_stream_request::Action::GetValuesRequest(request)) => {
+                                        Some(stream_get_values(&broker, &permissions, request).await)
+                                    },
+                                    Some(open_consumer_stream_request::Action::ActuateRequest(request)) => {
+                                        Some(stream_actuate(&broker, &permissions, request).await)
+                                    },
+                                    Some(open_consumer_stream_request::Action::RefreshTokenRequest(request)) => {
+                                        let request_id = request.request_id;
+                                        let result = refresh_token(&broker, authorization.as_ref(), &permissions, &HashSet::from([subscription_id]), request).await;
+                                        let response = refresh_token_response(request_id, &result);
+                                        if let Ok(refreshed_permissions) = result {
+                                            permissions = refreshed_permissions;
+                                            expiry_warned = false;
+                                        }
+                                        Some(proto::OpenConsumerStreamResponse {
+                                            action: Some(open_consumer_stream_response::Action::RefreshTokenResponse(response)),
+                                        })
+                                    },
+                                    None => None,
+                                };
+                                if let Some(response) = response {
+                                    if let Err(err) = response_stream_sender.send(Ok(response)).await {
+                                        debug!("Failed to send response: {}", err);
+                                    }
+                                }
+                            },
+                            Ok(None) => {
+                                debug!("consumer: no more messages");
+                                requests_closed = true;
+                            },
+                            Err(err) => {
+                                debug!("consumer: connection broken: {:?}", err);
+                                break;
+                            },
+                        }
+                    },
+                    event = permissions::expiry_event(permissions.expires_at(), expiry_warned) => {
+                        let response = match event {
+                            ExpiryEvent::ExpiresSoon(expires_at) => {
+                                expiry_warned = true;
+                                Ok(proto::OpenConsumerStreamResponse {
+                                    action: Some(open_consumer_stream_response::Action::TokenExpiryWarning(
+                                        token_expiry_warning(expires_at),
+                                    )),
+                                })
+                            }
+                            ExpiryEvent::Expired => {
+                                debug!("consumer: permissions expired");
+                                Err(tonic::Status::unauthenticated("Permissions expired"))
+                            }
+                        };
+                        let expired = response.is_err();
+                        if let Err(err) = response_stream_sender.send(response).await {
+                            debug!("Failed to send response: {}", err);
+                        }
+                        if expired {
+                            break;
+                        }
+                    },
+                    Some(update) = updates.next() => {
+                        if let Err(err) = response_stream_sender.send(Ok(update)).await {
+                            debug!("Failed to send signal updates: {}", err);
+                            break;
+                        }
+                    },
+                    _ = response_stream_sender.closed() => {
+                        debug!("consumer: response stream closed");
+                        break;
+                    },
+                    _ = shutdown_trigger.recv() => {
+                        debug!("consumer: shutdown received");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(tonic::Response::new(ReceiverStream::new(
+            response_stream_receiver,
+        )))
+    }
+
     async fn get_server_info(
         &self,
         _request: tonic::Request<proto::GetServerInfoRequest>,
@@ -740,7 +1198,7 @@ async fn provide_actuation(
     broker: &AuthorizedAccess<'_, '_>,
     request: &databroker_proto::kuksa::val::v2::ProvideActuationRequest,
     sender: mpsc::Sender<Result<OpenProviderStreamResponse, tonic::Status>>,
-) -> Result<OpenProviderStreamResponse, tonic::Status> {
+) -> Result<(SubscriptionId, OpenProviderStreamResponse), tonic::Status> {
     let vss_paths: Vec<_> = request
         .actuator_identifiers
         .iter()
@@ -786,7 +1244,7 @@ async fn provide_actuation(
         .provide_actuation(all_vss_ids, Box::new(provider))
         .await
     {
-        Ok(_) => {
+        Ok(subscription_id) => {
             let provide_actuation_response = ProvideActuationResponse {};
 
             let response = OpenProviderStreamResponse {
@@ -797,17 +1255,165 @@ async fn provide_actuation(
                 ),
             };
 
-            Ok(response)
+            Ok((subscription_id, response))
         }
 
         Err(error) => Err(error.0.to_tonic_status(error.1)),
     }
 }
 
+// Validate the token of a refresh token request and replace the permissions of the
+// given subscriptions of the stream. Returns the permissions granted by the new token.
+async fn refresh_token(
+    broker: &broker::DataBroker,
+    authorization: Option<&Authorization>,
+    permissions: &Permissions,
+    subscription_ids: &HashSet<SubscriptionId>,
+    request: proto::RefreshTokenRequest,
+) -> Result<Permissions, tonic::Status> {
+    let authorization =
+        authorization.ok_or_else(|| tonic::Status::unauthenticated("Unauthenticated"))?;
+    let refreshed_permissions = authorization
+        .permissions_from_token(&request.token)
+        .map_err(|err| tonic::Status::unauthenticated(err.to_string()))?;
+
+    if refreshed_permissions.subject() != permissions.subject()
+        || refreshed_permissions.issuer() != permissions.issuer()
+    {
+        return Err(tonic::Status::permission_denied(
+            "Token is issued to another subject",
+        ));
+    }
+
+    broker
+        .authorized_access(&refreshed_permissions)
+        .refresh_permissions(subscription_ids)
+        .await
+        .map_err(|(error, message)| error.to_tonic_status(message))?;
+
+    Ok(refreshed_permissions)
+}
+
+fn token_expiry_warning(expires_at: std::time::SystemTime) -> proto::TokenExpiryWarning {
+    proto::TokenExpiryWarning {
+        expires_at: Some(expires_at.into()),
+    }
+}
+
+fn refresh_token_response(
+    request_id: i32,
+    result: &Result<Permissions, tonic::Status>,
+) -> proto::RefreshTokenResponse {
+    match result {
+        Ok(permissions) => proto::RefreshTokenResponse {
+            request_id,
+            expires_at: permissions.expires_at().map(|expires_at| expires_at.into()),
+            error: None,
+        },
+        Err(status) => proto::RefreshTokenResponse {
+            request_id,
+            expires_at: None,
+            error: Some(status_to_proto_error(status)),
+        },
+    }
+}
+
+async fn register_signals(
+    broker: &AuthorizedAccess<'_, '_>,
+    permissions: &Permissions,
+    request: proto::RegisterSignalsRequest,
+) -> Result<proto::RegisterSignalsResponse, tonic::Status> {
+    if request.signals.is_empty() {
+        return Err(tonic::Status::invalid_argument("No signals provided"));
+    }
+
+    // Validate all signals before registering any of them
+    let mut signals = Vec::with_capacity(request.signals.len());
+    for metadata in request.signals {
+        if metadata.path.len() > MAX_REQUEST_PATH_LENGTH {
+            return Err(tonic::Status::invalid_argument(
+                "The provided path is too long",
+            ));
+        }
+        if !glob::is_valid_path(&metadata.path) {
+            return Err(tonic::Status::invalid_argument(format!(
+                "Invalid path {}",
+                metadata.path
+            )));
+        }
+        match permissions.can_create(&metadata.path) {
+            Ok(()) => {}
+            Err(PermissionError::Denied) => {
+                return Err(tonic::Status::permission_denied(format!(
+                    "Permission denied for {}",
+                    metadata.path
+                )))
+            }
+            Err(PermissionError::Expired) => {
+                return Err(tonic::Status::unauthenticated("Permission expired"))
+            }
+        }
+        let data_type = broker::DataType::try_from(metadata.data_type())?;
+        let entry_type = broker::EntryType::try_from(metadata.entry_type())?;
+        signals.push((data_type, entry_type, metadata));
+    }
+
+    let registrations: Vec<broker::EntryRegistration> = signals
+        .into_iter()
+        .map(
+            |(data_type, entry_type, metadata)| broker::EntryRegistration {
+                // Actuators only change when actuated
+                change_type: match entry_type {
+                    broker::EntryType::Attribute => broker::ChangeType::Static,
+                    broker::EntryType::Actuator => broker::ChangeType::OnChange,
+                    broker::EntryType::Sensor => broker::ChangeType::Continuous,
+                },
+                path: metadata.path,
+                data_type,
+                entry_type,
+                description: metadata.description,
+                min: metadata.min.map(DataValue::from),
+                max: metadata.max.map(DataValue::from),
+                allowed: metadata.allowed_values.map(DataValue::from),
+                unit: if metadata.unit.is_empty() {
+                    None
+                } else {
+                    Some(metadata.unit)
+                },
+            },
+        )
+        .collect();
+    let paths: Vec<String> = registrations
+        .iter()
+        .map(|registration| registration.path.clone())
+        .collect();
+
+    let ids = broker
+        .add_entries(registrations)
+        .await
+        .map_err(|(path, err)| match err {
+            broker::RegistrationError::ValidationError => {
+                tonic::Status::invalid_argument(format!("Failed to register {}", path))
+            }
+            broker::RegistrationError::PermissionDenied => {
+                tonic::Status::permission_denied(format!("Failed to register {}", path))
+            }
+            broker::RegistrationError::PermissionExpired => {
+                tonic::Status::unauthenticated(format!("Failed to register {}", path))
+            }
+        })?;
+    let signal_ids = paths.into_iter().zip(ids).collect();
+
+    Ok(proto::RegisterSignalsResponse {
+        request_id: request.request_id,
+        signal_ids,
+    })
+}
+
 async fn publish_values(
     broker: &AuthorizedAccess<'_, '_>,
     request: &databroker_proto::kuksa::val::v2::PublishValuesRequest,
-) -> Option<OpenProviderStreamResponse> {
+) -> PublishValuesResponse {
     let ids: Vec<(i32, broker::EntryUpdate)> = request
         .data_points
         .iter()
@@ -831,21 +1437,237 @@ async fn publish_values(
         .collect();
 
     // TODO check if provider is allowed to update the entries for the provided signals?
-    match broker.update_entries(ids).await {
-        Ok(_) => None,
-        Err(err) => Some(OpenProviderStreamResponse {
-            action: Some(
-                open_provider_stream_response::Action::PublishValuesResponse(
-                    PublishValuesResponse {
-                        request_id: request.request_id,
-                        status: err
-                            .iter()
-                            .map(|(id, error)| (*id, proto::Error::from(error)))
-                            .collect(),
-                    },
-                ),
+    // An empty status means that all values were applied
+    let status = match broker.update_entries(ids).await {
+        Ok(_) => HashMap::new(),
+        Err(err) => err
+            .iter()
+            .map(|(id, error)| (*id, proto::Error::from(error)))
+            .collect(),
+    };
+
+    PublishValuesResponse {
+        request_id: request.request_id,
+        status,
+    }
+}
+
+// Resolve the ids of the signals given by id, path or wildcard pattern.
+// Only signals readable with the given permissions are returned if `readable_only` is set.
+async fn resolve_signals(
+    broker: &AuthorizedAccess<'_, '_>,
+    signal_ids: Vec<proto::SignalId>,
+    patterns: &[String],
+    readable_only: bool,
+) -> Result<Vec<i32>, tonic::Status> {
+    let mut ids = Vec::new();
+
+    for signal_id in signal_ids {
+        let id = get_signal(Some(signal_id), broker).await?;
+        if readable_only {
+            match broker.get_datapoint(id).await {
+                Ok(_) => {}
+                Err(ReadError::NotFound) => {
+                    return Err(tonic::Status::not_found(format!(
+                        "Path not found (id: {})",
+                        id
+                    )))
+                }
+                Err(ReadError::PermissionDenied) => {
+                    return Err(tonic::Status::permission_denied(format!(
+                        "Permission denied (id: {})",
+                        id
+                    )))
+                }
+                Err(ReadError::PermissionExpired) => {
+                    return Err(tonic::Status::unauthenticated(format!(
+                        "Permission expired (id: {})",
+                        id
+                    )))
+                }
+            }
+        }
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    for pattern in patterns {
+        let matcher = Matcher::new(pattern)
+            .map_err(|_| tonic::Status::invalid_argument("Invalid Pattern Argument"))?;
+        let matches = broker
+            .filter_map_entries(|entry| {
+                if matcher.is_match(&entry.metadata().glob_path) {
+                    Some((entry.metadata().id, entry.datapoint().is_ok()))
+                } else {
+                    None
+                }
+            })
+            .await;
+        if matches.is_empty() {
+            return Err(tonic::Status::not_found(format!(
+                "No signals found matching pattern {}",
+                pattern
+            )));
+        }
+        let matched_ids: Vec<i32> = matches
+            .into_iter()
+            .filter(|(_, readable)| *readable || !readable_only)
+            .map(|(id, _)| id)
+            .collect();
+        if matched_ids.is_empty() {
+            return Err(tonic::Status::permission_denied(format!(
+                "Permission denied for signals matching pattern {}",
+                pattern
+            )));
+        }
+        for id in matched_ids {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+fn modify_subscription_error(error: SubscriptionError) -> proto::Error {
+    let status = match error {
+        SubscriptionError::NotFound => tonic::Status::not_found("Subscription not found"),
+        SubscriptionError::PermissionDenied => {
+            tonic::Status::permission_denied("Permission denied")
+        }
+        SubscriptionError::InvalidInput => {
+            tonic::Status::invalid_argument("No valid id or path specified")
+        }
+        SubscriptionError::InvalidBufferSize => {
+            tonic::Status::invalid_argument("Subscription buffer_size max allowed value is 1000")
+        }
+        SubscriptionError::InternalError => tonic::Status::internal("Internal Error"),
+    };
+    status_to_proto_error(&status)
+}
+
+async fn add_signals(
+    broker: &AuthorizedAccess<'_, '_>,
+    subscription_id: SubscriptionId,
+    request: proto::AddSignalsRequest,
+) -> proto::OpenConsumerStreamResponse {
+    let (signal_ids, error) =
+        match resolve_signals(broker, request.signal_ids, &request.patterns, true).await {
+            Ok(ids) => {
+                let added_entries = ids
+                    .iter()
+                    .map(|id| (*id, HashSet::from([broker::Field::Datapoint])))
+                    .collect();
+                match broker
+                    .modify_subscription(subscription_id, added_entries, HashSet::new())
+                    .await
+                {
+                    Ok(()) => (ids, None),
+                    Err(err) => (vec![], Some(modify_subscription_error(err))),
+                }
+            }
+            Err(status) => (vec![], Some(status_to_proto_error(&status))),
+        };
+
+    proto::OpenConsumerStreamResponse {
+        action: Some(open_consumer_stream_response::Action::AddSignalsResponse(
+            proto::AddSignalsResponse {
+                request_id: request.request_id,
+                signal_ids,
+                error,
+            },
+        )),
+    }
+}
+
+async fn remove_signals(
+    broker: &AuthorizedAccess<'_, '_>,
+    subscription_id: SubscriptionId,
+    request: proto::RemoveSignalsRequest,
+) -> proto::OpenConsumerStreamResponse {
+    let (signal_ids, error) =
+        match resolve_signals(broker, request.signal_ids, &request.patterns, false).await {
+            Ok(ids) => {
+                let removed_entries = ids.iter().cloned().collect();
+                match broker
+                    .modify_subscription(subscription_id, HashMap::new(), removed_entries)
+                    .await
+                {
+                    Ok(()) => (ids, None),
+                    Err(err) => (vec![], Some(modify_subscription_error(err))),
+                }
+            }
+            Err(status) => (vec![], Some(status_to_proto_error(&status))),
+        };
+
+    proto::OpenConsumerStreamResponse {
+        action: Some(
+            open_consumer_stream_response::Action::RemoveSignalsResponse(
+                proto::RemoveSignalsResponse {
+                    request_id: request.request_id,
+                    signal_ids,
+                    error,
+                },
             ),
-        }),
+        ),
+    }
+}
+
+async fn stream_get_values(
+    broker: &broker::DataBroker,
+    permissions: &Permissions,
+    request: proto::StreamGetValuesRequest,
+) -> proto::OpenConsumerStreamResponse {
+    let mut get_values_request = tonic::Request::new(proto::GetValuesRequest {
+        signal_ids: request.signal_ids,
+    });
+    get_values_request
+        .extensions_mut()
+        .insert(permissions.clone());
+
+    let (data_points, error) =
+        match proto::val_server::Val::get_values(broker, get_values_request).await {
+            Ok(response) => (response.into_inner().data_points, None),
+            Err(status) => (vec![], Some(status_to_proto_error(&status))),
+        };
+
+    proto::OpenConsumerStreamResponse {
+        action: Some(open_consumer_stream_response::Action::GetValuesResponse(
+            proto::StreamGetValuesResponse {
+                request_id: request.request_id,
+                data_points,
+                error,
+            },
+        )),
+    }
+}
+
+async fn stream_actuate(
+    broker: &broker::DataBroker,
+    permissions: &Permissions,
+    request: proto::StreamActuateRequest,
+) -> proto::OpenConsumerStreamResponse {
+    let mut batch_actuate_request = tonic::Request::new(proto::BatchActuateRequest {
+        actuate_requests: request.actuate_requests,
+    });
+    batch_actuate_request
+        .extensions_mut()
+        .insert(permissions.clone());
+
+    let error = match proto::val_server::Val::batch_actuate(broker, batch_actuate_request).await {
+        Ok(_) => None,
+        Err(status) => Some(status_to_proto_error(&status)),
+    };
+
+    proto::OpenConsumerStreamResponse {
+        action: Some(open_consumer_stream_response::Action::ActuateResponse(
+            proto::StreamActuateResponse {
+                request_id: request.request_id,
+                error,
+            },
+        )),
     }
 }
 
@@ -901,7 +1723,10 @@ fn convert_to_proto_stream(
                 );
             }
         }
-        let response = proto::SubscribeResponse { entries };
+        let response = proto::SubscribeResponse {
+            entries,
+            token_expiry_warning: None,
+        };
         Ok(response)
     })
 }
@@ -921,11 +1746,37 @@ fn convert_to_proto_stream_id(
                 entries.insert(update.id, dp);
             }
         }
-        let response = proto::SubscribeByIdResponse { entries };
+        let response = proto::SubscribeByIdResponse {
+            entries,
+            token_expiry_warning: None,
+        };
         Ok(response)
     })
 }
 
+fn convert_to_signal_updates_stream(
+    input: impl Stream<Item = broker::EntryUpdates>,
+) -> impl Stream<Item = proto::OpenConsumerStreamResponse> {
+    input.map(move |item| {
+        let mut entries: HashMap<i32, proto::Datapoint> =
+            HashMap::with_capacity(item.updates.len());
+        for update in item.updates {
+            let update_datapoint: Option<proto::Datapoint> = match update.update.datapoint {
+                Some(datapoint) => datapoint.into(),
+                None => None,
+            };
+            if let Some(dp) = update_datapoint {
+                entries.insert(update.id, dp);
+            }
+        }
+        proto::OpenConsumerStreamResponse {
+            action: Some(open_consumer_stream_response::Action::SignalUpdates(
+                proto::SignalUpdates { entries },
+            )),
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -933,6 +1784,7 @@ mod tests {
     use databroker_proto::kuksa::val::v2::val_server::Val;
     use proto::open_provider_stream_response::Action::{
         BatchActuateStreamRequest, ProvideActuationResponse, PublishValuesResponse,
+        RefreshTokenResponse, RegisterSignalsResponse, StreamOptionsResponse, TokenExpiryWarning,
     };
     use proto::{
         open_provider_stream_request, BatchActuateRequest, OpenProviderStreamRequest,
@@ -2215,6 +3067,18 @@ mod tests {
                                 Some(BatchActuateStreamRequest(_)) => {
                                     panic!("Should not happen")
                                 }
+                                Some(RegisterSignalsResponse(_)) => {
+                                    panic!("Should not happen")
+                                }
+                                Some(StreamOptionsResponse(_)) => {
+                                    panic!("Should not happen")
+                                }
+                                Some(RefreshTokenResponse(_)) => {
+                                    panic!("Should not happen")
+                                }
+                                Some(TokenExpiryWarning(_)) => {
+                                    panic!("Should not happen")
+                                }
                                 None => {
                                     panic!("Should not happen")
                                 }
@@ -2232,6 +3096,162 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_open_consumer_stream() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let entry_id_1 = broker::tests::helper_add_int32(&broker, "test.datapoint1", 10, timestamp)
+            .await
+            .expect("Shall succeed");
+        let entry_id_2 = broker::tests::helper_add_int32(&broker, "test.datapoint2", 20, timestamp)
+            .await
+            .expect("Shall succeed");
+
+        let requests = vec![
+            proto::OpenConsumerStreamRequest {
+                action: Some(open_consumer_stream_request::Action::AddSignalsRequest(
+                    proto::AddSignalsRequest {
+                        request_id: 1,
+                        signal_ids: vec![SignalId {
+                            signal: Some(proto::signal_id::Signal::Id(entry_id_1)),
+                        }],
+                        patterns: vec![],
+                    },
+                )),
+            },
+            proto::OpenConsumerStreamRequest {
+                action: Some(open_consumer_stream_request::Action::AddSignalsRequest(
+                    proto::AddSignalsRequest {
+                        request_id: 2,
+                        signal_ids: vec![],
+                        patterns: vec!["test.unknown.*".to_owned()],
+                    },
+                )),
+            },
+            proto::OpenConsumerStreamRequest {
+                action: Some(open_consumer_stream_request::Action::GetValuesRequest(
+                    proto::StreamGetValuesRequest {
+                        request_id: 3,
+                        signal_ids: vec![SignalId {
+                            signal: Some(proto::signal_id::Signal::Path(
+                                "test.datapoint2".to_owned(),
+                            )),
+                        }],
+                    },
+                )),
+            },
+        ];
+
+        // Manually insert permissions
+        let mut streaming_request = tonic_mock::streaming_request(requests);
+        streaming_request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let mut receiver = match broker.open_consumer_stream(streaming_request).await {
+            Ok(response) => response.into_inner().into_inner(),
+            Err(_) => panic!("Should not happen"),
+        };
+
+        async fn next_response(
+            receiver: &mut mpsc::Receiver<Result<proto::OpenConsumerStreamResponse, tonic::Status>>,
+        ) -> open_consumer_stream_response::Action {
+            match tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv()).await {
+                Ok(Some(Ok(response))) => {
+                    response.action.expect("Response should contain an action")
+                }
+                _ => panic!("Expected a response"),
+            }
+        }
+
+        let mut updates = vec![];
+        let mut responses = 0;
+        while responses < 3 || updates.is_empty() {
+            match next_response(&mut receiver).await {
+                open_consumer_stream_response::Action::AddSignalsResponse(response) => {
+                    responses += 1;
+                    match response.request_id {
+                        1 => {
+                            assert_eq!(response.signal_ids, vec![entry_id_1]);
+                            assert!(response.error.is_none());
+                        }
+                        2 => {
+                            assert!(response.signal_ids.is_empty());
+                            assert_eq!(
+                                response.error.expect("Should contain an error").code(),
+                                ErrorCode::NotFound
+                            );
+                        }
+                        _ => panic!("Unexpected request id"),
+                    }
+                }
+                open_consumer_stream_response::Action::GetValuesResponse(response) => {
+                    responses += 1;
+                    assert_eq!(response.request_id, 3);
+                    assert!(response.error.is_none());
+                    assert_eq!(response.data_points.len(), 1);
+                    assert_eq!(
+                        response.data_points[0].value,
+                        Some(Value {
+                            typed_value: Some(proto::value::TypedValue::Int32(20))
+                        })
+                    );
+                }
+                open_consumer_stream_response::Action::SignalUpdates(signal_updates) => {
+                    updates.push(signal_updates);
+                }
+                _ => panic!("Unexpected response"),
+            }
+        }
+
+        // Only the added signal is sent initially
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].entries.len(), 1);
+        assert_eq!(
+            updates[0].entries[&entry_id_1].value,
+            Some(Value {
+                typed_value: Some(proto::value::TypedValue::Int32(10))
+            })
+        );
+
+        let update = |value| broker::EntryUpdate {
+            path: None,
+            datapoint: Some(broker::Datapoint {
+                ts: std::time::SystemTime::now(),
+                source_ts: None,
+                value: DataValue::Int32(value),
+            }),
+            actuator_target: None,
+            entry_type: None,
+            data_type: None,
+            description: None,
+            allowed: None,
+            min: None,
+            max: None,
+            unit: None,
+        };
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .update_entries([(entry_id_1, update(11)), (entry_id_2, update(21))])
+            .await
+            .expect("Shall succeed");
+
+        // The subscription is kept after the consumer closed its request stream
+        match next_response(&mut receiver).await {
+            open_consumer_stream_response::Action::SignalUpdates(signal_updates) => {
+                assert_eq!(signal_updates.entries.len(), 1);
+                assert_eq!(
+                    signal_updates.entries[&entry_id_1].value,
+                    Some(Value {
+                        typed_value: Some(proto::value::TypedValue::Int32(11))
+                    })
+                );
+            }
+            _ => panic!("Unexpected response"),
+        }
+    }
+
     #[tokio::test]
     async fn test_list_metadata_min_max() {
         let broker = DataBroker::default();
@@ -2684,6 +3704,186 @@ mod tests {
         result_response.expect("Result should be Ok");
     }
 
+    #[tokio::test]
+    async fn test_subscribe_actuations() {
+        let broker = DataBroker::default();
+        let authorized_access = broker.authorized_access(&permissions::ALLOW_ALL);
+
+        let vss_id = authorized_access
+            .add_entry(
+                "Vehicle.ADAS.ABS.IsEnabled".to_owned(),
+                broker::DataType::Bool,
+                broker::ChangeType::OnChange,
+                broker::EntryType::Actuator,
+                "Some funny description".to_owned(),
+                None, // min
+                None, // max
+                None,
+                None,
+            )
+            .await
+            .expect("Register datapoint should succeed");
+
+        let mut request = tonic::Request::new(proto::SubscribeActuationsRequest {
+            signal_ids: vec![],
+            patterns: vec!["Vehicle.ADAS.**".to_string()],
+            buffer_size: 0,
+        });
+        request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let mut stream = proto::val_server::Val::subscribe_actuations(&broker, request)
+            .await
+            .expect("Subscribing to actuations should succeed")
+            .into_inner();
+
+        let consumer_permissions = permissions::PermissionBuilder::new()
+            .subject("test-consumer")
+            .add_actuate_permission(permissions::Permission::All)
+            .build()
+            .expect("Building permissions should succeed");
+
+        let mut request = tonic::Request::new(ActuateRequest {
+            signal_id: Some(SignalId {
+                signal: Some(proto::signal_id::Signal::Id(vss_id)),
+            }),
+            value: Some(Value {
+                typed_value: Some(proto::value::TypedValue::Bool(true)),
+            }),
+        });
+        request.extensions_mut().insert(consumer_permissions);
+
+        // No provider registered, the request is still observed
+        let result_response = proto::val_server::Val::actuate(&broker, request).await;
+        assert!(result_response.is_err());
+
+        let response = stream
+            .next()
+            .await
+            .expect("Stream should yield an item")
+            .expect("Item should be Ok");
+        assert_eq!(response.events.len(), 1);
+        let event = &response.events[0];
+        assert_eq!(event.signal_id, vss_id);
+        assert_eq!(event.path, "Vehicle.ADAS.ABS.IsEnabled");
+        assert_eq!(event.requester, "test-consumer");
+        assert_eq!(
+            event.value,
+            Some(Value {
+                typed_value: Some(proto::value::TypedValue::Bool(true)),
+            })
+        );
+        assert_eq!(
+            event.error.as_ref().expect("Error should be set").code(),
+            ErrorCode::Unavailable
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_status() {
+        let broker = DataBroker::default();
+        let authorized_access = broker.authorized_access(&permissions::ALLOW_ALL);
+
+        let vss_id = authorized_access
+            .add_entry(
+                "Vehicle.ADAS.ABS.IsEnabled".to_owned(),
+                broker::DataType::Bool,
+                broker::ChangeType::OnChange,
+                broker::EntryType::Actuator,
+                "Some funny description".to_owned(),
+                None, // min
+                None, // max
+                None,
+                None,
+            )
+            .await
+            .expect("Register datapoint should succeed");
+
+        async fn list_metadata_provider_status(broker: &DataBroker) -> proto::ProviderStatus {
+            let mut request = tonic::Request::new(proto::ListMetadataRequest {
+                root: "Vehicle.ADAS.ABS.IsEnabled".to_owned(),
+                filter: "".to_owned(),
+            });
+            request
+                .extensions_mut()
+                .insert(permissions::ALLOW_ALL.clone());
+            let response = broker
+                .list_metadata(request)
+                .await
+                .expect("Listing metadata should succeed")
+                .into_inner();
+            assert_eq!(response.metadata.len(), 1);
+            response.metadata[0].provider_status()
+        }
+
+        async fn next_status(
+            stream: &mut <DataBroker as Val>::SubscribeProviderStatusStream,
+            vss_id: i32,
+        ) -> proto::ProviderStatus {
+            let response = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+                .await
+                .expect("Status should be received")
+                .expect("Stream should yield an item")
+                .expect("Item should be Ok");
+            assert_eq!(response.statuses.len(), 1);
+            proto::ProviderStatus::try_from(response.statuses[&vss_id])
+                .expect("Status should be valid")
+        }
+
+        let mut request = tonic::Request::new(proto::SubscribeProviderStatusRequest {
+            signal_ids: vec![],
+            patterns: vec!["Vehicle.ADAS.**".to_string()],
+            buffer_size: 0,
+        });
+        request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+        let mut stream = broker
+            .subscribe_provider_status(request)
+            .await
+            .expect("Subscribing to provider status should succeed")
+            .into_inner();
+
+        assert_eq!(
+            next_status(&mut stream, vss_id).await,
+            proto::ProviderStatus::Unavailable
+        );
+        assert_eq!(
+            list_metadata_provider_status(&broker).await,
+            proto::ProviderStatus::Unavailable
+        );
+
+        let (sender, receiver) = mpsc::channel(10);
+        let actuation_provider = Provider { sender };
+        authorized_access
+            .provide_actuation(vec![vss_id], Box::new(actuation_provider))
+            .await
+            .expect("Registering a new Actuation Provider should succeed");
+
+        assert_eq!(
+            next_status(&mut stream, vss_id).await,
+            proto::ProviderStatus::Available
+        );
+        assert_eq!(
+            list_metadata_provider_status(&broker).await,
+            proto::ProviderStatus::Available
+        );
+
+        // Provider gone, detected by the housekeeping task
+        drop(receiver);
+        broker.start_housekeeping_task();
+
+        assert_eq!(
+            next_status(&mut stream, vss_id).await,
+            proto::ProviderStatus::Unavailable
+        );
+        assert_eq!(
+            list_metadata_provider_status(&broker).await,
+            proto::ProviderStatus::Unavailable
+        );
+    }
+
     #[tokio::test]
     async fn test_batch_actuate_out_of_range() {
         let broker = DataBroker::default();
@@ -3132,6 +4332,535 @@ mod tests {
         }
     }
 
+    fn signal_metadata(path: &str, data_type: proto::DataType) -> proto::Metadata {
+        proto::Metadata {
+            path: path.to_owned(),
+            id: 0,
+            data_type: data_type as i32,
+            entry_type: proto::EntryType::Actuator as i32,
+            description: "Registered at runtime".to_owned(),
+            comment: "".to_owned(),
+            deprecation: "".to_owned(),
+            unit: "km/h".to_owned(),
+            allowed_values: None,
+            min: Some(Value {
+                typed_value: Some(proto::value::TypedValue::Int32(0)),
+            }),
+            max: Some(Value {
+                typed_value: Some(proto::value::TypedValue::Int32(100)),
+            }),
+            provider_status: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_signals() {
+        let broker = DataBroker::default();
+
+        let mut request = tonic::Request::new(proto::RegisterSignalsRequest {
+            request_id: 1,
+            signals: vec![signal_metadata(
+                "Vehicle.Private.Speed",
+                proto::DataType::Int32,
+            )],
+        });
+        request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let response = broker
+            .register_signals(request)
+            .await
+            .expect("Registering signals should succeed")
+            .into_inner();
+        let id = response.signal_ids["Vehicle.Private.Speed"];
+
+        let metadata = broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .get_metadata(id)
+            .await
+            .expect("Registered signal should exist");
+        assert_eq!(metadata.path, "Vehicle.Private.Speed");
+        assert_eq!(metadata.data_type, broker::DataType::Int32);
+        assert_eq!(metadata.entry_type, broker::EntryType::Actuator);
+        assert_eq!(metadata.change_type, broker::ChangeType::OnChange);
+        assert_eq!(metadata.unit, Some("km/h".to_owned()));
+        assert_eq!(metadata.max, Some(DataValue::Int32(100)));
+    }
+
+    #[tokio::test]
+    async fn test_register_signals_rolled_back() {
+        let broker = DataBroker::default();
+
+        // Allowed values not matching the data type fail to be added
+        let mut invalid = signal_metadata("Vehicle.Private.Gear", proto::DataType::Int32);
+        invalid.allowed_values = Some(Value {
+            typed_value: Some(proto::value::TypedValue::StringArray(proto::StringArray {
+                values: vec!["P".to_owned(), "D".to_owned()],
+            })),
+        });
+        let mut request = tonic::Request::new(proto::RegisterSignalsRequest {
+            request_id: 1,
+            signals: vec![
+                signal_metadata("Vehicle.Private.Speed", proto::DataType::Int32),
+                invalid,
+            ],
+        });
+        request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let result = broker.register_signals(request).await;
+        assert_eq!(
+            result.expect_err("Should fail").code(),
+            tonic::Code::InvalidArgument
+        );
+
+        // The signals added before the failing one are removed again
+        assert!(broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .get_id_by_path("Vehicle.Private.Speed")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_register_signals_permission_denied() {
+        let broker = DataBroker::default();
+
+        let permissions = permissions::PermissionBuilder::new()
+            .add_create_permission(permissions::Permission::Glob(
+                "Vehicle.Private.*".to_owned(),
+            ))
+            .build()
+            .expect("Building permissions should succeed");
+
+        let mut request = tonic::Request::new(proto::RegisterSignalsRequest {
+            request_id: 1,
+            signals: vec![
+                signal_metadata("Vehicle.Private.Speed", proto::DataType::Int32),
+                signal_metadata("Vehicle.Speed", proto::DataType::Int32),
+            ],
+        });
+        request.extensions_mut().insert(permissions);
+
+        let result = broker.register_signals(request).await;
+        assert_eq!(
+            result.expect_err("Should fail").code(),
+            tonic::Code::PermissionDenied
+        );
+
+        // Nothing registered if any signal is denied
+        assert!(broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .get_id_by_path("Vehicle.Private.Speed")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_register_signals_invalid_argument() {
+        let broker = DataBroker::default();
+
+        for metadata in [
+            signal_metadata("Vehicle.Private.Speed", proto::DataType::Unspecified),
+            signal_metadata("Vehicle.Private.*", proto::DataType::Int32),
+        ] {
+            let mut request = tonic::Request::new(proto::RegisterSignalsRequest {
+                request_id: 1,
+                signals: vec![metadata],
+            });
+            request
+                .extensions_mut()
+                .insert(permissions::ALLOW_ALL.clone());
+
+            let result = broker.register_signals(request).await;
+            assert_eq!(
+                result.expect_err("Should fail").code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_provider_stream_register_signals() {
+        let broker = DataBroker::default();
+
+        let request = OpenProviderStreamRequest {
+            action: Some(
+                open_provider_stream_request::Action::RegisterSignalsRequest(
+                    proto::RegisterSignalsRequest {
+                        request_id: 5,
+                        signals: vec![signal_metadata(
+                            "Vehicle.Private.Speed",
+                            proto::DataType::Int32,
+                        )],
+                    },
+                ),
+            ),
+        };
+
+        let mut streaming_request = tonic_mock::streaming_request(vec![request]);
+        streaming_request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let mut receiver = broker
+            .open_provider_stream(streaming_request)
+            .await
+            .expect("Opening the stream should succeed")
+            .into_inner()
+            .into_inner();
+
+        match receiver.recv().await {
+            Some(Ok(OpenProviderStreamResponse {
+                action:
+                    Some(open_provider_stream_response::Action::RegisterSignalsResponse(response)),
+            })) => {
+                assert_eq!(response.request_id, 5);
+                assert!(response.signal_ids.contains_key("Vehicle.Private.Speed"));
+            }
+            _ => panic!("Expected a register signals response"),
+        }
+    }
+
+    fn publish_values_request(
+        request_id: i32,
+        id: i32,
+        value: proto::Value,
+    ) -> OpenProviderStreamRequest {
+        OpenProviderStreamRequest {
+            action: Some(open_provider_stream_request::Action::PublishValuesRequest(
+                PublishValuesRequest {
+                    request_id,
+                    data_points: HashMap::from([(
+                        id,
+                        proto::Datapoint {
+                            timestamp: None,
+                            value: Some(value),
+                        },
+                    )]),
+                },
+            )),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_provider_stream_acknowledge_publish_values() {
+        let broker = DataBroker::default();
+        let timestamp = std::time::SystemTime::now();
+
+        let entry_id = broker::tests::helper_add_int32(&broker, "test.datapoint1", 10, timestamp)
+            .await
+            .expect("Shall succeed");
+
+        let options_request = OpenProviderStreamRequest {
+            action: Some(open_provider_stream_request::Action::StreamOptionsRequest(
+                proto::StreamOptionsRequest {
+                    acknowledge_publish_values: true,
+                },
+            )),
+        };
+        let int_value = proto::Value {
+            typed_value: Some(proto::value::TypedValue::Int32(20)),
+        };
+        let bool_value = proto::Value {
+            typed_value: Some(proto::value::TypedValue::Bool(true)),
+        };
+
+        let mut streaming_request = tonic_mock::streaming_request(vec![
+            options_request.clone(),
+            publish_values_request(1, entry_id, int_value),
+            publish_values_request(2, entry_id, bool_value),
+            // Options can only be negotiated once
+            options_request,
+        ]);
+        streaming_request
+            .extensions_mut()
+            .insert(permissions::ALLOW_ALL.clone());
+
+        let mut receiver = broker
+            .open_provider_stream(streaming_request)
+            .await
+            .expect("Opening the stream should succeed")
+            .into_inner()
+            .into_inner();
+
+        match receiver.recv().await {
+            Some(Ok(OpenProviderStreamResponse {
+                action: Some(StreamOptionsResponse(response)),
+            })) => {
+                assert!(response.acknowledge_publish_values);
+            }
+            _ => panic!("Expected a stream options response"),
+        }
+
+        // Successful publish is acknowledged with an empty status
+        match receiver.recv().await {
+            Some(Ok(OpenProviderStreamResponse {
+                action: Some(PublishValuesResponse(response)),
+            })) => {
+                assert_eq!(response.request_id, 1);
+                assert!(response.status.is_empty());
+            }
+            _ => panic!("Expected a publish values response"),
+        }
+
+        match receiver.recv().await {
+            Some(Ok(OpenProviderStreamResponse {
+                action: Some(PublishValuesResponse(response)),
+            })) => {
+                assert_eq!(response.request_id, 2);
+                assert_eq!(
+                    response.status.get(&entry_id).map(|error| error.code()),
+                    Some(proto::ErrorCode::InvalidArgument)
+                );
+            }
+            _ => panic!("Expected a publish values response"),
+        }
+
+        match receiver.recv().await {
+            Some(Err(status)) => {
+                assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+            }
+            _ => panic!("Expected the stream options request to fail"),
+        }
+    }
+
+    fn signed_token(subject: &str, scope: &str, expires_at: u64) -> String {
+        let claims = serde_json::json!({
+            "sub": subject,
+            "iss": "test",
+            "aud": ["kuksa.val"],
+            "iat": 1516239022,
+            "exp": expires_at,
+            "scope": scope,
+        });
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!(
+            "../../../../certificates/jwt/jwt.key"
+        ))
+        .expect("key should be valid");
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            &claims,
+            &key,
+        )
+        .expect("encoding should succeed")
+    }
+
+    fn refresh_token_request(request_id: i32, token: String) -> OpenProviderStreamRequest {
+        OpenProviderStreamRequest {
+            action: Some(open_provider_stream_request::Action::RefreshTokenRequest(
+                proto::RefreshTokenRequest { request_id, token },
+            )),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_provider_stream_refresh_token() {
+        let broker = DataBroker::default();
+        let authorized_access = broker.authorized_access(&permissions::ALLOW_ALL);
+        authorized_access
+            .add_entry(
+                "Vehicle.Cabin.Light".to_owned(),
+                broker::DataType::Bool,
+                broker::ChangeType::OnChange,
+                broker::EntryType::Actuator,
+                "Test actuator".to_owned(),
+                None, // min
+                None, // max
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let authorization =
+            Authorization::new(include_str!("../../../../certificates/jwt/jwt.key.pub").to_owned())
+                .expect("public key should be valid");
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let refreshed_expiration = now + 7200;
+
+        let permissions = authorization
+            .permissions_from_token(&signed_token(
+                "provider",
+                "actuate:Vehicle.Cabin.Light",
+                now + 3600,
+            ))
+            .expect("token should be valid");
+
+        let mut streaming_request = tonic_mock::streaming_request(vec![
+            OpenProviderStreamRequest {
+                action: Some(
+                    open_provider_stream_request::Action::ProvideActuationRequest(
+                        proto::ProvideActuationRequest {
+                            actuator_identifiers: vec![SignalId {
+                                signal: Some(proto::signal_id::Signal::Path(
+                                    "Vehicle.Cabin.Light".to_string(),
+                                )),
+                            }],
+                        },
+                    ),
+                ),
+            },
+            refresh_token_request(
+                1,
+                signed_token(
+                    "provider",
+                    "actuate:Vehicle.Cabin.Light",
+                    refreshed_expiration,
+                ),
+            ),
+            refresh_token_request(
+                2,
+                signed_token("other", "actuate:Vehicle.Cabin.Light", refreshed_expiration),
+            ),
+            // No longer permitted to provide the actuator
+            refresh_token_request(
+                3,
+                signed_token("provider", "read:Vehicle.Cabin.Light", refreshed_expiration),
+            ),
+            refresh_token_request(4, "invalid".to_owned()),
+        ]);
+        streaming_request.extensions_mut().insert(permissions);
+        streaming_request.extensions_mut().insert(authorization);
+
+        let mut receiver = broker
+            .open_provider_stream(streaming_request)
+            .await
+            .expect("Opening the stream should succeed")
+            .into_inner()
+            .into_inner();
+
+        match receiver.recv().await {
+            Some(Ok(OpenProviderStreamResponse {
+                action: Some(ProvideActuationResponse(_)),
+            })) => {}
+            _ => panic!("Expected a provide actuation response"),
+        }
+
+        let expected = [
+            (1, None),
+            (2, Some(proto::ErrorCode::PermissionDenied)),
+            (3, Some(proto::ErrorCode::PermissionDenied)),
+            (4, Some(proto::ErrorCode::Unauthenticated)),
+        ];
+        for (request_id, error_code) in expected {
+            match receiver.recv().await {
+                Some(Ok(OpenProviderStreamResponse {
+                    action: Some(RefreshTokenResponse(response)),
+                })) => {
+                    assert_eq!(response.request_id, request_id);
+                    assert_eq!(
+                        response.error.as_ref().map(|error| error.code()),
+                        error_code
+                    );
+                    if error_code.is_none() {
+                        assert_eq!(
+                            response.expires_at.map(|expires_at| expires_at.seconds),
+                            Some(refreshed_expiration as i64)
+                        );
+                    }
+                }
+                _ => panic!("Expected a refresh token response"),
+            }
+        }
+    }
+
+    fn expiring_permissions(expires_in: std::time::Duration) -> Permissions {
+        Permissions::builder()
+            .add_read_permission(permissions::Permission::All)
+            .expires_at(std::time::SystemTime::now() + expires_in)
+            .build()
+            .expect("permissions should build")
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_permissions_expired() {
+        let broker = DataBroker::default();
+        broker::tests::helper_add_int32(
+            &broker,
+            "test.datapoint1",
+            10,
+            std::time::SystemTime::now(),
+        )
+        .await
+        .expect("Shall succeed");
+
+        let mut request = tonic::Request::new(proto::SubscribeRequest {
+            signal_paths: vec!["test.datapoint1".to_string()],
+            buffer_size: 5,
+        });
+        request
+            .extensions_mut()
+            .insert(expiring_permissions(std::time::Duration::from_secs(1)));
+
+        let mut stream = broker
+            .subscribe(request)
+            .await
+            .expect("subscribe should succeed")
+            .into_inner();
+
+        // Expiring within the warning period, so the warning is sent
+        // immediately. The stream ends with an error once the permissions
+        // have expired.
+        let (mut values, mut warnings) = (0, 0);
+        loop {
+            match tokio::time::timeout(std::time::Duration::from_secs(5), stream.next()).await {
+                Ok(Some(Ok(response))) => match response.token_expiry_warning {
+                    Some(warning) => {
+                        assert!(response.entries.is_empty());
+                        assert!(warning.expires_at.is_some());
+                        warnings += 1;
+                    }
+                    None => values += 1,
+                },
+                Ok(Some(Err(status))) => {
+                    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+                    break;
+                }
+                _ => panic!("Expected the stream to end with an error"),
+            }
+        }
+        assert_eq!((values, warnings), (1, 1));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_consumer_stream_permissions_expired() {
+        let broker = DataBroker::default();
+
+        let mut streaming_request =
+            tonic_mock::streaming_request(Vec::<proto::OpenConsumerStreamRequest>::new());
+        streaming_request
+            .extensions_mut()
+            .insert(expiring_permissions(std::time::Duration::from_secs(1)));
+
+        let mut receiver = broker
+            .open_consumer_stream(streaming_request)
+            .await
+            .expect("Opening the stream should succeed")
+            .into_inner()
+            .into_inner();
+
+        // Expiring within the warning period, so the warning is sent immediately
+        match receiver.recv().await {
+            Some(Ok(proto::OpenConsumerStreamResponse {
+                action: Some(open_consumer_stream_response::Action::TokenExpiryWarning(warning)),
+            })) => assert!(warning.expires_at.is_some()),
+            _ => panic!("Expected a token expiry warning"),
+        }
+
+        match tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv()).await {
+            Ok(Some(Err(status))) => assert_eq!(status.code(), tonic::Code::Unauthenticated),
+            _ => panic!("Expected the stream to end with an error"),
+        }
+        assert!(receiver.recv().await.is_none());
+    }
+
     #[tokio::test]
     async fn test_get_server_info() {
         let version = "1.1.1";
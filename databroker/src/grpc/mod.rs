@@ -11,8 +11,33 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
+use std::time::SystemTime;
+
+use futures::Stream;
+use tokio_stream::StreamExt;
+
+use crate::permissions::{self, Expiring, ExpiryEvent, Permissions};
+
 pub mod server;
 
 mod kuksa_val_v1;
 mod kuksa_val_v2;
 mod sdv_databroker_v1;
+
+// Terminate a response stream with UNAUTHENTICATED once the permissions
+// used to set it up have expired. Shortly before, the response `warning`
+// returns for the time of expiry is sent, if the responses of the stream
+// have a field to warn the client with.
+fn terminate_on_expiry<T>(
+    stream: impl Stream<Item = Result<T, tonic::Status>> + Send + 'static,
+    permissions: &Permissions,
+    warning: impl Fn(SystemTime) -> Option<T> + Send + 'static,
+) -> impl Stream<Item = Result<T, tonic::Status>> {
+    permissions::with_expiry(stream, permissions).filter_map(move |item| match item {
+        Expiring::Item(item) => Some(item),
+        Expiring::Event(ExpiryEvent::ExpiresSoon(expires_at)) => warning(expires_at).map(Ok),
+        Expiring::Event(ExpiryEvent::Expired) => {
+            Some(Err(tonic::Status::unauthenticated("Permissions expired")))
+        }
+    })
+}
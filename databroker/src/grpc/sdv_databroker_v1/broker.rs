@@ -20,7 +20,9 @@ use tokio_stream::{Stream, StreamExt};
 use std::collections::HashMap;
 use std::pin::Pin;
 
-use crate::broker::{self, ReadError};
+use crate::audit;
+use crate::broker::{self, QueryError, ReadError};
+use crate::grpc::terminate_on_expiry;
 use crate::permissions::Permissions;
 
 use tracing::debug;
@@ -97,7 +99,9 @@ impl proto::broker_server::Broker for broker::DataBroker {
             }
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::SdvDatabrokerV1);
 
         // Collect errors encountered
         let mut errors = HashMap::<String, i32>::new();
@@ -185,9 +189,19 @@ impl proto::broker_server::Broker for broker::DataBroker {
         match broker.subscribe_query(&query).await {
             Ok(stream) => {
                 let stream = convert_to_proto_stream(stream);
+                let stream = terminate_on_expiry(stream, &permissions, |expires_at| {
+                    Some(proto::SubscribeReply {
+                        fields: HashMap::new(),
+                        token_expires_at: Some(expires_at.into()),
+                    })
+                });
                 debug!("Subscribed to new query");
                 Ok(Response::new(Box::pin(stream)))
             }
+            Err(QueryError::PermissionDenied(path)) => Err(Status::new(
+                Code::PermissionDenied,
+                format!("Permission denied for {path}"),
+            )),
             Err(e) => Err(Status::new(Code::InvalidArgument, format!("{e:?}"))),
         }
     }
@@ -211,7 +225,12 @@ impl proto::broker_server::Broker for broker::DataBroker {
 
         let list = if request.names.is_empty() {
             broker
-                .map_entries(|entry| proto::Metadata::from(entry.metadata()))
+                .filter_map_entries(|entry| {
+                    permissions
+                        .can_read_metadata(&entry.metadata().path)
+                        .ok()
+                        .map(|()| proto::Metadata::from(entry.metadata()))
+                })
                 .await
         } else {
             broker
@@ -220,6 +239,7 @@ impl proto::broker_server::Broker for broker::DataBroker {
                         .names
                         .iter()
                         .filter_map(|name| db.get_metadata_by_path(name))
+                        .filter(|metadata| permissions.can_read_metadata(&metadata.path).is_ok())
                         .map(proto::Metadata::from)
                         .collect::<Vec<proto::Metadata>>()
                 })
@@ -240,7 +260,10 @@ fn convert_to_proto_stream(
             let value = proto::Datapoint::from(&field);
             datapoints.insert(field.name, value);
         }
-        let notification = proto::SubscribeReply { fields: datapoints };
+        let notification = proto::SubscribeReply {
+            fields: datapoints,
+            token_expires_at: None,
+        };
         Ok(notification)
     })
 }
@@ -22,6 +22,7 @@ use tonic::{Code, Response, Status};
 use tracing::debug;
 
 use crate::{
+    audit,
     broker::{self, RegistrationError},
     permissions::Permissions,
 };
@@ -40,7 +41,9 @@ impl proto::collector_server::Collector for broker::DataBroker {
             }
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::SdvDatabrokerV1);
 
         // Collect errors encountered
         let mut errors = HashMap::new();
@@ -110,7 +113,9 @@ impl proto::collector_server::Collector for broker::DataBroker {
         // Listening on stream
         tokio::spawn(async move {
             let permissions = permissions;
-            let broker = broker.authorized_access(&permissions);
+            let broker = broker
+                .authorized_access(&permissions)
+                .with_api(audit::Api::SdvDatabrokerV1);
             loop {
                 select! {
                     message = stream.message() => {
@@ -193,7 +198,9 @@ impl proto::collector_server::Collector for broker::DataBroker {
             }
             None => return Err(tonic::Status::unauthenticated("Unauthenticated")),
         };
-        let broker = self.authorized_access(&permissions);
+        let broker = self
+            .authorized_access(&permissions)
+            .with_api(audit::Api::SdvDatabrokerV1);
 
         let mut results = HashMap::new();
         let mut error = None;
@@ -11,7 +11,7 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
-use std::{convert::TryFrom, future::Future};
+use std::future::Future;
 
 use futures::Stream;
 use socket2::{Domain, Protocol, Socket, Type};
@@ -22,13 +22,17 @@ use tokio::{
 use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
 #[cfg(feature = "tls")]
 use tonic::transport::ServerTlsConfig;
-use tonic::transport::{server::Connected, Server};
+use tonic::transport::{
+    server::{Connected, UdsConnectInfo},
+    Server,
+};
 use tracing::{debug, info};
 
 use databroker_proto::{kuksa, sdv};
 
 use crate::{
-    authorization::Authorization,
+    audit,
+    authorization::{self, Authorization},
     broker,
     permissions::{self, Permissions},
 };
@@ -49,6 +53,28 @@ pub enum Api {
     SdvDatabrokerV1,
 }
 
+// Authorizes the requests to one of the APIs, recording rejected credentials
+// in the audit log of the broker
+#[derive(Clone)]
+struct AuthorizationInterceptor {
+    authorization: Authorization,
+    api: audit::Api,
+    broker: broker::DataBroker,
+}
+
+impl tonic::service::Interceptor for AuthorizationInterceptor {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        self.authorization.call(request).inspect_err(|status| {
+            if let Some(audit_log) = self.broker.audit_log() {
+                audit_log.record(
+                    audit::Record::new(audit::Event::Authorization, Some(self.api))
+                        .error(status.message()),
+                );
+            }
+        })
+    }
+}
+
 impl tonic::service::Interceptor for Authorization {
     fn call(
         &mut self,
@@ -59,40 +85,82 @@ impl tonic::service::Interceptor for Authorization {
                 request
                     .extensions_mut()
                     .insert(permissions::ALLOW_ALL.clone());
+                // Made available to validate tokens refreshed on long-lived streams
+                request.extensions_mut().insert(self.clone());
                 Ok(request)
             }
-            Authorization::Enabled { token_decoder } => {
-                match request.metadata().get("authorization") {
+            Authorization::Enabled { .. } => {
+                let permissions = match request.metadata().get("authorization") {
                     Some(header) => match header.to_str() {
                         Ok(header) if header.starts_with("Bearer ") => {
                             let token: &str = header[7..].into();
-                            match token_decoder.decode(token) {
-                                Ok(claims) => match Permissions::try_from(claims) {
-                                    Ok(permissions) => {
-                                        request.extensions_mut().insert(permissions);
-                                        Ok(request)
-                                    }
-                                    Err(err) => Err(tonic::Status::unauthenticated(format!(
-                                        "Invalid auth token: {err}"
-                                    ))),
-                                },
-                                Err(err) => Err(tonic::Status::unauthenticated(format!(
-                                    "Invalid auth token: {err}"
-                                ))),
-                            }
+                            self.permissions_from_token(token)
+                                .map_err(|err| tonic::Status::unauthenticated(err.to_string()))?
+                        }
+                        Ok(_) | Err(_) => {
+                            return Err(tonic::Status::unauthenticated("Invalid auth token"))
                         }
-                        Ok(_) | Err(_) => Err(tonic::Status::unauthenticated("Invalid auth token")),
                     },
-                    None => {
-                        debug!("No auth token provided");
-                        Err(tonic::Status::unauthenticated("No auth token provided"))
-                    }
-                }
+                    None => match self.permissions_from_peer(&request) {
+                        Some(permissions) => permissions
+                            .map_err(|err| tonic::Status::unauthenticated(err.to_string()))?,
+                        None => {
+                            debug!("No auth token provided");
+                            return Err(tonic::Status::unauthenticated("No auth token provided"));
+                        }
+                    },
+                };
+                request.extensions_mut().insert(permissions);
+                // Made available to validate tokens refreshed on long-lived streams
+                request.extensions_mut().insert(self.clone());
+                Ok(request)
             }
         }
     }
 }
 
+impl Authorization {
+    // Resolve the permissions of a client that did not provide an access token
+    // by the identity of its connection, i.e. its TLS client certificate or the
+    // credentials of its process if connected via unix socket. None if there is
+    // no policy or the connection does not identify the client.
+    fn permissions_from_peer(
+        &self,
+        request: &tonic::Request<()>,
+    ) -> Option<Result<Permissions, authorization::Error>> {
+        let Authorization::Enabled {
+            policy: Some(_), ..
+        } = self
+        else {
+            return None;
+        };
+
+        #[cfg(feature = "tls")]
+        if let Some(certificate) = request
+            .peer_certs()
+            .and_then(|certificates| certificates.first().cloned())
+        {
+            return Some(self.permissions_from_certificate(certificate.get_ref()));
+        }
+
+        if let Some(peer_cred) = request
+            .extensions()
+            .get::<UdsConnectInfo>()
+            .and_then(|connect_info| connect_info.peer_cred)
+        {
+            debug!(
+                "Unix socket peer: uid {}, gid {}, pid {:?}",
+                peer_cred.uid(),
+                peer_cred.gid(),
+                peer_cred.pid()
+            );
+            return Some(self.permissions_from_peer_credentials(peer_cred.uid(), peer_cred.gid()));
+        }
+
+        None
+    }
+}
+
 async fn shutdown<F>(databroker: broker::DataBroker, signal: F)
 where
     F: Future<Output = ()>,
@@ -229,7 +297,11 @@ where
         if apis.contains(&Api::KuksaValV1) {
             Some(kuksa::val::v1::val_server::ValServer::with_interceptor(
                 broker.clone(),
-                authorization.clone(),
+                AuthorizationInterceptor {
+                    authorization: authorization.clone(),
+                    api: audit::Api::KuksaValV1,
+                    broker: broker.clone(),
+                },
             ))
         } else {
             None
@@ -247,7 +319,11 @@ where
         router = router.add_optional_service(Some(
             kuksa::val::v2::val_server::ValServer::with_interceptor(
                 broker.clone(),
-                authorization.clone(),
+                AuthorizationInterceptor {
+                    authorization: authorization.clone(),
+                    api: audit::Api::KuksaValV2,
+                    broker: broker.clone(),
+                },
             ),
         ));
     }
@@ -259,13 +335,21 @@ where
         router = router.add_optional_service(Some(
             sdv::databroker::v1::broker_server::BrokerServer::with_interceptor(
                 broker.clone(),
-                authorization.clone(),
+                AuthorizationInterceptor {
+                    authorization: authorization.clone(),
+                    api: audit::Api::SdvDatabrokerV1,
+                    broker: broker.clone(),
+                },
             ),
         ));
         router = router.add_optional_service(Some(
             sdv::databroker::v1::collector_server::CollectorServer::with_interceptor(
                 broker.clone(),
-                authorization,
+                AuthorizationInterceptor {
+                    authorization,
+                    api: audit::Api::SdvDatabrokerV1,
+                    broker: broker.clone(),
+                },
             ),
         ));
     }
@@ -279,3 +363,50 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use tonic::service::Interceptor;
+
+    use super::*;
+    use crate::authorization::policy::Policy;
+
+    #[tokio::test]
+    async fn test_authenticate_unix_socket_peer() {
+        let (stream, _) = tokio::net::UnixStream::pair().unwrap();
+        let peer_cred = stream.peer_cred().unwrap();
+        let uds_request = || {
+            let mut request = tonic::Request::new(());
+            request.extensions_mut().insert(UdsConnectInfo {
+                peer_addr: None,
+                peer_cred: Some(peer_cred),
+            });
+            request
+        };
+
+        let policy = Policy::from_json(&format!(
+            r#"{{"clients": [{{"name": "service", "uids": [{}], "read": ["Vehicle.Speed"]}}]}}"#,
+            peer_cred.uid()
+        ))
+        .unwrap();
+        let mut authorization = Authorization::Enabled {
+            token_decoder: None,
+            policy: Some(policy),
+        };
+        let request = authorization
+            .call(uds_request())
+            .expect("peer should be authorized");
+        let permissions = request.extensions().get::<Permissions>().unwrap();
+        assert_eq!(permissions.subject(), Some("service"));
+        assert!(permissions.can_read("Vehicle.Speed").is_ok());
+        assert!(permissions.can_read("Vehicle.Width").is_err());
+
+        let policy = Policy::from_json(r#"{"clients": []}"#).unwrap();
+        let mut authorization = Authorization::Enabled {
+            token_decoder: None,
+            policy: Some(policy),
+        };
+        let status = authorization.call(uds_request()).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+}
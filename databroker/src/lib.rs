@@ -11,6 +11,7 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
+pub mod audit;
 pub mod authorization;
 pub mod broker;
 pub mod glob;
@@ -18,12 +19,16 @@ pub mod grpc;
 pub mod open_telemetry;
 pub mod permissions;
 pub mod query;
+pub mod transformation;
 pub mod types;
 pub mod vss;
 
 #[cfg(feature = "viss")]
 pub mod viss;
 
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
 use std::fmt::Write;
 
 use tracing::info;
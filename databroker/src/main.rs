@@ -16,12 +16,15 @@
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 static DEFAULT_UNIX_SOCKET_PATH: &str = "/run/kuksa/databroker.sock";
+// How often the JWKS and authorization policy files are checked for changes
+static RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
 
 use std::io;
 use std::os::unix::fs::FileTypeExt;
 use std::path::Path;
 
-use databroker::authorization::Authorization;
+use databroker::audit::AuditLog;
+use databroker::authorization::{jwt, policy::Policy, Authorization};
 use databroker::broker::RegistrationError;
 
 #[cfg(feature = "tls")]
@@ -35,7 +38,10 @@ use tracing::{debug, error, info, warn};
 
 #[cfg(feature = "viss")]
 use databroker::viss;
-use databroker::{broker, grpc, permissions, vss};
+
+#[cfg(feature = "mqtt")]
+use databroker::mqtt;
+use databroker::{broker, glob, grpc, permissions, vss};
 
 async fn shutdown_handler() {
     let mut sigint =
@@ -120,9 +126,15 @@ async fn read_metadata_file(
     info!("Populating metadata from file '{}'", path);
     let metadata_file = std::fs::OpenOptions::new().read(true).open(filename)?;
     let buffered = std::io::BufReader::new(metadata_file);
-    let entries = vss::parse_vss_from_reader(buffered)?;
+    let tree = vss::parse_vss_from_reader(buffered)?;
 
-    for (path, entry) in entries {
+    for (path, description) in tree.branches {
+        if let Err(err) = database.add_branch(path.clone(), description).await {
+            error!("Failed to add branch {path}: {err:?}")
+        }
+    }
+
+    for (path, entry) in tree.entries {
         debug!("Adding VSS datapoint {}", path);
 
         match database
@@ -286,6 +298,88 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Disable authorization")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("jwks-file")
+                .display_order(8)
+                .long("jwks-file")
+                .help("JSON Web Key Set with the public keys used to verify JWT access tokens, reloaded when changed")
+                .action(ArgAction::Set)
+                .value_name("FILE")
+                .required(false)
+                .conflicts_with("jwt-public-key"),
+        )
+        .arg(
+            Arg::new("jwt-issuer")
+                .display_order(9)
+                .long("jwt-issuer")
+                .help("Accepted (comma-separated) issuers of JWT access tokens, any issuer is accepted if not set")
+                .action(ArgAction::Set)
+                .value_delimiter(',')
+                .value_name("ISSUER")
+                .required(false),
+        )
+        .arg(
+            Arg::new("jwt-audience")
+                .display_order(10)
+                .long("jwt-audience")
+                .help("Accepted (comma-separated) audiences of JWT access tokens")
+                .action(ArgAction::Set)
+                .value_delimiter(',')
+                .value_name("AUDIENCE")
+                .default_value("kuksa.val"),
+        )
+        .arg(
+            Arg::new("authorization-policy")
+                .display_order(11)
+                .long("authorization-policy")
+                .help("Policy file (.json) granting permissions to clients authenticated by API key, TLS client certificate or unix socket peer credentials, reloaded when changed")
+                .action(ArgAction::Set)
+                .value_name("FILE")
+                .required(false),
+        )
+        .arg(
+            Arg::new("audit-log")
+                .display_order(12)
+                .long("audit-log")
+                .help("File to append an audit log of actuations, metadata changes and rejected credentials to")
+                .action(ArgAction::Set)
+                .value_name("FILE")
+                .env("KUKSA_DATABROKER_AUDIT_LOG")
+                .required(false),
+        )
+        .arg(
+            Arg::new("audit-log-max-size")
+                .display_order(13)
+                .long("audit-log-max-size")
+                .help("Size in bytes beyond which the audit log is rotated")
+                .action(ArgAction::Set)
+                .value_name("BYTES")
+                .requires("audit-log")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("10485760"),
+        )
+        .arg(
+            Arg::new("audit-log-max-files")
+                .display_order(14)
+                .long("audit-log-max-files")
+                .help("Number of rotated audit logs to keep")
+                .action(ArgAction::Set)
+                .value_name("NUMBER")
+                .requires("audit-log")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("audit-publish")
+                .display_order(15)
+                .long("audit-publish")
+                .help("Also audit the values published to signals matching these (comma-separated) patterns")
+                .action(ArgAction::Set)
+                .value_delimiter(',')
+                .value_name("PATTERN")
+                .requires("audit-log")
+                .required(false),
+        )
         .arg(
             Arg::new("enable-databroker-v1")
                 .display_order(33)
@@ -331,6 +425,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .action(ArgAction::Set)
                     .value_name("FILE")
                     .conflicts_with("insecure"),
+            )
+            .arg(
+                Arg::new("tls-client-ca")
+                    .display_order(23)
+                    .long("tls-client-ca")
+                    .help("CA certificate file (.pem) used to verify TLS client certificates, which are then optional")
+                    .action(ArgAction::Set)
+                    .value_name("FILE")
+                    .requires("tls-cert")
+                    .conflicts_with("insecure"),
             );
     }
 
@@ -365,6 +469,140 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .env("KUKSA_DATABROKER_VISS_PORT")
                     .value_parser(clap::value_parser!(u16))
                     .default_value("8090"),
+            )
+            .arg(
+                Arg::new("viss-history")
+                    .display_order(35)
+                    .long("viss-history")
+                    .help("Record the history of signals matching these (comma-separated) patterns for VISS history requests. The history of signals subscribed to through VISS is recorded while subscribed")
+                    .action(ArgAction::Set)
+                    .value_delimiter(',')
+                    .value_name("PATTERN")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("viss-history-size")
+                    .display_order(36)
+                    .long("viss-history-size")
+                    .help("How many values of each signal are kept in its history")
+                    .action(ArgAction::Set)
+                    .value_name("SIZE")
+                    .required(false)
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("100"),
+            )
+            .arg(
+                Arg::new("viss-require-actuation-provider")
+                    .display_order(37)
+                    .long("viss-require-actuation-provider")
+                    .help("Fail VISS set requests on actuators without a registered actuation provider, rather than setting their actuator target")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("viss-authorize-connection")
+                    .display_order(38)
+                    .long("viss-authorize-connection")
+                    .help("Authorize VISS websocket connections once, by the token given on upgrade or by a request, rather than every request by its own token")
+                    .action(ArgAction::SetTrue),
+            );
+
+        #[cfg(feature = "tls")]
+        {
+            parser = parser
+                .arg(
+                    Arg::new("viss-tls-cert")
+                        .display_order(33)
+                        .long("viss-tls-cert")
+                        .help("TLS certificate file (.pem) of the VISS server, if argument is not provided, the value of --tls-cert is used")
+                        .action(ArgAction::Set)
+                        .value_name("FILE")
+                        .requires("viss-tls-private-key")
+                        .conflicts_with("insecure"),
+                )
+                .arg(
+                    Arg::new("viss-tls-private-key")
+                        .display_order(34)
+                        .long("viss-tls-private-key")
+                        .help("TLS private key file (.key) of the VISS server, if argument is not provided, the value of --tls-private-key is used")
+                        .action(ArgAction::Set)
+                        .value_name("FILE")
+                        .requires("viss-tls-cert")
+                        .conflicts_with("insecure"),
+                );
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    {
+        parser = parser
+            .arg(
+                Arg::new("enable-mqtt")
+                    .display_order(40)
+                    .long("enable-mqtt")
+                    .help("Enable the bridge to an MQTT broker")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("mqtt-host")
+                    .display_order(41)
+                    .long("mqtt-host")
+                    .help("Host of the MQTT broker")
+                    .action(ArgAction::Set)
+                    .value_name("HOST")
+                    .required(false)
+                    .env("KUKSA_DATABROKER_MQTT_HOST")
+                    .default_value("localhost"),
+            )
+            .arg(
+                Arg::new("mqtt-port")
+                    .display_order(42)
+                    .long("mqtt-port")
+                    .help("Port of the MQTT broker")
+                    .action(ArgAction::Set)
+                    .value_name("PORT")
+                    .required(false)
+                    .env("KUKSA_DATABROKER_MQTT_PORT")
+                    .value_parser(clap::value_parser!(u16))
+                    .default_value("1883"),
+            )
+            .arg(
+                Arg::new("mqtt-client-id")
+                    .display_order(43)
+                    .long("mqtt-client-id")
+                    .help("Client id used to connect to the MQTT broker")
+                    .action(ArgAction::Set)
+                    .value_name("ID")
+                    .required(false)
+                    .default_value("kuksa-databroker"),
+            )
+            .arg(
+                Arg::new("mqtt-topic-prefix")
+                    .display_order(44)
+                    .long("mqtt-topic-prefix")
+                    .help("Prefix of the MQTT topics of the bridge")
+                    .action(ArgAction::Set)
+                    .value_name("PREFIX")
+                    .required(false)
+                    .default_value("kuksa"),
+            )
+            .arg(
+                Arg::new("mqtt-publish")
+                    .display_order(45)
+                    .long("mqtt-publish")
+                    .help("Publish the values of signals matching these (comma-separated) patterns to the MQTT broker")
+                    .action(ArgAction::Set)
+                    .value_delimiter(',')
+                    .value_name("PATTERN")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("mqtt-token-file")
+                    .display_order(46)
+                    .long("mqtt-token-file")
+                    .help("File containing the token (access token or API key) granting the permissions of the MQTT bridge, read again when the token expires")
+                    .action(ArgAction::Set)
+                    .value_name("FILE")
+                    .required(false),
             );
     }
 
@@ -396,6 +634,58 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let addr = std::net::SocketAddr::new(ip_addr, *port);
 
         let broker = broker::DataBroker::new(version, commit_sha);
+        let broker = match args.get_one::<String>("audit-log") {
+            Some(audit_log_filename) => {
+                let publish_patterns = args
+                    .get_many::<String>("audit-publish")
+                    .into_iter()
+                    .flatten()
+                    .map(|pattern| {
+                        glob::Matcher::new(pattern)
+                            .map_err(|_| format!("Invalid audit publish pattern: {pattern}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let audit_log = AuditLog::open(
+                    audit_log_filename,
+                    *args.get_one::<u64>("audit-log-max-size").unwrap(),
+                    *args.get_one::<usize>("audit-log-max-files").unwrap(),
+                )?
+                .publish_patterns(publish_patterns);
+                info!("Writing audit log to '{audit_log_filename}'");
+                broker.with_audit_log(audit_log)
+            }
+            None => broker,
+        };
+        #[cfg(feature = "viss")]
+        let broker = {
+            let history_patterns = args
+                .get_many::<String>("viss-history")
+                .into_iter()
+                .flatten()
+                .map(|pattern| {
+                    glob::Matcher::new(pattern)
+                        .map_err(|_| format!("Invalid VISS history pattern: {pattern}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            broker.with_history(
+                history_patterns,
+                *args.get_one::<usize>("viss-history-size").unwrap(),
+            )
+        };
+        let policy = match args.get_one::<String>("authorization-policy") {
+            Some(policy_filename) => {
+                let policy = Policy::from_file(policy_filename)?;
+                info!("Using '{policy_filename}' to grant permissions to clients");
+                policy.watch_file(policy_filename, RELOAD_INTERVAL);
+                Some(policy)
+            }
+            None => None,
+        };
+
+        let broker = match &policy {
+            Some(policy) => broker.with_delays(policy.delays()),
+            None => broker,
+        };
         let database = broker.authorized_access(&permissions::ALLOW_ALL);
 
         add_kuksa_attribute(
@@ -441,9 +731,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let cert = std::fs::read(cert_file)?;
                     let key = std::fs::read(key_file)?;
                     let identity = tonic::transport::Identity::from_pem(cert, key);
-                    ServerTLS::Enabled {
-                        tls_config: tonic::transport::ServerTlsConfig::new().identity(identity),
-                    }
+                    let tls_config = tonic::transport::ServerTlsConfig::new().identity(identity);
+                    let tls_config = match args.get_one::<String>("tls-client-ca") {
+                        Some(ca_file) => {
+                            let ca = std::fs::read(ca_file)?;
+                            info!("Using '{ca_file}' to verify TLS client certificates");
+                            tls_config
+                                .client_ca_root(tonic::transport::Certificate::from_pem(ca))
+                                .client_auth_optional(true)
+                        }
+                        None => tls_config,
+                    };
+                    ServerTLS::Enabled { tls_config }
                 }
                 (Some(_), None) => {
                     return Err(
@@ -481,13 +780,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             None => Ok(None),
         }?;
 
-        let authorization = match (enable_authorization, jwt_public_key) {
-            (true, Some(pub_key)) => Authorization::new(pub_key)?,
-            (true, None) => {
+        let token_decoder = match (jwt_public_key, args.get_one::<String>("jwks-file")) {
+            (Some(pub_key), _) => Some(jwt::Decoder::new(pub_key)?),
+            (None, Some(jwks_filename)) => {
+                let decoder = jwt::Decoder::from_jwks_file(jwks_filename)?;
+                info!("Using '{jwks_filename}' to authenticate access tokens");
+                decoder.watch_jwks_file(jwks_filename, RELOAD_INTERVAL);
+                Some(decoder)
+            }
+            (None, None) => None,
+        }
+        .map(|decoder| {
+            let decoder = match args.get_many::<String>("jwt-issuer") {
+                Some(issuers) => decoder.issuers(issuers.cloned().collect()),
+                None => decoder,
+            };
+            match args.get_many::<String>("jwt-audience") {
+                Some(audiences) => decoder.audiences(audiences.cloned().collect()),
+                None => decoder,
+            }
+        });
+
+        let authorization = match (enable_authorization, token_decoder, policy) {
+            (true, None, None) => {
                 warn!("Authorization is not enabled.");
                 Authorization::Disabled
             }
-            (false, _) => Authorization::Disabled,
+            (true, token_decoder, policy) => Authorization::Enabled {
+                token_decoder,
+                policy,
+            },
+            (false, _, _) => Authorization::Disabled,
         };
 
         #[cfg(feature = "viss")]
@@ -504,16 +827,96 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let viss_addr = std::net::SocketAddr::new(viss_bind_addr, *viss_port);
 
             if args.get_flag("enable-viss") {
+                // Like gRPC, serve plain websockets if TLS is not configured
+                #[cfg(feature = "tls")]
+                let viss_tls = match (
+                    args.get_one::<String>("viss-tls-cert")
+                        .or(args.get_one::<String>("tls-cert")),
+                    args.get_one::<String>("viss-tls-private-key")
+                        .or(args.get_one::<String>("tls-private-key")),
+                ) {
+                    (Some(cert_file), Some(key_file)) if !args.get_flag("insecure") => {
+                        let cert = std::fs::read(cert_file)?;
+                        let key = std::fs::read(key_file)?;
+                        viss::server::ServerTLS::Enabled {
+                            tls_config: std::sync::Arc::new(viss::server::tls_config(&cert, &key)?),
+                        }
+                    }
+                    _ => viss::server::ServerTLS::Disabled,
+                };
+
+                let actuation_fallback = if args.get_flag("viss-require-actuation-provider") {
+                    viss::v2::ActuationFallback::Error
+                } else {
+                    viss::v2::ActuationFallback::ActuatorTarget
+                };
+
+                let authorization_mode = if args.get_flag("viss-authorize-connection") {
+                    viss::v2::AuthorizationMode::PerConnection
+                } else {
+                    viss::v2::AuthorizationMode::PerMessage
+                };
+
                 let broker = broker.clone();
                 let authorization = authorization.clone();
                 tokio::spawn(async move {
-                    if let Err(err) = viss::server::serve(viss_addr, broker, authorization).await {
+                    if let Err(err) = viss::server::serve(
+                        viss_addr,
+                        broker,
+                        #[cfg(feature = "tls")]
+                        viss_tls,
+                        authorization,
+                        authorization_mode,
+                        actuation_fallback,
+                    )
+                    .await
+                    {
                         error!("{err}");
                     }
                 });
             }
         }
 
+        #[cfg(feature = "mqtt")]
+        if args.get_flag("enable-mqtt") {
+            let publish_patterns = args
+                .get_many::<String>("mqtt-publish")
+                .into_iter()
+                .flatten()
+                .map(|pattern| {
+                    glob::Matcher::new(pattern)
+                        .map_err(|_| format!("Invalid MQTT publish pattern: {pattern}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let config = mqtt::bridge::Config::new(
+                args.get_one::<String>("mqtt-host").unwrap(),
+                *args.get_one::<u16>("mqtt-port").unwrap(),
+            )
+            .client_id(args.get_one::<String>("mqtt-client-id").unwrap())
+            .topic_prefix(args.get_one::<String>("mqtt-topic-prefix").unwrap())
+            .publish_patterns(publish_patterns)
+            .token_file(
+                args.get_one::<String>("mqtt-token-file")
+                    .map(std::path::PathBuf::from),
+            );
+
+            let actuation_fallback = if args.get_flag("viss-require-actuation-provider") {
+                viss::v2::ActuationFallback::Error
+            } else {
+                viss::v2::ActuationFallback::ActuatorTarget
+            };
+
+            let broker = broker.clone();
+            let authorization = authorization.clone();
+            tokio::spawn(async move {
+                if let Err(err) =
+                    mqtt::bridge::serve(config, broker, authorization, actuation_fallback).await
+                {
+                    error!("{err}");
+                }
+            });
+        }
+
         let mut apis = vec![grpc::server::Api::KuksaValV1, grpc::server::Api::KuksaValV2];
 
         if args.get_flag("enable-databroker-v1") {
@@ -0,0 +1,835 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    audit,
+    authorization::Authorization,
+    broker, glob,
+    permissions::{self, Expiring, ExpiryEvent, Permissions},
+    viss::{
+        server::parse_v2_msg,
+        v2::{self, server::Viss},
+    },
+};
+
+// Delay before polling the event loop again after a connection error, which
+// makes it reconnect
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Capacity of the queue of requests to the MQTT broker
+const REQUEST_CAPACITY: usize = 64;
+
+// How often the bridge looks for newly registered signals to publish, and
+// for a renewed token once its token expired
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Configuration of the bridge to an MQTT broker.
+pub struct Config {
+    host: String,
+    port: u16,
+    client_id: String,
+    topic_prefix: String,
+    publish_patterns: Vec<glob::Matcher>,
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            client_id: "kuksa-databroker".to_owned(),
+            topic_prefix: "kuksa".to_owned(),
+            publish_patterns: Vec::new(),
+            token: None,
+            token_file: None,
+        }
+    }
+
+    /// Client id used to connect to the MQTT broker.
+    pub fn client_id(self, client_id: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            ..self
+        }
+    }
+
+    /// Prefix of all topics the bridge publishes or subscribes to.
+    pub fn topic_prefix(self, topic_prefix: impl Into<String>) -> Self {
+        Self {
+            topic_prefix: topic_prefix.into().trim_end_matches('/').to_owned(),
+            ..self
+        }
+    }
+
+    /// Publish the values of signals matching these patterns.
+    pub fn publish_patterns(self, publish_patterns: Vec<glob::Matcher>) -> Self {
+        Self {
+            publish_patterns,
+            ..self
+        }
+    }
+
+    /// Token (access token or API key) granting the permissions of the
+    /// bridge, which are used for all requests received over MQTT.
+    pub fn token(self, token: Option<String>) -> Self {
+        Self { token, ..self }
+    }
+
+    /// File containing the token, read instead of the token set with
+    /// `token`. It is read again when the token is about to expire, or has
+    /// expired, to continue with a renewed token.
+    pub fn token_file(self, token_file: Option<PathBuf>) -> Self {
+        Self { token_file, ..self }
+    }
+
+    fn read_token(&self) -> Result<Option<String>, std::io::Error> {
+        match &self.token_file {
+            Some(token_file) => Ok(Some(std::fs::read_to_string(token_file)?.trim().to_owned())),
+            None => Ok(self.token.clone()),
+        }
+    }
+
+    fn data_topic(&self, path: &str) -> String {
+        format!("{}/data/{}", self.topic_prefix, path.replace('.', "/"))
+    }
+
+    fn request_topic(&self) -> String {
+        format!("{}/request", self.topic_prefix)
+    }
+
+    fn response_topic(&self) -> String {
+        format!("{}/response", self.topic_prefix)
+    }
+}
+
+/// Connect to the MQTT broker and bridge it to the databroker:
+///
+/// - The values of signals matching the publish patterns are published
+///   (retained) to `<prefix>/data/<path>`, the VSS path using slashes as
+///   delimiter, as VISSv2 data objects. Signals registered later on are
+///   published once found, within the refresh interval.
+/// - VISSv2 get and set requests published to `<prefix>/request` are answered
+///   on `<prefix>/response`, where the `requestId` of the request correlates
+///   them.
+pub async fn serve(
+    config: Config,
+    broker: broker::DataBroker,
+    authorization: Authorization,
+    actuation_fallback: v2::ActuationFallback,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let token = config.read_token()?;
+    let permissions = bridge_permissions(&token, &authorization)?;
+    let token = Arc::new(RwLock::new(token));
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    let (client, eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+
+    info!(
+        "MQTT bridge connecting to {}:{} (topic prefix '{}')",
+        config.host, config.port, config.topic_prefix
+    );
+
+    let config = Arc::new(config);
+    tokio::spawn(publish_updates(
+        client.clone(),
+        config.clone(),
+        broker.clone(),
+        authorization.clone(),
+        token.clone(),
+        permissions,
+    ));
+
+    let server = Arc::new(
+        v2::server::Server::new(broker, authorization)
+            .with_actuation_fallback(actuation_fallback)
+            .with_api(audit::Api::Mqtt),
+    );
+    handle_events(eventloop, client, config, token, server).await;
+    Ok(())
+}
+
+// The permissions granted by the token of the bridge
+fn bridge_permissions(
+    token: &Option<String>,
+    authorization: &Authorization,
+) -> Result<Permissions, Box<dyn std::error::Error>> {
+    match (token, authorization) {
+        (_, Authorization::Disabled) => Ok(permissions::ALLOW_ALL.clone()),
+        (Some(token), Authorization::Enabled { .. }) => {
+            Ok(authorization.permissions_from_token(token)?)
+        }
+        (None, Authorization::Enabled { .. }) => {
+            Err("A token is required for the MQTT bridge if authorization is enabled".into())
+        }
+    }
+}
+
+// Read the token file again, returning the permissions of the token if it
+// has been renewed
+fn renew_token(
+    config: &Config,
+    authorization: &Authorization,
+    token: &RwLock<Option<String>>,
+) -> Option<Permissions> {
+    let renewed = match config.read_token() {
+        Ok(renewed) => renewed,
+        Err(err) => {
+            warn!("Failed to read the token of the MQTT bridge: {err}");
+            return None;
+        }
+    };
+    if renewed == *token.read().unwrap_or_else(PoisonError::into_inner) {
+        return None;
+    }
+    match bridge_permissions(&renewed, authorization) {
+        Ok(permissions) if !permissions.is_expired() => {
+            info!("The MQTT bridge continues with a renewed token");
+            *token.write().unwrap_or_else(PoisonError::into_inner) = renewed;
+            Some(permissions)
+        }
+        Ok(_) => None,
+        Err(err) => {
+            warn!("The renewed token of the MQTT bridge is invalid: {err}");
+            None
+        }
+    }
+}
+
+async fn handle_events(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    config: Arc<Config>,
+    token: Arc<RwLock<Option<String>>>,
+    server: Arc<v2::server::Server>,
+) {
+    let request_topic = config.request_topic();
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("MQTT bridge connected");
+                // Subscriptions do not survive a clean session
+                if let Err(err) = client.subscribe(&request_topic, QoS::AtLeastOnce).await {
+                    error!("Failed to subscribe to {request_topic}: {err}");
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == request_topic => {
+                let client = client.clone();
+                let config = config.clone();
+                let token = token.read().unwrap_or_else(PoisonError::into_inner).clone();
+                let server = server.clone();
+                tokio::spawn(async move {
+                    let response = handle_request(&server, &token, &publish.payload).await;
+                    match response {
+                        Ok(response) => {
+                            debug!("Sending response: {}", response);
+                            if let Err(err) = client
+                                .publish(config.response_topic(), QoS::AtLeastOnce, false, response)
+                                .await
+                            {
+                                warn!("Failed to publish response: {err}");
+                            }
+                        }
+                        Err(err) => error!("Failed to serialize response: {err}"),
+                    }
+                });
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!("MQTT connection error: {err}");
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+            }
+        }
+    }
+}
+
+// Requests are served with the permissions of the bridge, regardless of the
+// authorization they carry
+async fn handle_request(
+    server: &v2::server::Server,
+    token: &Option<String>,
+    payload: &[u8],
+) -> Result<String, serde_json::Error> {
+    let msg = match std::str::from_utf8(payload) {
+        Ok(msg) => msg,
+        Err(_) => {
+            return serde_json::to_string(&v2::GenericErrorResponse {
+                action: None,
+                request_id: None,
+                error: v2::Error::BadRequest {
+                    msg: Some("Request is not valid UTF-8".into()),
+                },
+            })
+        }
+    };
+    debug!("Received request: {}", msg);
+    match parse_v2_msg(msg) {
+        Ok(v2::Request::Get(request)) => {
+            let request = v2::GetRequest {
+                authorization: token.clone(),
+                ..request
+            };
+            match server.get(request).await {
+                Ok(response) => serde_json::to_string(&response),
+                Err(error_response) => serde_json::to_string(&error_response),
+            }
+        }
+        Ok(v2::Request::Set(request)) => {
+            let request = v2::SetRequest {
+                authorization: token.clone(),
+                ..request
+            };
+            match server.set(request).await {
+                Ok(response) => serde_json::to_string(&response),
+                Err(error_response) => serde_json::to_string(&error_response),
+            }
+        }
+        // Signals are published according to the configuration instead
+        Ok(v2::Request::Subscribe(request)) => serde_json::to_string(&v2::GenericErrorResponse {
+            action: Some("subscribe".into()),
+            request_id: Some(request.request_id),
+            error: v2::Error::NotImplemented,
+        }),
+        Ok(v2::Request::Unsubscribe(request)) => serde_json::to_string(&v2::GenericErrorResponse {
+            action: Some("unsubscribe".into()),
+            request_id: Some(request.request_id),
+            error: v2::Error::NotImplemented,
+        }),
+        Err(error_response) => serde_json::to_string(&error_response),
+    }
+}
+
+async fn publish_updates(
+    client: AsyncClient,
+    config: Arc<Config>,
+    broker: broker::DataBroker,
+    authorization: Authorization,
+    token: Arc<RwLock<Option<String>>>,
+    mut permissions: Permissions,
+) {
+    if config.publish_patterns.is_empty() {
+        return;
+    }
+    loop {
+        if !permissions.is_expired() {
+            if let Some(renewed) = publish_until_expiry(
+                &client,
+                &config,
+                &broker,
+                &authorization,
+                &token,
+                &permissions,
+            )
+            .await
+            {
+                permissions = renewed;
+                continue;
+            }
+        }
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+        if let Some(renewed) = renew_token(&config, &authorization, &token) {
+            permissions = renewed;
+        }
+    }
+}
+
+// Publish the updates of the signals matching the publish patterns until the
+// permissions expire, returning the permissions of a renewed token if the
+// token is renewed before
+async fn publish_until_expiry(
+    client: &AsyncClient,
+    config: &Config,
+    broker: &broker::DataBroker,
+    authorization: &Authorization,
+    token: &RwLock<Option<String>>,
+    permissions: &Permissions,
+) -> Option<Permissions> {
+    let broker = broker.authorized_access(permissions);
+    let (subscription_id, stream) = match broker.subscribe_modifiable(None).await {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            error!("Failed to subscribe to the signals to publish over MQTT: {err:?}");
+            return None;
+        }
+    };
+    let mut stream = Box::pin(permissions::with_expiry(stream, permissions));
+
+    let mut subscribed = HashSet::new();
+    subscribe_new_signals(
+        &broker,
+        permissions,
+        config,
+        subscription_id,
+        &mut subscribed,
+    )
+    .await;
+    if subscribed.is_empty() {
+        warn!("No signals match the MQTT publish patterns yet");
+    }
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    refresh.reset();
+
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {
+                subscribe_new_signals(&broker, permissions, config, subscription_id, &mut subscribed).await;
+            }
+            item = stream.next() => match item {
+                Some(Expiring::Item(updates)) => {
+                    for update in updates.updates {
+                        if let (Some(path), Some(datapoint)) =
+                            (update.update.path, update.update.datapoint)
+                        {
+                            publish_datapoint(client, config, path, datapoint).await;
+                        }
+                    }
+                }
+                Some(Expiring::Event(ExpiryEvent::ExpiresSoon(_))) => {
+                    if let Some(renewed) = renew_token(config, authorization, token) {
+                        return Some(renewed);
+                    }
+                    warn!("The token of the MQTT bridge expires soon");
+                }
+                Some(Expiring::Event(ExpiryEvent::Expired)) => {
+                    error!("The token of the MQTT bridge expired, signals are no longer published until it is renewed");
+                    return None;
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+// Add the signals matching the publish patterns that are not subscribed to
+// yet to the subscription. Signals the permissions do not allow to subscribe
+// to are left out.
+async fn subscribe_new_signals(
+    broker: &broker::AuthorizedAccess<'_, '_>,
+    permissions: &Permissions,
+    config: &Config,
+    subscription_id: broker::SubscriptionId,
+    subscribed: &mut HashSet<i32>,
+) {
+    let added: HashMap<_, _> = broker
+        .filter_map_entries(|entry| {
+            let metadata = entry.metadata();
+            (!subscribed.contains(&metadata.id)
+                && config
+                    .publish_patterns
+                    .iter()
+                    .any(|pattern| pattern.is_match(&metadata.glob_path))
+                && permissions.can_subscribe(&metadata.path).is_ok())
+            .then_some((metadata.id, HashSet::from([broker::Field::Datapoint])))
+        })
+        .await
+        .into_iter()
+        .collect();
+    if added.is_empty() {
+        return;
+    }
+    let ids: Vec<i32> = added.keys().copied().collect();
+    match broker
+        .modify_subscription(subscription_id, added, HashSet::new())
+        .await
+    {
+        Ok(()) => subscribed.extend(ids),
+        // Tried again with the next refresh
+        Err(err) => error!("Failed to subscribe to the signals to publish over MQTT: {err:?}"),
+    }
+}
+
+async fn publish_datapoint(
+    client: &AsyncClient,
+    config: &Config,
+    path: String,
+    datapoint: broker::Datapoint,
+) {
+    let topic = config.data_topic(&path);
+    let data = v2::DataObject {
+        path: path.into(),
+        dp: datapoint.into(),
+    };
+    match serde_json::to_vec(&data) {
+        Ok(payload) => {
+            if let Err(err) = client.publish(topic, QoS::AtLeastOnce, true, payload).await {
+                warn!("Failed to publish update: {err}");
+            }
+        }
+        Err(err) => error!("Failed to serialize update: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        authorization::policy::Policy,
+        types::{ChangeType, DataValue},
+    };
+    use std::time::SystemTime;
+
+    async fn add_speed(broker: &broker::DataBroker) -> i32 {
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_entry(
+                "Vehicle.Speed".to_owned(),
+                broker::DataType::Float,
+                ChangeType::Continuous,
+                broker::EntryType::Sensor,
+                "Speed".to_owned(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("entry should be added")
+    }
+
+    fn authorization() -> Authorization {
+        let policy = Policy::from_json(
+            r#"{"clients": [
+                {"name": "telematics", "api_keys": ["telematics-key"], "read": ["*"], "provide": ["*"]}
+            ]}"#,
+        )
+        .expect("policy should be valid");
+        Authorization::Enabled {
+            token_decoder: None,
+            policy: Some(policy),
+        }
+    }
+
+    async fn request(server: &v2::server::Server, token: &str, msg: &str) -> serde_json::Value {
+        let response = handle_request(server, &Some(token.to_owned()), msg.as_bytes())
+            .await
+            .expect("response should be serializable");
+        serde_json::from_str(&response).expect("response should be JSON")
+    }
+
+    #[test]
+    fn test_topics() {
+        let config = Config::new("localhost", 1883).topic_prefix("vehicle/1/");
+        assert_eq!(
+            config.data_topic("Vehicle.Cabin.Door.Row1.Left.IsOpen"),
+            "vehicle/1/data/Vehicle/Cabin/Door/Row1/Left/IsOpen"
+        );
+        assert_eq!(config.request_topic(), "vehicle/1/request");
+        assert_eq!(config.response_topic(), "vehicle/1/response");
+    }
+
+    #[tokio::test]
+    async fn test_handle_request() {
+        let broker = broker::DataBroker::default();
+        add_speed(&broker).await;
+        let server = v2::server::Server::new(broker, authorization());
+
+        // The token of the bridge replaces the authorization of the request
+        let response = request(
+            &server,
+            "telematics-key",
+            r#"{"action": "set", "path": "Vehicle.Speed", "value": "42", "requestId": "1", "authorization": "other-key"}"#,
+        )
+        .await;
+        assert_eq!(response["action"], "set");
+        assert_eq!(response["requestId"], "1");
+        assert!(response.get("error").is_none());
+
+        let response = request(
+            &server,
+            "telematics-key",
+            r#"{"action": "get", "path": "Vehicle.Speed", "requestId": "2"}"#,
+        )
+        .await;
+        assert_eq!(response["requestId"], "2");
+        assert_eq!(response["data"]["path"], "Vehicle.Speed");
+        assert_eq!(response["data"]["dp"]["value"], "42");
+
+        let response = request(
+            &server,
+            "unknown-key",
+            r#"{"action": "get", "path": "Vehicle.Speed", "requestId": "3"}"#,
+        )
+        .await;
+        assert_eq!(response["requestId"], "3");
+        assert_eq!(response["error"]["reason"], "user_unknown");
+
+        let response = request(
+            &server,
+            "telematics-key",
+            r#"{"action": "subscribe", "path": "Vehicle.Speed", "requestId": "4"}"#,
+        )
+        .await;
+        assert_eq!(response["requestId"], "4");
+        assert_eq!(response["error"]["number"], 501);
+
+        let response = request(&server, "telematics-key", "not json").await;
+        assert_eq!(response["error"]["number"], 400);
+    }
+
+    #[tokio::test]
+    async fn test_audit_api() {
+        let path = std::env::temp_dir().join(format!("mqtt-audit-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit_log = audit::AuditLog::open(&path, 1024 * 1024, 1)
+            .expect("audit log should open")
+            .publish_patterns(vec![
+                glob::Matcher::new("Vehicle.Speed").expect("pattern should be valid")
+            ]);
+        let broker = broker::DataBroker::default().with_audit_log(audit_log);
+        add_speed(&broker).await;
+        let server =
+            v2::server::Server::new(broker.clone(), authorization()).with_api(audit::Api::Mqtt);
+
+        request(
+            &server,
+            "telematics-key",
+            r#"{"action": "set", "path": "Vehicle.Speed", "value": "42", "requestId": "1"}"#,
+        )
+        .await;
+        request(
+            &server,
+            "unknown-key",
+            r#"{"action": "get", "path": "Vehicle.Speed", "requestId": "2"}"#,
+        )
+        .await;
+        broker.audit_log().unwrap().flush();
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .expect("audit log should be readable")
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("record should be JSON"))
+            .collect();
+        std::fs::remove_file(&path).expect("audit log should be removable");
+
+        let events: Vec<(&str, &str)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["event"].as_str().unwrap(),
+                    record["api"].as_str().unwrap_or_default(),
+                )
+            })
+            .collect();
+        assert!(events.contains(&("publish", "mqtt")));
+        assert!(events.contains(&("authorization", "mqtt")));
+    }
+
+    #[test]
+    fn test_renew_token() {
+        let path = std::env::temp_dir().join(format!("mqtt-token-{}", std::process::id()));
+        std::fs::write(&path, "telematics-key\n").expect("token file should be written");
+        let config = Config::new("localhost", 1883).token_file(Some(path.clone()));
+        let token = RwLock::new(Some("expired-key".to_owned()));
+
+        assert!(renew_token(&config, &authorization(), &token).is_some());
+        assert_eq!(
+            *token.read().expect("lock should not be poisoned"),
+            Some("telematics-key".to_owned())
+        );
+        // Unchanged
+        assert!(renew_token(&config, &authorization(), &token).is_none());
+
+        std::fs::write(&path, "unknown-key").expect("token file should be written");
+        assert!(renew_token(&config, &authorization(), &token).is_none());
+        assert_eq!(
+            *token.read().expect("lock should not be poisoned"),
+            Some("telematics-key".to_owned())
+        );
+        std::fs::remove_file(&path).expect("token file should be removed");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_new_signals() {
+        let broker = broker::DataBroker::default();
+        let permissions = permissions::PermissionBuilder::new()
+            .add_read_permission(permissions::Permission::Glob("Vehicle.Speed".to_owned()))
+            .build()
+            .expect("permissions should build");
+        let access = broker.authorized_access(&permissions);
+        let config = Config::new("localhost", 1883)
+            .publish_patterns(vec![glob::Matcher::new("Vehicle.**").unwrap()]);
+        let (subscription_id, _stream) = access
+            .subscribe_modifiable(None)
+            .await
+            .expect("subscription should succeed");
+
+        let mut subscribed = HashSet::new();
+        subscribe_new_signals(
+            &access,
+            &permissions,
+            &config,
+            subscription_id,
+            &mut subscribed,
+        )
+        .await;
+        assert!(subscribed.is_empty());
+
+        // Signals registered later on are published as well, unless they may
+        // not be read
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_entry(
+                "Vehicle.Width".to_owned(),
+                broker::DataType::Uint16,
+                ChangeType::Static,
+                broker::EntryType::Attribute,
+                "Width".to_owned(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("entry should be added");
+        let id = add_speed(&broker).await;
+        subscribe_new_signals(
+            &access,
+            &permissions,
+            &config,
+            subscription_id,
+            &mut subscribed,
+        )
+        .await;
+        assert_eq!(subscribed, HashSet::from([id]));
+    }
+
+    // Requires a locally started MQTT broker, e.g. `mosquitto -p 1883`. Its
+    // address can be set with KUKSA_MQTT_TEST_BROKER (default localhost:1883).
+    #[ignore]
+    #[tokio::test]
+    async fn test_bridge() {
+        let address =
+            std::env::var("KUKSA_MQTT_TEST_BROKER").unwrap_or("localhost:1883".to_owned());
+        let (host, port) = address
+            .rsplit_once(':')
+            .expect("address should be host:port");
+        let port: u16 = port.parse().expect("port should be a number");
+        let prefix = format!("kuksa-test/{}", uuid::Uuid::new_v4());
+
+        let broker = broker::DataBroker::default();
+        let id = add_speed(&broker).await;
+        let config = Config::new(host, port)
+            .client_id(format!("kuksa-databroker-{}", uuid::Uuid::new_v4()))
+            .topic_prefix(&prefix)
+            .publish_patterns(vec![glob::Matcher::new("Vehicle.**").unwrap()])
+            .token(Some("telematics-key".to_owned()));
+        tokio::spawn({
+            let broker = broker.clone();
+            async move {
+                serve(
+                    config,
+                    broker,
+                    authorization(),
+                    v2::ActuationFallback::default(),
+                )
+                .await
+                .expect("bridge should start")
+            }
+        });
+
+        let options = MqttOptions::new(format!("kuksa-test-{}", uuid::Uuid::new_v4()), host, port);
+        let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+        client
+            .subscribe(format!("{prefix}/data/#"), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        client
+            .subscribe(format!("{prefix}/response"), QoS::AtLeastOnce)
+            .await
+            .unwrap();
+
+        // Wait for both subscriptions and for the bridge to be connected
+        let mut subscribed = 0;
+        while subscribed < 2 {
+            if let Event::Incoming(Packet::SubAck(_)) =
+                eventloop.poll().await.expect("client should be connected")
+            {
+                subscribed += 1;
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .update_entries([(
+                id,
+                broker::EntryUpdate {
+                    datapoint: Some(broker::Datapoint {
+                        ts: SystemTime::now(),
+                        source_ts: None,
+                        value: DataValue::Float(10.0),
+                    }),
+                    ..Default::default()
+                },
+            )])
+            .await
+            .expect("update should succeed");
+        let (topic, payload) =
+            wait_for(&mut eventloop, |_, payload| payload["dp"]["value"] == "10").await;
+        assert_eq!(topic, format!("{prefix}/data/Vehicle/Speed"));
+        assert_eq!(payload["path"], "Vehicle.Speed");
+
+        client
+            .publish(
+                format!("{prefix}/request"),
+                QoS::AtLeastOnce,
+                false,
+                r#"{"action": "set", "path": "Vehicle.Speed", "value": "20", "requestId": "42"}"#,
+            )
+            .await
+            .unwrap();
+        let (_, payload) = wait_for(&mut eventloop, |topic, _| {
+            topic == format!("{prefix}/response")
+        })
+        .await;
+        assert_eq!(payload["action"], "set");
+        assert_eq!(payload["requestId"], "42");
+        assert!(payload.get("error").is_none());
+    }
+
+    // Wait for a message published to a subscribed topic
+    async fn wait_for(
+        eventloop: &mut EventLoop,
+        predicate: impl Fn(&str, &serde_json::Value) -> bool,
+    ) -> (String, serde_json::Value) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Event::Incoming(Packet::Publish(publish)) =
+                    eventloop.poll().await.expect("client should be connected")
+                {
+                    let payload =
+                        serde_json::from_slice(&publish.payload).expect("payload should be JSON");
+                    if predicate(&publish.topic, &payload) {
+                        return (publish.topic, payload);
+                    }
+                }
+            }
+        })
+        .await
+        .expect("message should be received")
+    }
+}
@@ -11,45 +11,102 @@
 * SPDX-License-Identifier: Apache-2.0
 ********************************************************************************/
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
+use futures::Stream;
 use lazy_static::lazy_static;
 use regex::RegexSet;
+use tokio_stream::StreamExt;
 
 use crate::glob;
+use crate::transformation::Transformations;
 
 lazy_static! {
     pub static ref ALLOW_ALL: Permissions = Permissions {
         expires_at: None,
+        subject: None,
+        issuer: None,
+        transformations: Transformations::default(),
         read: PathMatcher::Everything,
         actuate: PathMatcher::Everything,
         provide: PathMatcher::Everything,
         create: PathMatcher::Everything,
+        metadata: PathMatcher::Everything,
+        subscribe: PathMatcher::Everything,
+        deny_read: PathMatcher::Nothing,
+        deny_actuate: PathMatcher::Nothing,
+        deny_provide: PathMatcher::Nothing,
+        deny_create: PathMatcher::Nothing,
+        deny_metadata: PathMatcher::Nothing,
+        deny_subscribe: PathMatcher::Nothing,
     };
     pub static ref ALLOW_NONE: Permissions = Permissions {
         expires_at: None,
+        subject: None,
+        issuer: None,
+        transformations: Transformations::default(),
         read: PathMatcher::Nothing,
         actuate: PathMatcher::Nothing,
         provide: PathMatcher::Nothing,
         create: PathMatcher::Nothing,
+        metadata: PathMatcher::Nothing,
+        subscribe: PathMatcher::Nothing,
+        deny_read: PathMatcher::Nothing,
+        deny_actuate: PathMatcher::Nothing,
+        deny_provide: PathMatcher::Nothing,
+        deny_create: PathMatcher::Nothing,
+        deny_metadata: PathMatcher::Nothing,
+        deny_subscribe: PathMatcher::Nothing,
     };
 }
 
+// How long before the permissions of a stream expire its client is warned
+pub const EXPIRY_WARNING_PERIOD: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone)]
 pub struct Permissions {
     expires_at: Option<SystemTime>,
+    // Whom the permissions were granted to, e.g. the "sub" claim of a token
+    subject: Option<String>,
+    // Who vouched for the subject, e.g. the "iss" claim of a token
+    issuer: Option<String>,
+    // Applied to the values read, by path
+    transformations: Transformations,
     read: PathMatcher,
     actuate: PathMatcher,
     provide: PathMatcher,
     create: PathMatcher,
+    // Metadata is also granted (by convention) by read permissions, this
+    // grants it on its own
+    metadata: PathMatcher,
+    // Subscriptions require read permissions, and are limited to these paths
+    // if any
+    subscribe: PathMatcher,
+    // Denied paths, overriding the granted ones
+    deny_read: PathMatcher,
+    deny_actuate: PathMatcher,
+    deny_provide: PathMatcher,
+    deny_create: PathMatcher,
+    deny_metadata: PathMatcher,
+    deny_subscribe: PathMatcher,
 }
 
 pub struct PermissionBuilder {
     expiration: Option<SystemTime>,
+    subject: Option<String>,
+    issuer: Option<String>,
     read: PathMatchBuilder,
     actuate: PathMatchBuilder,
     provide: PathMatchBuilder,
     create: PathMatchBuilder,
+    metadata: PathMatchBuilder,
+    subscribe: PathMatchBuilder,
+    deny_read: PathMatchBuilder,
+    deny_actuate: PathMatchBuilder,
+    deny_provide: PathMatchBuilder,
+    deny_create: PathMatchBuilder,
+    deny_metadata: PathMatchBuilder,
+    deny_subscribe: PathMatchBuilder,
 }
 
 pub enum Permission {
@@ -82,6 +139,19 @@ pub enum PermissionsBuildError {
     BuildError,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ExpiryEvent {
+    // The permissions expire within EXPIRY_WARNING_PERIOD, at the given time
+    ExpiresSoon(SystemTime),
+    Expired,
+}
+
+#[derive(Debug)]
+pub enum Expiring<T> {
+    Item(T),
+    Event(ExpiryEvent),
+}
+
 impl Default for PermissionBuilder {
     fn default() -> Self {
         Self::new()
@@ -92,10 +162,20 @@ impl PermissionBuilder {
     pub fn new() -> Self {
         Self {
             expiration: None,
+            subject: None,
+            issuer: None,
             read: PathMatchBuilder::Nothing,
             actuate: PathMatchBuilder::Nothing,
             provide: PathMatchBuilder::Nothing,
             create: PathMatchBuilder::Nothing,
+            metadata: PathMatchBuilder::Nothing,
+            subscribe: PathMatchBuilder::Nothing,
+            deny_read: PathMatchBuilder::Nothing,
+            deny_actuate: PathMatchBuilder::Nothing,
+            deny_provide: PathMatchBuilder::Nothing,
+            deny_create: PathMatchBuilder::Nothing,
+            deny_metadata: PathMatchBuilder::Nothing,
+            deny_subscribe: PathMatchBuilder::Nothing,
         }
     }
 
@@ -104,57 +184,100 @@ impl PermissionBuilder {
         self
     }
 
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
     pub fn add_read_permission(mut self, permission: Permission) -> Self {
-        match permission {
-            Permission::Nothing => {
-                // Adding nothing
-            }
-            Permission::All => self.read.extend_with(PathMatchBuilder::Everything),
-            Permission::Glob(path) => self.read.extend_with_glob(path),
-        };
+        self.read.extend_with_permission(permission);
         self
     }
 
     pub fn add_actuate_permission(mut self, permission: Permission) -> Self {
-        match permission {
-            Permission::Nothing => {
-                // Adding nothing
-            }
-            Permission::All => self.actuate.extend_with(PathMatchBuilder::Everything),
-            Permission::Glob(path) => self.actuate.extend_with_glob(path),
-        };
+        self.actuate.extend_with_permission(permission);
         self
     }
 
     pub fn add_provide_permission(mut self, permission: Permission) -> Self {
-        match permission {
-            Permission::Nothing => {
-                // Adding nothing
-            }
-            Permission::All => self.provide.extend_with(PathMatchBuilder::Everything),
-            Permission::Glob(path) => self.provide.extend_with_glob(path),
-        };
+        self.provide.extend_with_permission(permission);
         self
     }
 
     pub fn add_create_permission(mut self, permission: Permission) -> Self {
-        match permission {
-            Permission::Nothing => {
-                // Adding nothing
-            }
-            Permission::All => self.create.extend_with(PathMatchBuilder::Everything),
-            Permission::Glob(path) => self.create.extend_with_glob(path),
-        };
+        self.create.extend_with_permission(permission);
+        self
+    }
+
+    pub fn add_metadata_permission(mut self, permission: Permission) -> Self {
+        self.metadata.extend_with_permission(permission);
+        self
+    }
+
+    pub fn add_subscribe_permission(mut self, permission: Permission) -> Self {
+        self.subscribe.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny read access to paths, regardless of the granted permissions.
+    pub fn add_read_denial(mut self, permission: Permission) -> Self {
+        self.deny_read.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny actuate access to paths, regardless of the granted permissions.
+    pub fn add_actuate_denial(mut self, permission: Permission) -> Self {
+        self.deny_actuate.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny provide access to paths, regardless of the granted permissions.
+    pub fn add_provide_denial(mut self, permission: Permission) -> Self {
+        self.deny_provide.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny create access to paths, regardless of the granted permissions.
+    pub fn add_create_denial(mut self, permission: Permission) -> Self {
+        self.deny_create.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny access to the metadata of paths, regardless of the granted permissions.
+    pub fn add_metadata_denial(mut self, permission: Permission) -> Self {
+        self.deny_metadata.extend_with_permission(permission);
+        self
+    }
+
+    /// Deny subscribing to paths, regardless of the granted permissions.
+    pub fn add_subscribe_denial(mut self, permission: Permission) -> Self {
+        self.deny_subscribe.extend_with_permission(permission);
         self
     }
 
     pub fn build(self) -> Result<Permissions, PermissionsBuildError> {
         Ok(Permissions {
             expires_at: self.expiration,
+            subject: self.subject,
+            issuer: self.issuer,
+            transformations: Transformations::default(),
             read: self.read.build()?,
             actuate: self.actuate.build()?,
             provide: self.provide.build()?,
             create: self.create.build()?,
+            metadata: self.metadata.build()?,
+            subscribe: self.subscribe.build()?,
+            deny_read: self.deny_read.build()?,
+            deny_actuate: self.deny_actuate.build()?,
+            deny_provide: self.deny_provide.build()?,
+            deny_create: self.deny_create.build()?,
+            deny_metadata: self.deny_metadata.build()?,
+            deny_subscribe: self.deny_subscribe.build()?,
         })
     }
 }
@@ -164,24 +287,50 @@ impl Permissions {
         PermissionBuilder::new()
     }
 
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// Transform the values read with these permissions.
+    pub fn with_transformations(mut self, transformations: Transformations) -> Self {
+        self.transformations = transformations;
+        self
+    }
+
+    pub fn transformations(&self) -> &Transformations {
+        &self.transformations
+    }
+
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
     pub fn can_read(&self, path: &str) -> Result<(), PermissionError> {
         if self.is_expired() {
             return Err(PermissionError::Expired);
         }
 
+        if self.deny_read.is_match(path) {
+            return Err(PermissionError::Denied);
+        }
+
         if self.read.is_match(path) {
             return Ok(());
         }
 
         // Read permissions are included (by convention) in the
-        // other permissions as well.
-        if self.actuate.is_match(path) {
+        // other permissions as well, unless those are denied.
+        if self.actuate.is_match(path) && !self.deny_actuate.is_match(path) {
             return Ok(());
         }
-        if self.provide.is_match(path) {
+        if self.provide.is_match(path) && !self.deny_provide.is_match(path) {
             return Ok(());
         }
-        if self.create.is_match(path) {
+        if self.create.is_match(path) && !self.deny_create.is_match(path) {
             return Ok(());
         }
 
@@ -194,7 +343,7 @@ impl Permissions {
             return Err(PermissionError::Expired);
         }
 
-        if self.actuate.is_match(path) {
+        if self.actuate.is_match(path) && !self.deny_actuate.is_match(path) {
             return Ok(());
         }
         Err(PermissionError::Denied)
@@ -206,7 +355,7 @@ impl Permissions {
             return Err(PermissionError::Expired);
         }
 
-        if self.provide.is_match(path) {
+        if self.provide.is_match(path) && !self.deny_provide.is_match(path) {
             return Ok(());
         }
         Err(PermissionError::Denied)
@@ -217,12 +366,47 @@ impl Permissions {
             return Err(PermissionError::Expired);
         }
 
-        if self.create.is_match(path) {
+        if self.create.is_match(path) && !self.deny_create.is_match(path) {
             return Ok(());
         }
         Err(PermissionError::Denied)
     }
 
+    /// Whether the metadata of a path may be accessed, which is also
+    /// permitted if the path may be read. Denying read denies it as well.
+    pub fn can_read_metadata(&self, path: &str) -> Result<(), PermissionError> {
+        if self.is_expired() {
+            return Err(PermissionError::Expired);
+        }
+
+        if self.deny_metadata.is_match(path) || self.deny_read.is_match(path) {
+            return Err(PermissionError::Denied);
+        }
+
+        if self.metadata.is_match(path) {
+            return Ok(());
+        }
+        self.can_read(path)
+    }
+
+    /// Whether a path may be subscribed to, which requires it to be readable.
+    /// Subscribe permissions only narrow down the readable paths: if there
+    /// are any, only the paths they match may be subscribed to.
+    pub fn can_subscribe(&self, path: &str) -> Result<(), PermissionError> {
+        if self.is_expired() {
+            return Err(PermissionError::Expired);
+        }
+
+        if self.deny_subscribe.is_match(path) {
+            return Err(PermissionError::Denied);
+        }
+
+        if !matches!(self.subscribe, PathMatcher::Nothing) && !self.subscribe.is_match(path) {
+            return Err(PermissionError::Denied);
+        }
+        self.can_read(path)
+    }
+
     #[cfg_attr(feature="otel", tracing::instrument(name="permissions_expired", skip(self), fields(timestamp=chrono::Utc::now().to_string())))]
     #[inline]
     pub fn is_expired(&self) -> bool {
@@ -247,6 +431,16 @@ impl PathMatcher {
 }
 
 impl PathMatchBuilder {
+    pub fn extend_with_permission(&mut self, permission: Permission) {
+        match permission {
+            Permission::Nothing => {
+                // Adding nothing
+            }
+            Permission::All => self.extend_with(PathMatchBuilder::Everything),
+            Permission::Glob(path) => self.extend_with_glob(path),
+        }
+    }
+
     pub fn extend_with(&mut self, other: PathMatchBuilder) {
         if let PathMatchBuilder::Everything = self {
             // We already allow everything
@@ -305,3 +499,61 @@ impl PathMatchBuilder {
         }
     }
 }
+
+/// Wait for the next expiry event of permissions expiring at `expires_at`.
+///
+/// Completes with `ExpiresSoon` once within `EXPIRY_WARNING_PERIOD` of the
+/// expiration (unless `warned`), and with `Expired` once expired. Never
+/// completes for permissions without expiration.
+pub async fn expiry_event(expires_at: Option<SystemTime>, warned: bool) -> ExpiryEvent {
+    let Some(expires_at) = expires_at else {
+        return std::future::pending().await;
+    };
+    let remaining = expires_at
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    if !warned && !remaining.is_zero() {
+        tokio::time::sleep(remaining.saturating_sub(EXPIRY_WARNING_PERIOD)).await;
+        ExpiryEvent::ExpiresSoon(expires_at)
+    } else {
+        tokio::time::sleep(remaining).await;
+        ExpiryEvent::Expired
+    }
+}
+
+/// Interleave the expiry events of `permissions` with the items of a stream
+/// delivering data accessed with them.
+///
+/// The stream ends after `Expired`, which is also emitted if the wrapped stream
+/// ends because its subscription was removed due to the expiry.
+pub fn with_expiry<S>(stream: S, permissions: &Permissions) -> impl Stream<Item = Expiring<S::Item>>
+where
+    S: Stream + Send + 'static,
+{
+    let expires_at = permissions.expires_at();
+    let stream = Box::pin(stream);
+    futures::stream::unfold(
+        (stream, false, false),
+        move |(mut stream, warned, done)| async move {
+            if done {
+                return None;
+            }
+            tokio::select! {
+                item = stream.next() => match item {
+                    Some(item) => Some((Expiring::Item(item), (stream, warned, false))),
+                    None => match expires_at {
+                        Some(expires_at) if expires_at <= SystemTime::now() => Some((
+                            Expiring::Event(ExpiryEvent::Expired),
+                            (stream, warned, true),
+                        )),
+                        _ => None,
+                    },
+                },
+                event = expiry_event(expires_at, warned) => {
+                    let done = event == ExpiryEvent::Expired;
+                    Some((Expiring::Event(event), (stream, true, done)))
+                }
+            }
+        },
+    )
+}
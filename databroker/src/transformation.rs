@@ -0,0 +1,296 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use ring::hmac;
+use serde::Deserialize;
+
+use crate::{glob, types::DataValue};
+
+// Decimals rounded to at most, see Transformation::Round
+const MAX_ROUND_DECIMALS: u32 = 15;
+
+/// Transformation of the values a client reads, e.g. to reduce their
+/// precision before they are handed to a less trusted consumer.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transformation {
+    /// Round floating point values to a number of decimals.
+    Round(u32),
+    /// Round numeric values to a multiple of a step.
+    Quantise(f64),
+    /// Replace values by the (hex encoded) HMAC-SHA256 of their string
+    /// representation, keyed with the given secret.
+    Hash(String),
+    /// Only provide values once they are a number of seconds old.
+    Delay(u64),
+    /// Replace values by "not available".
+    Redact,
+}
+
+/// Transformations applied to the values of the paths matching their glob
+/// patterns, in order.
+#[derive(Debug, Clone, Default)]
+pub struct Transformations {
+    rules: Arc<Vec<(glob::Matcher, Transformation)>>,
+}
+
+impl Transformation {
+    fn apply(&self, value: DataValue) -> DataValue {
+        match self {
+            Transformation::Round(decimals) => {
+                // Doubles have no more significant decimals, while larger
+                // factors overflow
+                let factor = 10f64.powi((*decimals).min(MAX_ROUND_DECIMALS) as i32);
+                map_float(value, |value| (value * factor).round() / factor)
+            }
+            Transformation::Quantise(step) => map_numeric(value, |value| {
+                if *step > 0.0 {
+                    (value / step).round() * step
+                } else {
+                    value
+                }
+            }),
+            Transformation::Hash(key) => match value {
+                DataValue::NotAvailable => DataValue::NotAvailable,
+                value => DataValue::String(hash(key, &value.to_string())),
+            },
+            Transformation::Delay(_) => value,
+            Transformation::Redact => DataValue::NotAvailable,
+        }
+    }
+}
+
+impl Transformations {
+    pub fn new(rules: Vec<(glob::Matcher, Transformation)>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether any transformation applies to the path.
+    pub fn applies_to(&self, path: &str) -> bool {
+        self.matching(path).next().is_some()
+    }
+
+    /// Apply the transformations of the path to a value. Delays are not
+    /// applied, see [`Transformations::delay`].
+    pub fn apply(&self, path: &str, value: DataValue) -> DataValue {
+        self.matching(path)
+            .fold(value, |value, transformation| transformation.apply(value))
+    }
+
+    /// The longest delay of the values of the path, if any.
+    pub fn delay(&self, path: &str) -> Option<Duration> {
+        self.matching(path)
+            .filter_map(|transformation| match transformation {
+                Transformation::Delay(seconds) => Some(Duration::from_secs(*seconds)),
+                _ => None,
+            })
+            .max()
+    }
+
+    fn matching<'a>(&'a self, path: &str) -> impl Iterator<Item = &'a Transformation> + 'a {
+        // Patterns match the path with '/' as separator, like Metadata::glob_path
+        let glob_path = path.replace('.', "/");
+        self.rules
+            .iter()
+            .filter(move |(matcher, _)| matcher.is_match(&glob_path))
+            .map(|(_, transformation)| transformation)
+    }
+}
+
+fn map_float(value: DataValue, f: impl Fn(f64) -> f64) -> DataValue {
+    match value {
+        DataValue::Float(value) => DataValue::Float(f(value.into()) as f32),
+        DataValue::Double(value) => DataValue::Double(f(value)),
+        DataValue::FloatArray(values) => DataValue::FloatArray(
+            values
+                .into_iter()
+                .map(|value| f(value.into()) as f32)
+                .collect(),
+        ),
+        DataValue::DoubleArray(values) => {
+            DataValue::DoubleArray(values.into_iter().map(f).collect())
+        }
+        value => value,
+    }
+}
+
+fn map_numeric(value: DataValue, f: impl Fn(f64) -> f64) -> DataValue {
+    // Conversions back to integers saturate
+    match value {
+        DataValue::Int32(value) => DataValue::Int32(f(value.into()) as i32),
+        DataValue::Int64(value) => DataValue::Int64(f(value as f64) as i64),
+        DataValue::Uint32(value) => DataValue::Uint32(f(value.into()) as u32),
+        DataValue::Uint64(value) => DataValue::Uint64(f(value as f64) as u64),
+        DataValue::Int32Array(values) => DataValue::Int32Array(
+            values
+                .into_iter()
+                .map(|value| f(value.into()) as i32)
+                .collect(),
+        ),
+        DataValue::Int64Array(values) => DataValue::Int64Array(
+            values
+                .into_iter()
+                .map(|value| f(value as f64) as i64)
+                .collect(),
+        ),
+        DataValue::Uint32Array(values) => DataValue::Uint32Array(
+            values
+                .into_iter()
+                .map(|value| f(value.into()) as u32)
+                .collect(),
+        ),
+        DataValue::Uint64Array(values) => DataValue::Uint64Array(
+            values
+                .into_iter()
+                .map(|value| f(value as f64) as u64)
+                .collect(),
+        ),
+        value => map_float(value, f),
+    }
+}
+
+fn hash(key: &str, value: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+    hmac::sign(&key, value.as_bytes())
+        .as_ref()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transformations(rules: &[(&str, Transformation)]) -> Transformations {
+        Transformations::new(
+            rules
+                .iter()
+                .map(|(pattern, transformation)| {
+                    (
+                        glob::Matcher::new(pattern).expect("pattern should be valid"),
+                        transformation.clone(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_round_and_quantise() {
+        let transformations = transformations(&[
+            ("Vehicle.CurrentLocation.*", Transformation::Round(2)),
+            ("Vehicle.Speed", Transformation::Quantise(5.0)),
+        ]);
+        assert_eq!(
+            transformations.apply(
+                "Vehicle.CurrentLocation.Latitude",
+                DataValue::Double(48.137154)
+            ),
+            DataValue::Double(48.14)
+        );
+        assert_eq!(
+            transformations.apply("Vehicle.Speed", DataValue::Float(42.0)),
+            DataValue::Float(40.0)
+        );
+        assert_eq!(
+            transformations.apply("Vehicle.Speed", DataValue::Uint32(48)),
+            DataValue::Uint32(50)
+        );
+        assert_eq!(
+            transformations.apply("Vehicle.Width", DataValue::Double(1.234)),
+            DataValue::Double(1.234)
+        );
+
+        // Too many decimals to round to leave values as they are
+        let transformations = Transformations::new(vec![(
+            glob::Matcher::new("Vehicle.Speed").expect("pattern should be valid"),
+            Transformation::Round(400),
+        )]);
+        assert_eq!(
+            transformations.apply("Vehicle.Speed", DataValue::Double(1.234)),
+            DataValue::Double(1.234)
+        );
+    }
+
+    #[test]
+    fn test_hash_and_redact() {
+        let transformations = transformations(&[
+            (
+                "Vehicle.VehicleIdentification.VIN",
+                Transformation::Hash("secret".to_owned()),
+            ),
+            ("Vehicle.Cabin.**", Transformation::Redact),
+        ]);
+        let hashed = transformations.apply(
+            "Vehicle.VehicleIdentification.VIN",
+            DataValue::String("WBA12345678901234".to_owned()),
+        );
+        match &hashed {
+            DataValue::String(hash) => {
+                assert_eq!(hash.len(), 64);
+                assert_ne!(hash, "WBA12345678901234");
+            }
+            _ => panic!("expected the hash to be a string"),
+        }
+        // The same value is hashed the same
+        assert_eq!(
+            transformations.apply(
+                "Vehicle.VehicleIdentification.VIN",
+                DataValue::String("WBA12345678901234".to_owned()),
+            ),
+            hashed
+        );
+        assert_eq!(
+            transformations.apply(
+                "Vehicle.Cabin.Seat.Row1.Pos1.Position",
+                DataValue::Uint32(3)
+            ),
+            DataValue::NotAvailable
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let transformations = transformations(&[
+            ("Vehicle.CurrentLocation.*", Transformation::Delay(10)),
+            (
+                "Vehicle.CurrentLocation.Latitude",
+                Transformation::Delay(60),
+            ),
+        ]);
+        assert_eq!(
+            transformations.delay("Vehicle.CurrentLocation.Latitude"),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            transformations.delay("Vehicle.CurrentLocation.Longitude"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(transformations.delay("Vehicle.Speed"), None);
+        assert_eq!(
+            transformations.apply("Vehicle.CurrentLocation.Longitude", DataValue::Double(11.5)),
+            DataValue::Double(11.5)
+        );
+    }
+}
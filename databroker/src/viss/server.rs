@@ -12,15 +12,39 @@
 ********************************************************************************/
 
 use axum::{
-    extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
+use serde::Deserialize;
 use std::{borrow::Cow, net::SocketAddr};
+#[cfg(feature = "tls")]
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "tls")]
+use axum::extract::connect_info::Connected;
+#[cfg(feature = "tls")]
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+#[cfg(feature = "tls")]
+use tokio_rustls::{rustls::ServerConfig, server::TlsStream, TlsAcceptor};
 
 use tracing::{debug, error, info, trace};
 
+#[cfg(feature = "tls")]
+use futures::SinkExt;
 use futures::{channel::mpsc, Sink};
 use futures::{stream::StreamExt, Stream};
 
@@ -28,28 +52,68 @@ use crate::authorization::Authorization;
 use crate::broker;
 
 use super::v2::{self, server::Viss};
+use super::v3;
+
+#[cfg(feature = "tls")]
+pub enum ServerTLS {
+    Disabled,
+    Enabled { tls_config: Arc<ServerConfig> },
+}
 
 #[derive(Clone)]
 struct AppState {
     broker: broker::DataBroker,
     authorization: Authorization,
+    authorization_mode: v2::AuthorizationMode,
+    actuation_fallback: v2::ActuationFallback,
+}
+
+impl AppState {
+    fn server(self) -> v2::server::Server {
+        v2::server::Server::new(self.broker, self.authorization)
+            .with_authorization_mode(self.authorization_mode)
+            .with_actuation_fallback(self.actuation_fallback)
+    }
 }
 
 pub async fn serve(
     addr: impl Into<std::net::SocketAddr>,
     broker: broker::DataBroker,
-    // #[cfg(feature = "tls")] server_tls: ServerTLS,
+    #[cfg(feature = "tls")] server_tls: ServerTLS,
     authorization: Authorization,
+    authorization_mode: v2::AuthorizationMode,
+    actuation_fallback: v2::ActuationFallback,
     // signal: F
 ) -> Result<(), Box<dyn std::error::Error>> {
     let app = Router::new()
         .route("/", get(handle_upgrade))
+        .route("/*path", get(handle_http_get).post(handle_http_set))
         .with_state(AppState {
             broker,
             authorization,
+            authorization_mode,
+            actuation_fallback,
         });
 
     let addr = addr.into();
+
+    #[cfg(feature = "tls")]
+    if let ServerTLS::Enabled { tls_config } = server_tls {
+        let listener = TcpListener::bind(&addr).await.map_err(|err| {
+            error!("Failed to bind address {addr}: {err}");
+            err
+        })?;
+
+        info!("VISSv2 (secure websocket) service listening on {}", addr);
+        return axum::Server::builder(hyper::server::accept::from_stream(accept_tls(
+            listener,
+            TlsAcceptor::from(tls_config),
+        )))
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .map_err(|e| e.into());
+    }
+
     let builder = axum::Server::try_bind(&addr).map_err(|err| {
         error!("Failed to bind address {addr}: {err}");
         err
@@ -62,42 +126,162 @@ pub async fn serve(
         .map_err(|e| e.into())
 }
 
-// Handle upgrade request
+/// Build the TLS configuration of the server from its PEM encoded
+/// certificate (chain) and private key.
+#[cfg(feature = "tls")]
+pub fn tls_config(cert: &[u8], key: &[u8]) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let certs = rustls_pemfile::certs(&mut &cert[..]).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut &key[..])?
+        .ok_or("No private key found in TLS private key file")?;
+    Ok(ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?)
+}
+
+// Accept connections and perform their TLS handshakes concurrently, so a slow
+// client does not hold up the others
+#[cfg(feature = "tls")]
+fn accept_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> mpsc::Receiver<Result<TlsConnection, io::Error>> {
+    let (sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    error!("Failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let mut sender = sender.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let _ = sender.send(Ok(TlsConnection { stream, addr })).await;
+                    }
+                    Err(err) => debug!("TLS handshake with {addr} failed: {err}"),
+                }
+            });
+        }
+    });
+    receiver
+}
+
+// TLS stream of a client, along with its address
+#[cfg(feature = "tls")]
+struct TlsConnection {
+    stream: TlsStream<TcpStream>,
+    addr: SocketAddr,
+}
+
+#[cfg(feature = "tls")]
+impl Connected<&TlsConnection> for SocketAddr {
+    fn connect_info(target: &TlsConnection) -> Self {
+        target.addr
+    }
+}
+
+#[cfg(feature = "tls")]
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+// Handle upgrade request. If authorized per connection, a bearer token
+// given on upgrade authorizes the connection.
 async fn handle_upgrade(
     ws: WebSocketUpgrade,
     axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
     axum::extract::State(state): axum::extract::State<AppState>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+) -> Response {
     debug!("Received websocket upgrade request");
-    ws.protocols(["VISSv2"])
-        .on_upgrade(move |socket| handle_websocket(socket, addr, state.broker, state.authorization))
+    let authorization_mode = state.authorization_mode;
+    let server = state.server();
+    if let (v2::AuthorizationMode::PerConnection, Some(token)) =
+        (authorization_mode, http_authorization(&headers))
+    {
+        if let Err(error) = server.authorize_connection(&token) {
+            return http_error(v2::GenericErrorResponse {
+                action: None,
+                request_id: None,
+                error,
+            });
+        }
+    }
+    ws.protocols(["VISSv2", v3::SUBPROTOCOL])
+        .on_upgrade(move |socket| handle_websocket(socket, addr, server))
+}
+
+// The VISS version spoken on a websocket
+#[derive(Clone, Copy)]
+enum Protocol {
+    V2,
+    V3,
+}
+
+impl Protocol {
+    fn serialize(self, response: impl v2::Response) -> Result<String, serde_json::Error> {
+        match self {
+            Protocol::V2 => serde_json::to_string(&response),
+            Protocol::V3 => {
+                serde_json::to_string(&v3::response_from_v2(serde_json::to_value(response)?))
+            }
+        }
+    }
 }
 
 // Handle websocket (one per connection)
-async fn handle_websocket(
-    socket: WebSocket,
-    addr: SocketAddr,
-    broker: broker::DataBroker,
-    authorization: Authorization,
-) {
-    let valid_subprotocol = match socket.protocol() {
+async fn handle_websocket(socket: WebSocket, addr: SocketAddr, server: v2::server::Server) {
+    let protocol = match socket.protocol() {
         Some(subprotocol) => match subprotocol.to_str() {
             Ok("VISSv2") => {
                 debug!("VISSv2 requested");
-                true
+                Some(Protocol::V2)
+            }
+            Ok(v3::SUBPROTOCOL) => {
+                debug!("VISSv3 requested");
+                Some(Protocol::V3)
             }
             Ok(_) | Err(_) => {
                 debug!("Unsupported websocket subprotocol");
-                false
+                None
             }
         },
         None => {
             debug!("Websocket subprotocol not specified, defaulting to VISSv2");
-            true
+            Some(Protocol::V2)
         }
     };
     let mut socket = socket;
-    if !valid_subprotocol {
+    let Some(protocol) = protocol else {
         let _ = socket
             .send(Message::Close(Some(CloseFrame {
                 code: axum::extract::ws::close_code::PROTOCOL,
@@ -105,19 +289,19 @@ async fn handle_websocket(
             })))
             .await;
         return;
-    }
+    };
 
     let (write, read) = socket.split();
 
-    handle_viss_v2(write, read, addr, broker, authorization).await;
+    handle_viss(write, read, addr, server, protocol).await;
 }
 
-async fn handle_viss_v2<W, R>(
+async fn handle_viss<W, R>(
     write: W,
     mut read: R,
     client_addr: SocketAddr,
-    broker: broker::DataBroker,
-    authorization: Authorization,
+    server: v2::server::Server,
+    protocol: Protocol,
 ) where
     W: Sink<Message> + Unpin + Send + 'static,
     <W as Sink<Message>>::Error: Send,
@@ -127,7 +311,6 @@ async fn handle_viss_v2<W, R>(
     // single consumer will write to the socket.
     let (sender, receiver) = mpsc::channel::<Message>(10);
 
-    let server = v2::server::Server::new(broker, authorization);
     let mut write_task = tokio::spawn(async move {
         let _ = receiver.map(Ok).forward(write).await;
     });
@@ -140,60 +323,22 @@ async fn handle_viss_v2<W, R>(
 
                     // Handle it
                     let sender = sender.clone();
-                    let serialized_response = match parse_v2_msg(&msg) {
-                        Ok(request) => {
-                            match request {
-                                v2::Request::Get(request) => match server.get(request).await {
-                                    Ok(response) => serialize(response),
-                                    Err(error_response) => serialize(error_response),
-                                },
-                                v2::Request::Set(request) => match server.set(request).await {
-                                    Ok(response) => serialize(response),
-                                    Err(error_response) => serialize(error_response),
-                                },
-                                v2::Request::Subscribe(request) => {
-                                    match server.subscribe(request).await {
-                                        Ok((response, stream)) => {
-                                            // Setup background stream
-                                            let mut background_sender = sender.clone();
-
-                                            tokio::spawn(async move {
-                                                let mut stream = stream;
-                                                while let Some(event) = stream.next().await {
-                                                    let serialized_event = match event {
-                                                        Ok(event) => serialize(event),
-                                                        Err(error_event) => serialize(error_event),
-                                                    };
-
-                                                    if let Ok(text) = serialized_event {
-                                                        debug!("Sending notification: {}", text);
-                                                        if let Err(err) = background_sender
-                                                            .try_send(Message::Text(text))
-                                                        {
-                                                            debug!("Failed to send notification: {err}");
-                                                            if err.is_disconnected() {
-                                                                break;
-                                                            }
-                                                        };
-                                                    }
-                                                }
-                                            });
-
-                                            // Return response
-                                            serialize(response)
-                                        }
-                                        Err(error_response) => serialize(error_response),
-                                    }
-                                }
-                                v2::Request::Unsubscribe(request) => {
-                                    match server.unsubscribe(request).await {
-                                        Ok(response) => serialize(response),
-                                        Err(error_response) => serialize(error_response),
-                                    }
-                                }
+                    let serialized_response = match protocol {
+                        Protocol::V2 => match parse_v2_msg(&msg) {
+                            Ok(request) => {
+                                handle_request(&server, request, &sender, protocol).await
                             }
-                        }
-                        Err(error_response) => serialize(error_response),
+                            Err(error_response) => protocol.serialize(error_response),
+                        },
+                        Protocol::V3 => match v3::parse_msg(&msg) {
+                            Ok(v3::Request::V2(request)) => {
+                                handle_request(&server, request, &sender, protocol).await
+                            }
+                            Ok(v3::Request::Capabilities(request)) => {
+                                protocol.serialize(v3::capabilities(request))
+                            }
+                            Err(error_response) => protocol.serialize(error_response),
+                        },
                     };
 
                     // Send it
@@ -252,7 +397,184 @@ async fn handle_viss_v2<W, R>(
     info!("Websocket connection closed ({})", client_addr);
 }
 
-fn parse_v2_msg(msg: &str) -> Result<v2::Request, v2::GenericErrorResponse> {
+async fn handle_request(
+    server: &v2::server::Server,
+    request: v2::Request,
+    sender: &mpsc::Sender<Message>,
+    protocol: Protocol,
+) -> Result<String, serde_json::Error> {
+    match request {
+        v2::Request::Get(request) => match server.get(request).await {
+            Ok(response) => protocol.serialize(response),
+            Err(error_response) => protocol.serialize(error_response),
+        },
+        v2::Request::Set(request) => match server.set(request).await {
+            Ok(response) => protocol.serialize(response),
+            Err(error_response) => protocol.serialize(error_response),
+        },
+        v2::Request::Subscribe(request) => {
+            match server.subscribe(request).await {
+                Ok((response, stream)) => {
+                    // Setup background stream
+                    let mut background_sender = sender.clone();
+
+                    tokio::spawn(async move {
+                        let mut stream = stream;
+                        while let Some(event) = stream.next().await {
+                            let serialized_event = match event {
+                                Ok(event) => protocol.serialize(event),
+                                Err(error_event) => protocol.serialize(error_event),
+                            };
+
+                            if let Ok(text) = serialized_event {
+                                debug!("Sending notification: {}", text);
+                                if let Err(err) = background_sender.try_send(Message::Text(text)) {
+                                    debug!("Failed to send notification: {err}");
+                                    if err.is_disconnected() {
+                                        break;
+                                    }
+                                };
+                            }
+                        }
+                    });
+
+                    // Return response
+                    protocol.serialize(response)
+                }
+                Err(error_response) => protocol.serialize(error_response),
+            }
+        }
+        v2::Request::Unsubscribe(request) => match server.unsubscribe(request).await {
+            Ok(response) => protocol.serialize(response),
+            Err(error_response) => protocol.serialize(error_response),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct HttpGetParams {
+    // JSON encoded filter, e.g. {"type":"static-metadata"}
+    filter: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HttpSetBody {
+    value: v2::Value,
+}
+
+// Handle get request of the HTTP transport, i.e. `GET /Vehicle/Speed`
+async fn handle_http_get(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(path): axum::extract::Path<String>,
+    Query(params): Query<HttpGetParams>,
+    headers: HeaderMap,
+) -> Response {
+    let request_id = v2::RequestId::new();
+    let filter = match params.filter.as_deref().map(v2::filters_from_str) {
+        Some(Ok(filter)) => filter,
+        Some(Err(_)) => {
+            return http_error(v2::GetErrorResponse {
+                request_id,
+                error: v2::Error::BadRequest {
+                    msg: Some("Invalid filter.".into()),
+                },
+                ts: std::time::SystemTime::now().into(),
+            })
+        }
+        None => Vec::new(),
+    };
+    let request = v2::GetRequest {
+        path: http_path(&path),
+        request_id,
+        authorization: http_authorization(&headers),
+        filter,
+    };
+    debug!("Received HTTP get request: {}", request.path.as_ref());
+
+    let server = state.server();
+    match server.get(request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(error_response) => http_error(error_response),
+    }
+}
+
+// Handle set request of the HTTP transport, i.e. `POST /Vehicle/Speed` with
+// the body `{"value": "100"}`
+async fn handle_http_set(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(path): axum::extract::Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let request_id = v2::RequestId::new();
+    let value = match serde_json::from_str::<HttpSetBody>(&body) {
+        Ok(body) => body.value,
+        Err(_) => {
+            return http_error(v2::SetErrorResponse {
+                request_id,
+                error: v2::Error::BadRequest { msg: None },
+                ts: std::time::SystemTime::now().into(),
+            })
+        }
+    };
+    let request = v2::SetRequest {
+        path: http_path(&path),
+        value,
+        request_id,
+        authorization: http_authorization(&headers),
+        filter: Vec::new(),
+    };
+    debug!("Received HTTP set request: {}", request.path.as_ref());
+
+    let server = state.server();
+    match server.set(request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(error_response) => http_error(error_response),
+    }
+}
+
+// Paths use '/' as delimiter in URLs
+fn http_path(path: &str) -> v2::Path {
+    v2::Path::from(path.trim_matches('/').replace('/', "."))
+}
+
+// The access token is provided as a bearer token
+fn http_authorization(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    Some(value.strip_prefix("Bearer ").unwrap_or(value).to_owned())
+}
+
+fn http_error(response: impl HttpErrorResponse) -> Response {
+    let status = u16::try_from(response.error().number())
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, Json(response)).into_response()
+}
+
+trait HttpErrorResponse: v2::Response {
+    fn error(&self) -> &v2::Error;
+}
+
+impl HttpErrorResponse for v2::GetErrorResponse {
+    fn error(&self) -> &v2::Error {
+        &self.error
+    }
+}
+
+impl HttpErrorResponse for v2::SetErrorResponse {
+    fn error(&self) -> &v2::Error {
+        &self.error
+    }
+}
+
+impl HttpErrorResponse for v2::GenericErrorResponse {
+    fn error(&self) -> &v2::Error {
+        &self.error
+    }
+}
+
+pub(crate) fn parse_v2_msg(msg: &str) -> Result<v2::Request, v2::GenericErrorResponse> {
     let request: v2::Request =
         serde_json::from_str(msg).map_err(|_| {
             match serde_json::from_str::<v2::GenericRequest>(msg) {
@@ -271,6 +593,191 @@ fn parse_v2_msg(msg: &str) -> Result<v2::Request, v2::GenericErrorResponse> {
     Ok(request)
 }
 
-fn serialize(response: impl v2::Response) -> Result<String, serde_json::Error> {
-    serde_json::to_string(&response)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        permissions,
+        types::{ChangeType, DataType},
+    };
+    use futures::SinkExt;
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("body should be readable");
+        serde_json::from_slice(&body).expect("body should be JSON")
+    }
+
+    #[tokio::test]
+    async fn test_http_get_and_set() {
+        let broker = broker::DataBroker::default();
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_entry(
+                "Vehicle.Cabin.Light.IsDomeOn".to_owned(),
+                DataType::Bool,
+                ChangeType::OnChange,
+                broker::EntryType::Actuator,
+                "Dome light".to_owned(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("entry should be added");
+        let state = AppState {
+            broker,
+            authorization: Authorization::Disabled,
+            authorization_mode: v2::AuthorizationMode::default(),
+            actuation_fallback: v2::ActuationFallback::default(),
+        };
+
+        let response = handle_http_set(
+            axum::extract::State(state.clone()),
+            axum::extract::Path("Vehicle/Cabin/Light/IsDomeOn".to_owned()),
+            HeaderMap::new(),
+            r#"{"value": "true"}"#.to_owned(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = handle_http_get(
+            axum::extract::State(state.clone()),
+            axum::extract::Path("Vehicle/Cabin/Light/IsDomeOn".to_owned()),
+            Query(HttpGetParams { filter: None }),
+            HeaderMap::new(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_json(response).await;
+        assert_eq!(body["data"]["path"], "Vehicle.Cabin.Light.IsDomeOn");
+
+        let response = handle_http_get(
+            axum::extract::State(state.clone()),
+            axum::extract::Path("Vehicle/Cabin/Light/Unknown".to_owned()),
+            Query(HttpGetParams { filter: None }),
+            HeaderMap::new(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["reason"], "invalid_path");
+
+        let response = handle_http_set(
+            axum::extract::State(state),
+            axum::extract::Path("Vehicle/Cabin/Light/IsDomeOn".to_owned()),
+            HeaderMap::new(),
+            "true".to_owned(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_http_authorization() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(http_authorization(&headers), None);
+        headers.insert(
+            header::AUTHORIZATION,
+            "Bearer token".parse().expect("header should be valid"),
+        );
+        assert_eq!(http_authorization(&headers), Some("token".to_owned()));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_config() {
+        let cert = include_bytes!("../../../certificates/Server.pem");
+        let key = include_bytes!("../../../certificates/Server.key");
+        assert!(tls_config(cert, key).is_ok());
+        // The certificate is not a private key
+        assert!(tls_config(cert, cert).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_v3() {
+        let broker = broker::DataBroker::default();
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_entry(
+                "Vehicle.Speed".to_owned(),
+                DataType::Float,
+                ChangeType::Continuous,
+                broker::EntryType::Sensor,
+                "Speed".to_owned(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("entry should be added");
+        let state = AppState {
+            broker,
+            authorization: Authorization::Disabled,
+            authorization_mode: v2::AuthorizationMode::default(),
+            actuation_fallback: v2::ActuationFallback::default(),
+        };
+
+        let (write, mut responses) = mpsc::channel::<Message>(10);
+        let (mut requests, read) = mpsc::channel::<Result<Message, axum::Error>>(10);
+        let addr = "127.0.0.1:0".parse().expect("address should be valid");
+        tokio::spawn(handle_viss(write, read, addr, state.server(), Protocol::V3));
+
+        let response = exchange(
+            &mut requests,
+            &mut responses,
+            r#"{"action": "get", "path": "Vehicle.Unknown", "requestId": "1"}"#,
+        )
+        .await;
+        assert_eq!(response["error"]["reason"], "invalid_path");
+        assert_eq!(
+            response["error"]["description"],
+            "The specified data path does not exist."
+        );
+
+        let response = exchange(
+            &mut requests,
+            &mut responses,
+            r#"{"action": "get", "path": "Vehicle", "requestId": "2",
+                "filter": {"variant": "metadata", "parameter": "0"}}"#,
+        )
+        .await;
+        assert_eq!(
+            response["metadata"],
+            serde_json::json!({"Vehicle": {"type": "branch", "description": ""}})
+        );
+
+        let response = exchange(
+            &mut requests,
+            &mut responses,
+            r#"{"action": "get", "path": "Vehicle", "requestId": "3",
+                "filter": {"variant": "capabilities"}}"#,
+        )
+        .await;
+        assert_eq!(response["requestId"], "3");
+        assert_eq!(
+            response["capabilities"]["protocols"],
+            serde_json::json!(["VISSv2", "VISS-v3.0"])
+        );
+    }
+
+    async fn exchange(
+        requests: &mut mpsc::Sender<Result<Message, axum::Error>>,
+        responses: &mut mpsc::Receiver<Message>,
+        request: &str,
+    ) -> serde_json::Value {
+        requests
+            .send(Ok(Message::Text(request.to_owned())))
+            .await
+            .expect("request should be sent");
+        match tokio::time::timeout(std::time::Duration::from_secs(1), responses.next()).await {
+            Ok(Some(Message::Text(text))) => {
+                serde_json::from_str(&text).expect("response should be JSON")
+            }
+            _ => panic!("expected a response in time"),
+        }
+    }
 }
@@ -16,7 +16,8 @@ use std::{convert::TryFrom, time::SystemTime};
 use crate::broker;
 
 use super::types::{
-    ActuatorEntry, AttributeEntry, DataPoint, DataType, MetadataEntry, SensorEntry, Value,
+    ActuatorEntry, AttributeEntry, DataPoint, DataPoints, DataType, MetadataEntry, SensorEntry,
+    Value,
 };
 
 pub enum Error {
@@ -32,6 +33,18 @@ impl From<broker::Datapoint> for DataPoint {
     }
 }
 
+impl From<broker::Datapoint> for DataPoints {
+    fn from(dp: broker::Datapoint) -> Self {
+        DataPoints::One(dp.into())
+    }
+}
+
+impl From<Vec<broker::Datapoint>> for DataPoints {
+    fn from(dps: Vec<broker::Datapoint>) -> Self {
+        DataPoints::Many(dps.into_iter().map(DataPoint::from).collect())
+    }
+}
+
 impl TryFrom<Value> for String {
     type Error = Error;
 
@@ -320,31 +333,37 @@ impl From<broker::DataType> for DataType {
 }
 impl From<&broker::Metadata> for MetadataEntry {
     fn from(metadata: &broker::Metadata) -> Self {
+        let datatype = Some(metadata.data_type.clone().into());
+        let description = Some(metadata.description.clone());
+        let unit = metadata.unit.clone();
+        let allowed = metadata.allowed.clone().map(|allowed| allowed.into());
+        let min = metadata.min.clone().map(|min| min.into());
+        let max = metadata.max.clone().map(|max| max.into());
         match metadata.entry_type {
             broker::EntryType::Sensor => MetadataEntry::Sensor(SensorEntry {
-                datatype: metadata.data_type.clone().into(),
-                description: metadata.description.clone(),
+                datatype,
+                description,
                 comment: None,
-                unit: None,
-                allowed: metadata.allowed.clone().map(|allowed| allowed.into()),
-                min: None,
-                max: None,
+                unit,
+                allowed,
+                min,
+                max,
             }),
             broker::EntryType::Attribute => MetadataEntry::Attribute(AttributeEntry {
-                datatype: metadata.data_type.clone().into(),
-                description: metadata.description.clone(),
-                unit: None,
-                allowed: metadata.allowed.clone().map(|allowed| allowed.into()),
+                datatype,
+                description,
+                unit,
+                allowed,
                 default: None, // TODO: Add to metadata
             }),
             broker::EntryType::Actuator => MetadataEntry::Actuator(ActuatorEntry {
-                description: metadata.description.clone(),
+                datatype,
+                description,
                 comment: None,
-                datatype: metadata.data_type.clone().into(),
-                unit: None,
-                allowed: metadata.allowed.clone().map(|allowed| allowed.into()),
-                min: None,
-                max: None,
+                unit,
+                allowed,
+                min,
+                max,
             }),
         }
     }
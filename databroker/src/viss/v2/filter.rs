@@ -0,0 +1,461 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use crate::{broker::Datapoint, types::DataValue};
+
+use super::types::{Error, Filter, LogicOp};
+
+/// The timebased, range and change filters of a subscription.
+#[derive(Default)]
+pub struct SubscriptionFilter {
+    pub period: Option<Duration>,
+    range: Vec<(LogicOp, f64)>,
+    change: Option<(LogicOp, f64)>,
+    // The last notified value of each path, used by the change filter
+    notified: HashMap<String, DataValue>,
+}
+
+impl SubscriptionFilter {
+    pub fn new(filters: &[Filter]) -> Result<Self, Error> {
+        let mut subscription_filter = Self::default();
+        for filter in filters {
+            match filter {
+                Filter::Paths(_) => {}
+                Filter::Timebased(filter) => {
+                    let period = filter
+                        .parameter
+                        .period
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|period| *period > 0)
+                        .ok_or_else(|| Error::BadRequest {
+                            msg: Some(
+                                "The period must be a positive number of milliseconds.".into(),
+                            ),
+                        })?;
+                    subscription_filter.period = Some(Duration::from_millis(period));
+                }
+                Filter::Range(filter) => {
+                    for parameter in &filter.parameter {
+                        subscription_filter
+                            .range
+                            .push((parameter.logic_op, number(&parameter.boundary)?));
+                    }
+                }
+                Filter::Change(filter) => {
+                    subscription_filter.change =
+                        Some((filter.parameter.logic_op, number(&filter.parameter.diff)?));
+                }
+                Filter::StaticMetadata(_) | Filter::History(_) | Filter::Curvelog(_) => {
+                    return Err(Error::BadRequest {
+                        msg: Some("The filter is not supported by subscribe requests.".into()),
+                    })
+                }
+            }
+        }
+        Ok(subscription_filter)
+    }
+
+    /// Whether a value of the path is to be notified, in which case it is
+    /// remembered as the last notified value of the path.
+    ///
+    /// Values that are not numeric are never within a range and are
+    /// considered changed whenever they differ from the last notified value.
+    pub fn notify(&mut self, path: &str, value: &DataValue) -> bool {
+        let within_range = self.range.iter().all(|(logic_op, boundary)| {
+            as_f64(value).is_some_and(|value| compare(*logic_op, value, *boundary))
+        });
+        if !within_range {
+            return false;
+        }
+
+        if let Some((logic_op, diff)) = self.change {
+            if let Some(notified) = self.notified.get(path) {
+                let changed = match (as_f64(value), as_f64(notified)) {
+                    (Some(value), Some(notified)) => compare(logic_op, value - notified, diff),
+                    _ => value != notified,
+                };
+                if !changed {
+                    return false;
+                }
+            }
+            self.notified.insert(path.to_owned(), value.clone());
+        }
+        true
+    }
+}
+
+/// The history and curvelog filters of a get request.
+pub struct History {
+    pub since: SystemTime,
+    curvelog: Option<Curvelog>,
+}
+
+struct Curvelog {
+    max_err: f64,
+    buf_size: Option<usize>,
+}
+
+impl History {
+    /// The history requested by the filters, if any.
+    pub fn new(filters: &[Filter]) -> Result<Option<Self>, Error> {
+        let mut period = None;
+        let mut curvelog = None;
+        for filter in filters {
+            match filter {
+                Filter::History(filter) => period = Some(parse_duration(&filter.parameter)?),
+                Filter::Curvelog(filter) => {
+                    let max_err = number(&filter.parameter.maxerr)?;
+                    if max_err < 0.0 {
+                        return Err(Error::BadRequest {
+                            msg: Some("The maximum error must not be negative.".into()),
+                        });
+                    }
+                    let buf_size = match &filter.parameter.bufsize {
+                        Some(buf_size) => Some(
+                            buf_size
+                                .parse::<usize>()
+                                .ok()
+                                .filter(|buf_size| *buf_size > 1)
+                                .ok_or_else(|| Error::BadRequest {
+                                    msg: Some(
+                                        "The buffer size must be a number greater than one.".into(),
+                                    ),
+                                })?,
+                        ),
+                        None => None,
+                    };
+                    curvelog = Some(Curvelog { max_err, buf_size });
+                }
+                _ => {}
+            }
+        }
+        match period {
+            Some(period) => Ok(Some(Self {
+                since: SystemTime::now()
+                    .checked_sub(period)
+                    .unwrap_or(SystemTime::UNIX_EPOCH),
+                curvelog,
+            })),
+            None if curvelog.is_some() => Err(Error::BadRequest {
+                msg: Some("The curvelog filter requires a history filter.".into()),
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// The datapoints of the history, oldest first, compressed if requested.
+    pub fn apply(&self, datapoints: Vec<Datapoint>) -> Vec<Datapoint> {
+        match &self.curvelog {
+            Some(curvelog) => curvelog.compress(datapoints),
+            None => datapoints,
+        }
+    }
+}
+
+impl Curvelog {
+    // Numeric values are reduced to those needed to linearly interpolate all
+    // others within the maximum error, other values to those that differ from
+    // the previous one. The first and the last value are always kept.
+    fn compress(&self, datapoints: Vec<Datapoint>) -> Vec<Datapoint> {
+        let keep = match datapoints
+            .iter()
+            .map(|datapoint| {
+                let ts = datapoint
+                    .ts
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                as_f64(&datapoint.value).map(|value| (ts, value))
+            })
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(points) => simplify(&points, self.max_err),
+            None => (0..datapoints.len())
+                .map(|i| {
+                    i == 0
+                        || i == datapoints.len() - 1
+                        || datapoints[i].value != datapoints[i - 1].value
+                })
+                .collect(),
+        };
+        let mut compressed: Vec<Datapoint> = datapoints
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(datapoint, keep)| keep.then_some(datapoint))
+            .collect();
+        if let Some(buf_size) = self.buf_size {
+            let excess = compressed.len().saturating_sub(buf_size);
+            compressed.drain(..excess);
+        }
+        compressed
+    }
+}
+
+// Douglas-Peucker line simplification, where the error of a point is the
+// difference of its value to the value interpolated at its time.
+fn simplify(points: &[(f64, f64)], max_err: f64) -> Vec<bool> {
+    let mut keep = vec![false; points.len()];
+    if points.is_empty() {
+        return keep;
+    }
+    let last = points.len() - 1;
+    keep[0] = true;
+    keep[last] = true;
+
+    let mut segments = vec![(0, last)];
+    while let Some((start, end)) = segments.pop() {
+        let (t0, v0) = points[start];
+        let (t1, v1) = points[end];
+        let interpolate = |t: f64| {
+            if t1 > t0 {
+                v0 + (v1 - v0) * (t - t0) / (t1 - t0)
+            } else {
+                v0
+            }
+        };
+        let worst = (start + 1..end)
+            .map(|i| (i, (points[i].1 - interpolate(points[i].0)).abs()))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((i, err)) = worst {
+            if err > max_err {
+                keep[i] = true;
+                segments.push((start, i));
+                segments.push((i, end));
+            }
+        }
+    }
+    keep
+}
+
+// Durations of weeks, days, hours, minutes and seconds, e.g. "P1DT12H" or
+// "PT0.5S". Years and months are not supported, as their length varies.
+fn parse_duration(duration: &str) -> Result<Duration, Error> {
+    let error = || Error::BadRequest {
+        msg: Some(format!(
+            "'{duration}' is not a supported ISO 8601 duration."
+        )),
+    };
+    let rest = duration.strip_prefix('P').ok_or_else(error)?;
+    let (date, time) = match rest.split_once('T') {
+        Some((_, "")) => return Err(error()),
+        Some((date, time)) => (date, time),
+        None => (rest, ""),
+    };
+
+    let mut seconds = 0.0;
+    let mut empty = true;
+    for (mut part, units) in [
+        (date, &[('W', 604800.0), ('D', 86400.0)][..]),
+        (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)][..]),
+    ] {
+        for (unit, unit_seconds) in units {
+            if let Some((number, rest)) = part.split_once(*unit) {
+                let number = number
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite() && *number >= 0.0)
+                    .ok_or_else(error)?;
+                seconds += number * unit_seconds;
+                part = rest;
+                empty = false;
+            }
+        }
+        if !part.is_empty() {
+            return Err(error());
+        }
+    }
+    if empty {
+        return Err(error());
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| error())
+}
+
+fn number(value: &str) -> Result<f64, Error> {
+    value.parse().map_err(|_| Error::BadRequest {
+        msg: Some(format!("Filter value '{value}' is not a number.")),
+    })
+}
+
+fn compare(logic_op: LogicOp, value: f64, other: f64) -> bool {
+    match logic_op {
+        LogicOp::Eq => value == other,
+        LogicOp::Ne => value != other,
+        LogicOp::Gt => value > other,
+        LogicOp::Gte => value >= other,
+        LogicOp::Lt => value < other,
+        LogicOp::Lte => value <= other,
+    }
+}
+
+fn as_f64(value: &DataValue) -> Option<f64> {
+    match value {
+        DataValue::Int32(value) => Some(f64::from(*value)),
+        DataValue::Int64(value) => Some(*value as f64),
+        DataValue::Uint32(value) => Some(f64::from(*value)),
+        DataValue::Uint64(value) => Some(*value as f64),
+        DataValue::Float(value) => Some(f64::from(*value)),
+        DataValue::Double(value) => Some(*value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viss::v2::filters_from_str;
+
+    fn subscription_filter(json: &str) -> SubscriptionFilter {
+        SubscriptionFilter::new(&filters_from_str(json).expect("filters should parse"))
+            .map_err(|_| "invalid filter")
+            .expect("filters should be valid")
+    }
+
+    #[test]
+    fn test_range() {
+        let mut filter = subscription_filter(
+            r#"{"type": "range", "parameter": [
+                {"logic-op": "gte", "boundary": "50"},
+                {"logic-op": "lt", "boundary": "100"}
+            ]}"#,
+        );
+        assert!(!filter.notify("Vehicle.Speed", &DataValue::Float(49.9)));
+        assert!(filter.notify("Vehicle.Speed", &DataValue::Float(50.0)));
+        assert!(filter.notify("Vehicle.Speed", &DataValue::Uint32(99)));
+        assert!(!filter.notify("Vehicle.Speed", &DataValue::Uint32(100)));
+        assert!(!filter.notify("Vehicle.Speed", &DataValue::NotAvailable));
+    }
+
+    #[test]
+    fn test_change() {
+        let mut filter = subscription_filter(
+            r#"{"type": "change", "parameter": {"logic-op": "gt", "diff": "10"}}"#,
+        );
+        // The first value is always notified
+        assert!(filter.notify("Vehicle.Speed", &DataValue::Float(20.0)));
+        assert!(!filter.notify("Vehicle.Speed", &DataValue::Float(30.0)));
+        assert!(filter.notify("Vehicle.Speed", &DataValue::Float(30.5)));
+        // Compared to the last notified value
+        assert!(!filter.notify("Vehicle.Speed", &DataValue::Float(40.0)));
+
+        assert!(filter.notify("Vehicle.IsMoving", &DataValue::Bool(true)));
+        assert!(!filter.notify("Vehicle.IsMoving", &DataValue::Bool(true)));
+        assert!(filter.notify("Vehicle.IsMoving", &DataValue::Bool(false)));
+    }
+
+    fn datapoints(values: &[f64]) -> Vec<Datapoint> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Datapoint {
+                ts: SystemTime::UNIX_EPOCH + Duration::from_secs(i as u64),
+                source_ts: None,
+                value: DataValue::Double(*value),
+            })
+            .collect()
+    }
+
+    fn values(datapoints: &[Datapoint]) -> Vec<DataValue> {
+        datapoints
+            .iter()
+            .map(|datapoint| datapoint.value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT30S").ok(), Some(Duration::from_secs(30)));
+        assert_eq!(
+            parse_duration("P1DT2H3M").ok(),
+            Some(Duration::from_secs(86400 + 7200 + 180))
+        );
+        assert_eq!(
+            parse_duration("P2W").ok(),
+            Some(Duration::from_secs(2 * 604800))
+        );
+        assert_eq!(
+            parse_duration("PT0.5S").ok(),
+            Some(Duration::from_millis(500))
+        );
+        for duration in ["", "P", "PT", "30S", "P1Y", "P1M", "PT1D", "P1D2W", "PT-1S"] {
+            assert!(parse_duration(duration).is_err(), "{duration}");
+        }
+    }
+
+    #[test]
+    fn test_curvelog() {
+        let history = History::new(
+            &filters_from_str(
+                r#"[{"type": "history", "parameter": "PT1H"},
+                    {"type": "curvelog", "parameter": {"maxerr": "0.5"}}]"#,
+            )
+            .expect("filters should parse"),
+        )
+        .map_err(|_| "invalid filter")
+        .expect("filters should be valid")
+        .expect("history should be requested");
+
+        // Values on a straight line are interpolated
+        let compressed = history.apply(datapoints(&[0.0, 1.0, 2.0, 3.0, 10.0, 10.2, 10.0]));
+        assert_eq!(
+            values(&compressed),
+            [0.0, 3.0, 10.0, 10.0].map(DataValue::Double)
+        );
+
+        let curvelog = Curvelog {
+            max_err: 0.0,
+            buf_size: Some(2),
+        };
+        let compressed = curvelog.compress(datapoints(&[1.0, 5.0, 1.0, 5.0]));
+        assert_eq!(values(&compressed), [1.0, 5.0].map(DataValue::Double));
+
+        let mut strings = datapoints(&[0.0; 4]);
+        for (datapoint, value) in strings.iter_mut().zip(["a", "a", "b", "b"]) {
+            datapoint.value = DataValue::String(value.into());
+        }
+        let compressed = curvelog.compress(strings);
+        assert_eq!(
+            values(&compressed),
+            ["b", "b"].map(|value| DataValue::String(value.into()))
+        );
+
+        assert!(History::new(
+            &filters_from_str(r#"{"type": "curvelog", "parameter": {"maxerr": "0.5"}}"#)
+                .expect("filters should parse")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_timebased() {
+        let filter = subscription_filter(
+            r#"[{"type": "paths", "parameter": "*"}, {"type": "timebased", "parameter": {"period": "500"}}]"#,
+        );
+        assert_eq!(filter.period, Some(Duration::from_millis(500)));
+
+        for json in [
+            r#"{"type": "timebased", "parameter": {"period": "0"}}"#,
+            r#"{"type": "range", "parameter": {"logic-op": "gt", "boundary": "fast"}}"#,
+        ] {
+            assert!(SubscriptionFilter::new(
+                &filters_from_str(json).expect("filters should parse")
+            )
+            .is_err());
+        }
+    }
+}
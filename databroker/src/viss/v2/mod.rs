@@ -12,8 +12,10 @@
 ********************************************************************************/
 
 mod conversions;
+mod filter;
 
 pub(crate) mod server;
 pub(crate) mod types;
 
+pub use server::{ActuationFallback, AuthorizationMode};
 pub use types::*;
@@ -12,34 +12,40 @@
 ********************************************************************************/
 
 use std::{
-    collections::{HashMap, HashSet},
-    convert::TryFrom,
+    collections::{BTreeMap, HashMap, HashSet},
     pin::Pin,
-    sync::Arc,
-    time::SystemTime,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 
 use futures::{
+    future::Either,
     stream::{AbortHandle, Abortable},
     Stream, StreamExt,
 };
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, time::MissedTickBehavior};
 use tracing::warn;
 
 use crate::{
-    authorization::Authorization,
+    audit,
+    authorization::{self, Authorization},
     broker::{self, AuthorizedAccess, UpdateError},
-    permissions::{self, Permissions},
+    glob,
+    permissions::{self, Expiring, ExpiryEvent, Permissions},
 };
 
-use super::{conversions, types::*};
+use super::{
+    conversions,
+    filter::{History, SubscriptionFilter},
+    types::*,
+};
 
 #[tonic::async_trait]
 pub(crate) trait Viss: Send + Sync + 'static {
     async fn get(&self, request: GetRequest) -> Result<GetSuccessResponse, GetErrorResponse>;
     async fn set(&self, request: SetRequest) -> Result<SetSuccessResponse, SetErrorResponse>;
 
-    type SubscribeStream: Stream<Item = Result<SubscriptionEvent, SubscriptionErrorEvent>>
+    type SubscribeStream: Stream<Item = Result<SubscriptionNotification, SubscriptionErrorEvent>>
         + Send
         + 'static;
 
@@ -70,9 +76,51 @@ impl Drop for SubscriptionHandle {
     }
 }
 
+/// How actuators are set that no actuation provider is registered for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ActuationFallback {
+    /// Set their actuator target, as with sdv.databroker.v1
+    #[default]
+    ActuatorTarget,
+    /// Fail with "service unavailable"
+    Error,
+}
+
+/// How the requests of a connection are authorized.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AuthorizationMode {
+    /// Every request carries its own token
+    #[default]
+    PerMessage,
+    /// A token (given on upgrade or by a request) authorizes the requests
+    /// of the connection that follow, until it expires or another token
+    /// replaces it. Subscriptions keep the token they were opened with.
+    PerConnection,
+}
+
+// Failed attempts to authorize a connection, after which further attempts
+// are refused
+const MAX_AUTHORIZATION_ATTEMPTS: u32 = 3;
+
+// The authorization of a connection, if authorized once per connection
+#[derive(Default)]
+struct ConnectionAuthorization {
+    // The token the permissions were decoded from
+    token: Option<String>,
+    permissions: Option<Permissions>,
+    // Generation of the authorization the permissions were decoded with
+    generation: u64,
+    failed_attempts: u32,
+}
+
 pub struct Server {
     broker: broker::DataBroker,
     authorization: Authorization,
+    authorization_mode: AuthorizationMode,
+    connection: Mutex<ConnectionAuthorization>,
+    actuation_fallback: ActuationFallback,
+    // The API requests are recorded as in the audit log
+    api: audit::Api,
     subscriptions: Arc<RwLock<HashMap<SubscriptionId, SubscriptionHandle>>>,
 }
 
@@ -81,9 +129,68 @@ impl Server {
         Self {
             broker,
             authorization,
+            authorization_mode: AuthorizationMode::default(),
+            connection: Mutex::new(ConnectionAuthorization::default()),
+            actuation_fallback: ActuationFallback::default(),
+            api: audit::Api::Viss,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
         }
     }
+
+    pub fn with_authorization_mode(self, authorization_mode: AuthorizationMode) -> Self {
+        Self {
+            authorization_mode,
+            ..self
+        }
+    }
+
+    pub fn with_actuation_fallback(self, actuation_fallback: ActuationFallback) -> Self {
+        Self {
+            actuation_fallback,
+            ..self
+        }
+    }
+
+    /// Record requests as made through another API in the audit log, e.g.
+    /// when VISS requests are bridged from MQTT.
+    pub fn with_api(self, api: audit::Api) -> Self {
+        Self { api, ..self }
+    }
+
+    /// Authorize the requests of the connection that follow with a token,
+    /// which replaces the token the connection was authorized with before.
+    ///
+    /// The decoded permissions are kept until they expire, and are not
+    /// decoded again while the token stays the same, unless the policy or
+    /// the keys verifying tokens have been reloaded since. Once the token has
+    /// been rejected too many times, further attempts are refused.
+    pub fn authorize_connection(&self, token: &str) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("lock should not be poisoned");
+        if connection.failed_attempts >= MAX_AUTHORIZATION_ATTEMPTS {
+            self.audit_authorization_failure("Too many failed authorization attempts");
+            return Err(Error::UnauthorizedTooManyAttempts);
+        }
+        let generation = self.authorization.generation();
+        if connection.token.as_deref() == Some(token)
+            && connection.permissions.is_some()
+            && connection.generation == generation
+        {
+            return Ok(());
+        }
+        match self.decode_token(token) {
+            Ok(permissions) => {
+                connection.token = Some(token.to_owned());
+                connection.permissions = Some(permissions);
+                connection.generation = generation;
+                connection.failed_attempts = 0;
+                Ok(())
+            }
+            Err(error) => {
+                connection.failed_attempts += 1;
+                Err(error)
+            }
+        }
+    }
 }
 
 #[tonic::async_trait]
@@ -91,17 +198,31 @@ impl Viss for Server {
     async fn get(&self, request: GetRequest) -> Result<GetSuccessResponse, GetErrorResponse> {
         let request_id = request.request_id;
 
-        if let Some(Filter::StaticMetadata(_)) = &request.filter {
-            // Authorization not required for metadata, don't bail if an
-            // access token is missing.
-            let broker = self.broker.authorized_access(&permissions::ALLOW_NONE);
-            let metadata = generate_metadata(&broker, request.path.as_ref()).await;
-            return Ok(GetSuccessResponse::Metadata(MetadataResponse {
-                request_id,
-                metadata,
-            }));
+        if let Some(filter) = request.filter.iter().find_map(|filter| match filter {
+            Filter::StaticMetadata(filter) => Some(filter),
+            _ => None,
+        }) {
+            if !self.has_token(&request.authorization) {
+                // Authorization not required for metadata, don't bail if an
+                // access token is missing. Only a token narrows it down.
+                let broker = self.broker.authorized_access(&permissions::ALLOW_NONE);
+                let metadata =
+                    generate_metadata(&broker, None, request.path.as_ref(), &filter.parameter)
+                        .await
+                        .map_err(|error| GetErrorResponse {
+                            request_id: request_id.clone(),
+                            error,
+                            ts: SystemTime::now().into(),
+                        })?;
+                return Ok(GetSuccessResponse::Metadata(MetadataResponse {
+                    request_id,
+                    metadata,
+                }));
+            }
         }
-        let permissions = resolve_permissions(&self.authorization, &request.authorization)
+
+        let permissions = self
+            .resolve_permissions(&request.authorization)
             .map_err(|error| GetErrorResponse {
                 request_id: request_id.clone(),
                 error,
@@ -109,137 +230,253 @@ impl Viss for Server {
             })?;
         let broker = self.broker.authorized_access(&permissions);
 
-        // Get datapoints
-        match broker.get_datapoint_by_path(request.path.as_ref()).await {
-            Ok(datapoint) => {
-                let dp = DataPoint::from(datapoint);
-                Ok(GetSuccessResponse::Data(DataResponse {
-                    request_id,
-                    data: Data::Object(DataObject {
-                        path: request.path,
-                        dp,
-                    }),
-                }))
+        let error_response = |error| GetErrorResponse {
+            request_id: request_id.clone(),
+            error,
+            ts: SystemTime::now().into(),
+        };
+        if request.filter.iter().any(|filter| {
+            !matches!(
+                filter,
+                Filter::StaticMetadata(_)
+                    | Filter::Paths(_)
+                    | Filter::History(_)
+                    | Filter::Curvelog(_)
+            )
+        }) {
+            return Err(error_response(Error::BadRequest {
+                msg: Some("The filter is only supported by subscribe requests.".into()),
+            }));
+        }
+        let history = History::new(&request.filter).map_err(error_response)?;
+
+        if let Some(filter) = request.filter.iter().find_map(|filter| match filter {
+            Filter::StaticMetadata(filter) => Some(filter),
+            _ => None,
+        }) {
+            // Only signals whose metadata may be read are included.
+            let metadata = generate_metadata(
+                &broker,
+                Some(&permissions),
+                request.path.as_ref(),
+                &filter.parameter,
+            )
+            .await
+            .map_err(error_response)?;
+            return Ok(GetSuccessResponse::Metadata(MetadataResponse {
+                request_id,
+                metadata,
+            }));
+        }
+
+        if let Some(filter) = paths_filter(&request.filter) {
+            let matchers = path_matchers(request.path.as_ref(), filter).map_err(error_response)?;
+            let results = broker
+                .filter_map_entries(|entry| {
+                    let metadata = entry.metadata();
+                    if !is_match(&matchers, metadata) {
+                        return None;
+                    }
+                    Some(entry.datapoint().map(|datapoint| DataObject {
+                        path: metadata.path.clone().into(),
+                        dp: datapoint.clone().into(),
+                    }))
+                })
+                .await;
+
+            // Signals that may not be read are left out, unless that
+            // leaves nothing to respond with
+            let mut first_error = None;
+            let mut objects = Vec::new();
+            for result in results {
+                match result {
+                    Ok(object) => objects.push(object),
+                    Err(err) => {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            if objects.is_empty() {
+                return Err(error_response(match first_error {
+                    Some(err) => read_error(err),
+                    None => Error::NotFoundInvalidPath,
+                }));
+            }
+            objects.sort_by(|a, b| a.path.as_ref().cmp(b.path.as_ref()));
+            if let Some(history) = &history {
+                for object in &mut objects {
+                    object.dp = get_history(&broker, object.path.as_ref(), history)
+                        .await
+                        .map_err(|err| error_response(read_error(err)))?;
+                }
             }
+            return Ok(GetSuccessResponse::Data(DataResponse {
+                request_id,
+                data: Data::from_objects(objects),
+            }));
+        }
+
+        // Get datapoints
+        let dp = match &history {
+            Some(history) => get_history(&broker, request.path.as_ref(), history).await,
+            None => broker
+                .get_datapoint_by_path(request.path.as_ref())
+                .await
+                .map(DataPoints::from),
+        };
+        match dp {
+            Ok(dp) => Ok(GetSuccessResponse::Data(DataResponse {
+                request_id,
+                data: Data::Object(DataObject {
+                    path: request.path,
+                    dp,
+                }),
+            })),
             Err(err) => Err(GetErrorResponse {
                 request_id,
                 ts: SystemTime::now().into(),
-                error: match err {
-                    broker::ReadError::NotFound => Error::NotFoundInvalidPath,
-                    broker::ReadError::PermissionDenied => Error::Forbidden,
-                    broker::ReadError::PermissionExpired => Error::UnauthorizedTokenExpired,
-                },
+                error: read_error(err),
             }),
         }
     }
 
     async fn set(&self, request: SetRequest) -> Result<SetSuccessResponse, SetErrorResponse> {
         let request_id = request.request_id;
-        let permissions = resolve_permissions(&self.authorization, &request.authorization)
+        let permissions = self
+            .resolve_permissions(&request.authorization)
             .map_err(|error| SetErrorResponse {
                 request_id: request_id.clone(),
                 error,
                 ts: SystemTime::now().into(),
             })?;
-        let broker = self.broker.authorized_access(&permissions);
+        let broker = self
+            .broker
+            .authorized_access(&permissions)
+            .with_api(self.api);
 
-        match broker.get_metadata_by_path(request.path.as_ref()).await {
-            Some(metadata) => {
-                if metadata.entry_type != broker::EntryType::Actuator {
-                    return Err(SetErrorResponse {
-                        request_id,
-                        error: Error::UnauthorizedReadOnly,
-                        ts: SystemTime::now().into(),
-                    });
-                }
+        let error_response = |error| SetErrorResponse {
+            request_id: request_id.clone(),
+            error,
+            ts: SystemTime::now().into(),
+        };
 
-                let value = request.value;
-
-                let update = value
-                    .try_into_type(&metadata.data_type)
-                    .map(|actuator_target| broker::EntryUpdate {
-                        path: None,
-                        datapoint: None,
-                        actuator_target: Some(Some(broker::Datapoint {
-                            value: actuator_target,
-                            source_ts: None,
-                            ts: SystemTime::now(),
-                        })),
-                        entry_type: None,
-                        data_type: None,
-                        description: None,
-                        min: None,
-                        max: None,
-                        allowed: None,
-                        unit: None,
+        if request
+            .filter
+            .iter()
+            .any(|filter| !matches!(filter, Filter::Paths(_)))
+        {
+            return Err(error_response(Error::BadRequest {
+                msg: Some("The filter is not supported by set requests.".into()),
+            }));
+        }
+
+        let entries = match paths_filter(&request.filter) {
+            Some(filter) => {
+                let matchers =
+                    path_matchers(request.path.as_ref(), filter).map_err(error_response)?;
+                broker
+                    .filter_map_entries(|entry| {
+                        let metadata = entry.metadata();
+                        is_match(&matchers, metadata).then(|| metadata.clone())
                     })
-                    .map_err(|err| SetErrorResponse {
-                        request_id: request_id.clone(),
-                        error: match err {
-                            conversions::Error::ParseError => Error::BadRequest {
-                                msg: Some(format!(
-                                    "Failed to parse the value as a {}",
-                                    DataType::from(metadata.data_type.clone())
-                                )),
-                            },
+                    .await
+            }
+            None => broker
+                .get_metadata_by_path(request.path.as_ref())
+                .await
+                .into_iter()
+                .collect(),
+        };
+        if entries.is_empty() {
+            return Err(error_response(Error::NotFoundInvalidPath));
+        }
+
+        let mut actuations = Vec::new();
+        let mut updates = Vec::new();
+        for metadata in entries {
+            let value = request
+                .value
+                .clone()
+                .try_into_type(&metadata.data_type)
+                .map_err(|err| {
+                    error_response(match err {
+                        conversions::Error::ParseError => Error::BadRequest {
+                            msg: Some(format!(
+                                "Failed to parse the value as a {}",
+                                DataType::from(metadata.data_type.clone())
+                            )),
                         },
-                        ts: SystemTime::now().into(),
-                    })?;
+                    })
+                })?;
+            let datapoint = broker::Datapoint {
+                value,
+                source_ts: None,
+                ts: SystemTime::now(),
+            };
 
-                let updates = vec![(metadata.id, update)];
-                match broker.update_entries(updates).await {
-                    Ok(()) => Ok(SetSuccessResponse {
-                        request_id,
-                        ts: SystemTime::now().into(),
-                    }),
-                    Err(errors) => {
-                        let error = if let Some((_, error)) = errors.first() {
-                            match error {
-                                UpdateError::NotFound => Error::NotFoundInvalidPath,
-                                UpdateError::WrongType => Error::BadRequest {
-                                    msg: Some("Wrong data type.".into()),
-                                },
-                                UpdateError::OutOfBoundsAllowed => Error::BadRequest {
-                                    msg: Some("Value out of allowed bounds.".into()),
-                                },
-                                UpdateError::OutOfBoundsMinMax => Error::BadRequest {
-                                    msg: Some("Value out of min/max bounds.".into()),
-                                },
-                                UpdateError::OutOfBoundsType => Error::BadRequest {
-                                    msg: Some("Value out of type bounds.".into()),
-                                },
-                                UpdateError::UnsupportedType => Error::BadRequest {
-                                    msg: Some("Unsupported data type.".into()),
-                                },
-                                UpdateError::PermissionDenied => Error::Forbidden,
-                                UpdateError::PermissionExpired => Error::UnauthorizedTokenExpired,
-                            }
-                        } else {
-                            Error::InternalServerError
-                        };
-
-                        Err(SetErrorResponse {
-                            request_id,
-                            error,
-                            ts: SystemTime::now().into(),
-                        })
+            match metadata.entry_type {
+                broker::EntryType::Actuator => {
+                    if broker.has_actuation_provider(metadata.id).await {
+                        actuations.push(broker::ActuationChange {
+                            id: metadata.id,
+                            data_value: datapoint.value,
+                        });
+                    } else if self.actuation_fallback == ActuationFallback::ActuatorTarget {
+                        updates.push((
+                            metadata.id,
+                            broker::EntryUpdate {
+                                actuator_target: Some(Some(datapoint)),
+                                ..Default::default()
+                            },
+                        ));
+                    } else {
+                        return Err(error_response(Error::ServiceUnavailable));
+                    }
+                }
+                broker::EntryType::Sensor | broker::EntryType::Attribute => {
+                    // Only providers may set the values of sensors and attributes
+                    match permissions.can_write_datapoint(&metadata.path) {
+                        Ok(()) => updates.push((
+                            metadata.id,
+                            broker::EntryUpdate {
+                                datapoint: Some(datapoint),
+                                ..Default::default()
+                            },
+                        )),
+                        Err(permissions::PermissionError::Denied) => {
+                            return Err(error_response(Error::UnauthorizedReadOnly))
+                        }
+                        Err(permissions::PermissionError::Expired) => {
+                            return Err(error_response(Error::UnauthorizedTokenExpired))
+                        }
                     }
                 }
             }
-            None => {
-                // Not found
-                Err(SetErrorResponse {
-                    request_id,
-                    error: Error::NotFoundInvalidPath,
-                    ts: SystemTime::now().into(),
+        }
+
+        if !actuations.is_empty() {
+            broker
+                .batch_actuate(actuations)
+                .await
+                .map_err(|(err, msg)| error_response(actuation_error(err, msg)))?;
+        }
+        if !updates.is_empty() {
+            broker.update_entries(updates).await.map_err(|errors| {
+                error_response(match errors.first() {
+                    Some((_, error)) => update_error(error),
+                    None => Error::InternalServerError,
                 })
-            }
+            })?;
         }
+        Ok(SetSuccessResponse {
+            request_id,
+            ts: SystemTime::now().into(),
+        })
     }
 
     type SubscribeStream = Pin<
         Box<
-            dyn Stream<Item = Result<SubscriptionEvent, SubscriptionErrorEvent>>
+            dyn Stream<Item = Result<SubscriptionNotification, SubscriptionErrorEvent>>
                 + Send
                 + Sync
                 + 'static,
@@ -251,7 +488,8 @@ impl Viss for Server {
         request: SubscribeRequest,
     ) -> Result<(SubscribeSuccessResponse, Self::SubscribeStream), SubscribeErrorResponse> {
         let request_id = request.request_id;
-        let permissions = resolve_permissions(&self.authorization, &request.authorization)
+        let permissions = self
+            .resolve_permissions(&request.authorization)
             .map_err(|error| SubscribeErrorResponse {
                 request_id: request_id.clone(),
                 error,
@@ -259,20 +497,71 @@ impl Viss for Server {
             })?;
         let broker = self.broker.authorized_access(&permissions);
 
-        let Some(entries) = broker
-            .get_id_by_path(request.path.as_ref())
-            .await
-            .map(|id| HashMap::from([(id, HashSet::from([broker::Field::Datapoint]))]))
-        else {
-            return Err(SubscribeErrorResponse {
-                request_id,
-                error: Error::NotFoundInvalidPath,
-                ts: SystemTime::now().into(),
-            });
+        let error_response = |error| SubscribeErrorResponse {
+            request_id: request_id.clone(),
+            error,
+            ts: SystemTime::now().into(),
         };
+        let filter = SubscriptionFilter::new(&request.filter).map_err(error_response)?;
+
+        let ids = match paths_filter(&request.filter) {
+            Some(filter) => {
+                let matchers =
+                    path_matchers(request.path.as_ref(), filter).map_err(error_response)?;
+                let results = broker
+                    .filter_map_entries(|entry| {
+                        let metadata = entry.metadata();
+                        is_match(&matchers, metadata)
+                            .then(|| entry.datapoint().map(|_| metadata.id))
+                    })
+                    .await;
+
+                // Signals that may not be read are left out like for get,
+                // unless that leaves nothing to subscribe to
+                let mut first_error = None;
+                let mut ids = Vec::new();
+                for result in results {
+                    match result {
+                        Ok(id) => ids.push(id),
+                        Err(err) => {
+                            first_error.get_or_insert(err);
+                        }
+                    }
+                }
+                match first_error {
+                    Some(err) if ids.is_empty() => return Err(error_response(read_error(err))),
+                    _ => ids,
+                }
+            }
+            None => broker
+                .get_id_by_path(request.path.as_ref())
+                .await
+                .into_iter()
+                .collect(),
+        };
+        if ids.is_empty() {
+            return Err(error_response(Error::NotFoundInvalidPath));
+        }
+        let entries = ids
+            .iter()
+            .map(|id| (*id, HashSet::from([broker::Field::Datapoint])))
+            .collect();
 
         match broker.subscribe(entries, None).await {
             Ok(stream) => {
+                // The history of subscribed signals is kept for later get
+                // requests, as long as they are subscribed to
+                broker.record_history(ids.iter().copied()).await;
+                let recording = HistoryRecording {
+                    broker: self.broker.clone(),
+                    permissions: permissions.clone(),
+                    ids,
+                };
+                let stream = stream.map(move |item| {
+                    let _recording = &recording;
+                    item
+                });
+
                 let subscription_id = SubscriptionId::new();
 
                 let (abort_handle, abort_registration) = AbortHandle::new_pair();
@@ -286,7 +575,13 @@ impl Viss for Server {
                     SubscriptionHandle::from(abort_handle),
                 );
 
-                let stream = convert_to_viss_stream(subscription_id.clone(), stream);
+                let stream = convert_to_viss_stream(subscription_id.clone(), stream, filter);
+                let stream = terminate_on_expiry(
+                    subscription_id.clone(),
+                    stream,
+                    &permissions,
+                    self.subscriptions.clone(),
+                );
 
                 Ok((
                     SubscribeSuccessResponse {
@@ -301,6 +596,7 @@ impl Viss for Server {
                 request_id,
                 error: match err {
                     broker::SubscriptionError::NotFound => Error::NotFoundInvalidPath,
+                    broker::SubscriptionError::PermissionDenied => Error::Forbidden,
                     broker::SubscriptionError::InvalidInput => Error::NotFoundInvalidPath,
                     broker::SubscriptionError::InternalError => Error::InternalServerError,
                     broker::SubscriptionError::InvalidBufferSize => Error::InternalServerError,
@@ -337,59 +633,372 @@ impl Viss for Server {
 
 fn convert_to_viss_stream(
     subscription_id: SubscriptionId,
-    stream: impl Stream<Item = broker::EntryUpdates>,
+    stream: impl Stream<Item = broker::EntryUpdates> + Send + Sync + 'static,
+    mut filter: SubscriptionFilter,
 ) -> impl Stream<Item = Result<SubscriptionEvent, SubscriptionErrorEvent>> {
-    stream.map(move |mut item| {
+    // None if an update is incomplete
+    let datapoints = stream.map(|item| {
+        item.updates
+            .into_iter()
+            .map(|item| match (item.update.path, item.update.datapoint) {
+                (Some(path), Some(datapoint)) => Some((path, datapoint)),
+                (_, _) => None,
+            })
+            .collect::<Option<Vec<_>>>()
+    });
+    let datapoints = match filter.period {
+        Some(period) => Either::Left(sample(datapoints, period)),
+        None => Either::Right(datapoints),
+    };
+
+    datapoints.filter_map(move |datapoints| {
         let ts = SystemTime::now().into();
         let subscription_id = subscription_id.clone();
-        match item.updates.pop() {
-            Some(item) => match (item.update.path, item.update.datapoint) {
-                (Some(path), Some(datapoint)) => Ok(SubscriptionEvent {
-                    subscription_id,
-                    data: Data::Object(DataObject {
+        let event = match datapoints {
+            Some(datapoints) => {
+                let objects: Vec<_> = datapoints
+                    .into_iter()
+                    .filter(|(path, datapoint)| filter.notify(path, &datapoint.value))
+                    .map(|(path, datapoint)| DataObject {
                         path: path.into(),
                         dp: datapoint.into(),
-                    }),
-                    ts,
-                }),
-                (_, _) => Err(SubscriptionErrorEvent {
-                    subscription_id,
-                    error: Error::InternalServerError,
-                    ts,
-                }),
-            },
-            None => Err(SubscriptionErrorEvent {
+                    })
+                    .collect();
+                // Nothing to notify if all values were filtered out
+                (!objects.is_empty()).then(|| {
+                    Ok(SubscriptionEvent {
+                        subscription_id,
+                        data: Data::from_objects(objects),
+                        ts,
+                    })
+                })
+            }
+            None => Some(Err(SubscriptionErrorEvent {
                 subscription_id,
                 error: Error::InternalServerError,
                 ts,
-            }),
+            })),
+        };
+        futures::future::ready(event)
+    })
+}
+
+// Notify the latest values periodically rather than on change, as long as
+// the subscription lasts.
+fn sample(
+    datapoints: impl Stream<Item = Option<Vec<(String, broker::Datapoint)>>> + Send + Sync + 'static,
+    period: Duration,
+) -> impl Stream<Item = Option<Vec<(String, broker::Datapoint)>>> {
+    enum Event {
+        Update(Option<Vec<(String, broker::Datapoint)>>),
+        Tick,
+        End,
+    }
+
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let ticks = futures::stream::unfold(interval, |mut interval| async move {
+        interval.tick().await;
+        Some((Event::Tick, interval))
+    });
+    let updates = datapoints
+        .map(Event::Update)
+        .chain(futures::stream::once(futures::future::ready(Event::End)));
+
+    let mut latest = BTreeMap::new();
+    futures::stream::select(updates, ticks)
+        .take_while(|event| futures::future::ready(!matches!(event, Event::End)))
+        .filter_map(move |event| {
+            let datapoints = match event {
+                Event::Update(Some(datapoints)) => {
+                    latest.extend(datapoints);
+                    None
+                }
+                Event::Update(None) => Some(None),
+                Event::Tick if !latest.is_empty() => Some(Some(
+                    latest
+                        .iter()
+                        .map(|(path, datapoint)| (path.clone(), datapoint.clone()))
+                        .collect(),
+                )),
+                Event::Tick | Event::End => None,
+            };
+            futures::future::ready(datapoints)
+        })
+}
+
+// Stops recording the history of the signals of a subscription once its
+// stream is dropped
+struct HistoryRecording {
+    broker: broker::DataBroker,
+    permissions: Permissions,
+    ids: Vec<i32>,
+}
+
+impl Drop for HistoryRecording {
+    fn drop(&mut self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let broker = self.broker.clone();
+        let permissions = self.permissions.clone();
+        let ids = std::mem::take(&mut self.ids);
+        runtime.spawn(async move {
+            broker
+                .authorized_access(&permissions)
+                .stop_recording_history(ids)
+                .await;
+        });
+    }
+}
+
+// End a subscription with a token_expired error once the permissions used
+// to set it up have expired, after warning the client shortly before.
+// The subscription is removed once the permissions expired, after notifying
+// the client
+fn terminate_on_expiry(
+    subscription_id: SubscriptionId,
+    stream: impl Stream<Item = Result<SubscriptionEvent, SubscriptionErrorEvent>> + Send + 'static,
+    permissions: &Permissions,
+    subscriptions: Arc<RwLock<HashMap<SubscriptionId, SubscriptionHandle>>>,
+) -> impl Stream<Item = Result<SubscriptionNotification, SubscriptionErrorEvent>> {
+    permissions::with_expiry(stream, permissions).filter_map(move |item| {
+        let subscription_id = subscription_id.clone();
+        let subscriptions = subscriptions.clone();
+        async move {
+            match item {
+                Expiring::Item(item) => Some(item.map(SubscriptionNotification::Event)),
+                Expiring::Event(ExpiryEvent::ExpiresSoon(expires_at)) => Some(Ok(
+                    SubscriptionNotification::TokenExpiryWarning(TokenExpiryWarningEvent {
+                        subscription_id,
+                        token_expires_at: expires_at.into(),
+                        ts: SystemTime::now().into(),
+                    }),
+                )),
+                Expiring::Event(ExpiryEvent::Expired) => {
+                    subscriptions.write().await.remove(&subscription_id);
+                    Some(Err(SubscriptionErrorEvent {
+                        subscription_id,
+                        error: Error::UnauthorizedTokenExpired,
+                        ts: SystemTime::now().into(),
+                    }))
+                }
+            }
         }
     })
 }
 
-fn resolve_permissions(
-    authorization: &Authorization,
-    token: &Option<String>,
-) -> Result<Permissions, Error> {
-    match authorization {
-        Authorization::Disabled => Ok(permissions::ALLOW_ALL.clone()),
-        Authorization::Enabled { token_decoder } => match token {
-            Some(token) => match token_decoder.decode(token) {
-                Ok(claims) => match Permissions::try_from(claims) {
-                    Ok(permissions) => Ok(permissions),
-                    Err(_) => Err(Error::UnauthorizedTokenInvalid),
-                },
-                Err(_) => Err(Error::UnauthorizedTokenInvalid),
+impl Server {
+    // The token of a request authorizes it, or the whole connection if
+    // authorized per connection
+    fn resolve_permissions(&self, token: &Option<String>) -> Result<Permissions, Error> {
+        match &self.authorization {
+            Authorization::Disabled => Ok(permissions::ALLOW_ALL.clone()),
+            Authorization::Enabled { .. } => match (self.authorization_mode, token) {
+                (AuthorizationMode::PerMessage, Some(token)) => self.decode_token(token),
+                (AuthorizationMode::PerConnection, Some(token)) => {
+                    self.authorize_connection(token)?;
+                    self.connection_permissions()
+                }
+                (AuthorizationMode::PerConnection, None) => self.connection_permissions(),
+                (AuthorizationMode::PerMessage, None) => {
+                    self.audit_authorization_failure("No auth token provided");
+                    Err(Error::UnauthorizedTokenMissing)
+                }
             },
-            None => Err(Error::UnauthorizedTokenMissing),
+        }
+    }
+
+    // Whether a request is authorized by a token, its own or the one of the
+    // connection
+    fn has_token(&self, token: &Option<String>) -> bool {
+        match (&self.authorization, self.authorization_mode) {
+            (Authorization::Disabled, _) => false,
+            (Authorization::Enabled { .. }, AuthorizationMode::PerMessage) => token.is_some(),
+            (Authorization::Enabled { .. }, AuthorizationMode::PerConnection) => {
+                token.is_some()
+                    || self
+                        .connection
+                        .lock()
+                        .expect("lock should not be poisoned")
+                        .permissions
+                        .is_some()
+            }
+        }
+    }
+
+    // The permissions of the connection, decoded again if the policy or the
+    // keys have been reloaded since, so that revoked tokens stop working
+    fn connection_permissions(&self) -> Result<Permissions, Error> {
+        let mut connection = self.connection.lock().expect("lock should not be poisoned");
+        let generation = self.authorization.generation();
+        if connection.generation != generation {
+            if let Some(token) = connection.token.clone() {
+                connection.generation = generation;
+                match self.decode_token(&token) {
+                    Ok(permissions) => connection.permissions = Some(permissions),
+                    Err(error) => {
+                        connection.token = None;
+                        connection.permissions = None;
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        match &connection.permissions {
+            Some(permissions) if permissions.is_expired() => Err(Error::UnauthorizedTokenExpired),
+            Some(permissions) => Ok(permissions.clone()),
+            None => {
+                self.audit_authorization_failure("No auth token provided");
+                Err(Error::UnauthorizedTokenMissing)
+            }
+        }
+    }
+
+    fn decode_token(&self, token: &str) -> Result<Permissions, Error> {
+        self.authorization
+            .permissions_from_token(token)
+            .map_err(|err| {
+                self.audit_authorization_failure(err.to_string());
+                match err {
+                    authorization::Error::InvalidApiKey => Error::ForbiddenUserUnknown,
+                    _ => Error::UnauthorizedTokenInvalid,
+                }
+            })
+    }
+
+    fn audit_authorization_failure(&self, error: impl Into<String>) {
+        if let Some(audit_log) = self.broker.audit_log() {
+            audit_log.record(
+                audit::Record::new(audit::Event::Authorization, Some(self.api)).error(error),
+            );
+        }
+    }
+}
+
+// Matchers of the paths of a paths filter, which are relative to the path of
+// the request
+fn path_matchers(root: &str, filter: &PathsFilter) -> Result<Vec<glob::Matcher>, Error> {
+    let root = root.trim_end_matches(['.', '/']).replace('/', ".");
+    filter
+        .parameter
+        .iter()
+        .map(|relative| {
+            let pattern = format!(
+                "{root}.{}",
+                relative.trim_start_matches(['.', '/']).replace('/', ".")
+            );
+            glob::Matcher::new(&pattern).map_err(|_| Error::BadRequest {
+                msg: Some(format!("Invalid path in paths filter: {pattern}")),
+            })
+        })
+        .collect()
+}
+
+fn paths_filter(filters: &[Filter]) -> Option<&PathsFilter> {
+    filters.iter().find_map(|filter| match filter {
+        Filter::Paths(filter) => Some(filter),
+        _ => None,
+    })
+}
+
+fn is_match(matchers: &[glob::Matcher], metadata: &broker::Metadata) -> bool {
+    matchers
+        .iter()
+        .any(|matcher| matcher.is_match(&metadata.glob_path))
+}
+
+fn update_error(err: &UpdateError) -> Error {
+    match err {
+        UpdateError::NotFound => Error::NotFoundInvalidPath,
+        UpdateError::WrongType => Error::BadRequest {
+            msg: Some("Wrong data type.".into()),
         },
+        UpdateError::OutOfBoundsAllowed => Error::BadRequestInvalidValue {
+            msg: Some("Value out of allowed bounds.".into()),
+        },
+        UpdateError::OutOfBoundsMinMax => Error::BadRequestInvalidValue {
+            msg: Some("Value out of min/max bounds.".into()),
+        },
+        UpdateError::OutOfBoundsType => Error::BadRequestInvalidValue {
+            msg: Some("Value out of type bounds.".into()),
+        },
+        UpdateError::UnsupportedType => Error::BadRequest {
+            msg: Some("Unsupported data type.".into()),
+        },
+        UpdateError::PermissionDenied => Error::Forbidden,
+        UpdateError::PermissionExpired => Error::UnauthorizedTokenExpired,
+    }
+}
+
+fn actuation_error(err: broker::ActuationError, msg: String) -> Error {
+    match err {
+        broker::ActuationError::NotFound => Error::NotFoundInvalidPath,
+        broker::ActuationError::WrongType | broker::ActuationError::UnsupportedType => {
+            Error::BadRequest { msg: Some(msg) }
+        }
+        broker::ActuationError::OutOfBounds => Error::BadRequestInvalidValue { msg: Some(msg) },
+        broker::ActuationError::PermissionDenied => Error::Forbidden,
+        broker::ActuationError::PermissionExpired => Error::UnauthorizedTokenExpired,
+        broker::ActuationError::ProviderNotAvailable => Error::ServiceUnavailable,
+        broker::ActuationError::TransmissionFailure => Error::BadGateway,
+        broker::ActuationError::ProviderAlreadyExists => Error::InternalServerError,
+    }
+}
+
+fn read_error(err: broker::ReadError) -> Error {
+    match err {
+        broker::ReadError::NotFound => Error::NotFoundInvalidPath,
+        broker::ReadError::PermissionDenied => Error::Forbidden,
+        broker::ReadError::PermissionExpired => Error::UnauthorizedTokenExpired,
     }
 }
 
+async fn get_history(
+    broker: &AuthorizedAccess<'_, '_>,
+    path: &str,
+    history: &History,
+) -> Result<DataPoints, broker::ReadError> {
+    let datapoints = broker.get_history_by_path(path, history.since).await?;
+    Ok(history.apply(datapoints).into())
+}
+
+// The fields of metadata entries that can be selected
+const METADATA_FIELDS: [&str; 8] = [
+    "datatype",
+    "description",
+    "comment",
+    "unit",
+    "allowed",
+    "min",
+    "max",
+    "default",
+];
+
+// The metadata below the path, of the signals whose metadata the permissions
+// allow to read, if any are given
 async fn generate_metadata(
     db: &AuthorizedAccess<'_, '_>,
+    permissions: Option<&Permissions>,
     path: &str,
-) -> HashMap<String, MetadataEntry> {
+    parameter: &StaticMetadataParameter,
+) -> Result<HashMap<String, MetadataEntry>, Error> {
+    let depth = match &parameter.depth {
+        Some(depth) => Some(depth.parse::<usize>().map_err(|_| Error::BadRequest {
+            msg: Some("The depth must be a number.".into()),
+        })?),
+        None => None,
+    };
+    if let Some(field) = parameter
+        .fields
+        .iter()
+        .find(|field| !METADATA_FIELDS.contains(&field.as_str()))
+    {
+        return Err(Error::BadRequest {
+            msg: Some(format!("Unknown metadata field '{field}'.")),
+        });
+    }
+
     let mut metadata: HashMap<String, MetadataEntry> = HashMap::new();
 
     // We want to remove all but the last "component" present in the path.
@@ -407,15 +1016,85 @@ async fn generate_metadata(
     db.for_each_entry(|entry| {
         let entry_metadata = entry.metadata();
         let entry_path = &entry_metadata.path;
-        if entry_path.starts_with(path) {
+        if entry_path.starts_with(path)
+            && permissions
+                .is_none_or(|permissions| permissions.can_read_metadata(entry_path).is_ok())
+        {
             if let Some(path) = entry_path.strip_prefix(prefix_to_strip) {
-                insert_entry(&mut metadata, path, entry_metadata.into());
+                // Entries below the depth limit are represented by the
+                // branch at the limit
+                match depth.and_then(|depth| path.match_indices('.').nth(depth)) {
+                    Some((index, _)) => insert_entry(
+                        &mut metadata,
+                        &path[..index],
+                        MetadataEntry::Branch(BranchEntry {
+                            description: None,
+                            children: HashMap::new(),
+                        }),
+                    ),
+                    None => insert_entry(&mut metadata, path, entry_metadata.into()),
+                }
             }
         }
     })
     .await;
 
-    metadata
+    let branches = db.get_branch_descriptions(path).await;
+    complete_entries(&mut metadata, prefix_to_strip, &branches, &parameter.fields);
+    Ok(metadata)
+}
+
+// Describe the branches and leave out the fields that were not selected, if
+// any were
+fn complete_entries(
+    entries: &mut HashMap<String, MetadataEntry>,
+    prefix: &str,
+    branches: &HashMap<String, String>,
+    fields: &[String],
+) {
+    let selected = |field: &str| fields.is_empty() || fields.iter().any(|f| f == field);
+    for (name, entry) in entries {
+        match entry {
+            MetadataEntry::Branch(branch) => {
+                let path = format!("{prefix}{name}");
+                if selected("description") {
+                    branch.description = Some(branches.get(&path).cloned().unwrap_or_default());
+                }
+                complete_entries(&mut branch.children, &format!("{path}."), branches, fields);
+            }
+            MetadataEntry::Sensor(sensor) => {
+                retain(&mut sensor.datatype, selected("datatype"));
+                retain(&mut sensor.description, selected("description"));
+                retain(&mut sensor.comment, selected("comment"));
+                retain(&mut sensor.unit, selected("unit"));
+                retain(&mut sensor.allowed, selected("allowed"));
+                retain(&mut sensor.min, selected("min"));
+                retain(&mut sensor.max, selected("max"));
+            }
+            MetadataEntry::Attribute(attribute) => {
+                retain(&mut attribute.datatype, selected("datatype"));
+                retain(&mut attribute.description, selected("description"));
+                retain(&mut attribute.unit, selected("unit"));
+                retain(&mut attribute.allowed, selected("allowed"));
+                retain(&mut attribute.default, selected("default"));
+            }
+            MetadataEntry::Actuator(actuator) => {
+                retain(&mut actuator.datatype, selected("datatype"));
+                retain(&mut actuator.description, selected("description"));
+                retain(&mut actuator.comment, selected("comment"));
+                retain(&mut actuator.unit, selected("unit"));
+                retain(&mut actuator.allowed, selected("allowed"));
+                retain(&mut actuator.min, selected("min"));
+                retain(&mut actuator.max, selected("max"));
+            }
+        }
+    }
+}
+
+fn retain<T>(field: &mut Option<T>, selected: bool) {
+    if !selected {
+        *field = None;
+    }
 }
 
 fn insert_entry(entries: &mut HashMap<String, MetadataEntry>, path: &str, entry: MetadataEntry) {
@@ -433,7 +1112,7 @@ fn insert_entry(entries: &mut HashMap<String, MetadataEntry>, path: &str, entry:
             }
             None => {
                 let mut branch = BranchEntry {
-                    description: "".into(),
+                    description: None,
                     children: HashMap::default(),
                 };
                 insert_entry(&mut branch.children, path, entry);
@@ -441,7 +1120,786 @@ fn insert_entry(entries: &mut HashMap<String, MetadataEntry>, path: &str, entry:
             }
         },
         None => {
-            entries.insert(path.to_owned(), entry);
+            // A branch at the depth limit represents several entries
+            entries.entry(path.to_owned()).or_insert(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        authorization::policy::Policy,
+        types::{ChangeType, DataValue},
+    };
+    use tokio::sync::mpsc;
+
+    async fn add_doors(broker: &broker::DataBroker) {
+        let broker = broker.authorized_access(&permissions::ALLOW_ALL);
+        for (path, entry_type) in [
+            (
+                "Vehicle.Cabin.Door.Row1.Left.IsOpen",
+                broker::EntryType::Actuator,
+            ),
+            (
+                "Vehicle.Cabin.Door.Row1.Right.IsOpen",
+                broker::EntryType::Actuator,
+            ),
+            (
+                "Vehicle.Cabin.Door.Row1.Left.IsLocked",
+                broker::EntryType::Sensor,
+            ),
+        ] {
+            broker
+                .add_entry(
+                    path.to_owned(),
+                    broker::DataType::Bool,
+                    ChangeType::OnChange,
+                    entry_type,
+                    "Door".to_owned(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .expect("entry should be added");
+        }
+    }
+
+    fn paths_filter(parameter: &str) -> Vec<Filter> {
+        filters_from_str(&format!(r#"{{"type": "paths", "parameter": {parameter}}}"#))
+            .expect("filter should parse")
+    }
+
+    fn request_id() -> RequestId {
+        serde_json::from_str(r#""1""#).expect("request id should parse")
+    }
+
+    #[tokio::test]
+    async fn test_paths_filter() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        server
+            .set(SetRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                value: Value::Scalar("true".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: paths_filter(r#""Row1.*.IsOpen""#),
+            })
+            .await
+            .map_err(|_| "set failed")
+            .expect("set should succeed");
+        for path in [
+            "Vehicle.Cabin.Door.Row1.Left.IsOpen",
+            "Vehicle.Cabin.Door.Row1.Right.IsOpen",
+        ] {
+            let entry = broker
+                .authorized_access(&permissions::ALLOW_ALL)
+                .get_entry_by_path(path)
+                .await
+                .expect("entry should exist");
+            assert_eq!(
+                entry.actuator_target.map(|datapoint| datapoint.value),
+                Some(DataValue::Bool(true))
+            );
+        }
+
+        let response = server
+            .get(GetRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: paths_filter(r#"["Row1.Left.*", "Row1.Right.IsOpen"]"#),
+            })
+            .await
+            .map_err(|_| "get failed")
+            .expect("get should succeed");
+        let response = serde_json::to_value(response).expect("response should serialize");
+        let paths: Vec<_> = response["data"]
+            .as_array()
+            .expect("data should be an array")
+            .iter()
+            .map(|object| object["path"].as_str().expect("path should be a string"))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "Vehicle.Cabin.Door.Row1.Left.IsLocked",
+                "Vehicle.Cabin.Door.Row1.Left.IsOpen",
+                "Vehicle.Cabin.Door.Row1.Right.IsOpen"
+            ]
+        );
+
+        // Sensors can be set by providers, which everyone is without
+        // authorization
+        assert!(server
+            .set(SetRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                value: Value::Scalar("true".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: paths_filter(r#""**""#),
+            })
+            .await
+            .is_ok());
+
+        assert!(server
+            .get(GetRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: paths_filter(r#""Row2.*.IsOpen""#),
+            })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paths_filter_subscribe() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        let (_, mut stream) = server
+            .subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: paths_filter(r#""*.*.IsOpen""#),
+            })
+            .await
+            .map_err(|_| "subscribe failed")
+            .expect("subscribe should succeed");
+
+        // The current values of both signals are sent first
+        let event = stream
+            .next()
+            .await
+            .expect("stream should yield")
+            .map_err(|_| "error event")
+            .expect("event should not be an error");
+        let event = serde_json::to_value(event).expect("event should serialize");
+        assert_eq!(event["data"].as_array().map(|data| data.len()), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_paths_filter_subscribe_unreadable() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        let policy = Policy::from_json(
+            r#"{"clients": [
+                {"name": "app", "api_keys": ["app-key"], "read": ["Vehicle.Cabin.Door.Row1.Left.*"]}
+            ]}"#,
+        )
+        .expect("policy should be valid");
+        let server = Server::new(
+            broker,
+            Authorization::Enabled {
+                token_decoder: None,
+                policy: Some(policy),
+            },
+        );
+        let subscribe = |filter| {
+            server.subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Cabin.Door".to_owned()),
+                request_id: request_id(),
+                authorization: Some("app-key".to_owned()),
+                filter: paths_filter(filter),
+            })
+        };
+
+        // Only the readable signal is subscribed to
+        let (_, mut stream) = subscribe(r#""*.*.IsOpen""#)
+            .await
+            .map_err(|_| "subscribe failed")
+            .expect("subscribe should succeed");
+        let event = stream
+            .next()
+            .await
+            .expect("stream should yield")
+            .map_err(|_| "error event")
+            .expect("event should not be an error");
+        let event = serde_json::to_value(event).expect("event should serialize");
+        assert_eq!(event["data"]["path"], "Vehicle.Cabin.Door.Row1.Left.IsOpen");
+
+        let error = subscribe(r#""*.Right.*""#)
+            .await
+            .err()
+            .expect("subscribe should fail");
+        assert_eq!(ErrorSpec::from(error.error).number, 403);
+    }
+
+    async fn add_speed(broker: &broker::DataBroker) -> i32 {
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_entry(
+                "Vehicle.Speed".to_owned(),
+                broker::DataType::Float,
+                ChangeType::Continuous,
+                broker::EntryType::Sensor,
+                "Speed".to_owned(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("entry should be added")
+    }
+
+    async fn set_speed(broker: &broker::DataBroker, id: i32, speed: f32) {
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .update_entries([(
+                id,
+                broker::EntryUpdate {
+                    datapoint: Some(broker::Datapoint {
+                        ts: SystemTime::now(),
+                        source_ts: None,
+                        value: DataValue::Float(speed),
+                    }),
+                    ..Default::default()
+                },
+            )])
+            .await
+            .expect("update should succeed");
+    }
+
+    async fn next_value(stream: &mut <Server as Viss>::SubscribeStream) -> serde_json::Value {
+        let event = tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .expect("event should be received in time")
+            .expect("stream should yield")
+            .map_err(|_| "error event")
+            .expect("event should not be an error");
+        serde_json::to_value(event).expect("event should serialize")["data"]["dp"]["value"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_range_and_timebased_filters() {
+        let broker = broker::DataBroker::default();
+        let id = add_speed(&broker).await;
+        set_speed(&broker, id, 10.0).await;
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        let (_, mut stream) = server
+            .subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(
+                    r#"{"type": "range", "parameter": {"logic-op": "gt", "boundary": "50"}}"#,
+                )
+                .expect("filter should parse"),
+            })
+            .await
+            .map_err(|_| "subscribe failed")
+            .expect("subscribe should succeed");
+        set_speed(&broker, id, 40.0).await;
+        set_speed(&broker, id, 60.0).await;
+        assert_eq!(next_value(&mut stream).await, "60");
+
+        let (_, mut stream) = server
+            .subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(r#"{"type": "timebased", "parameter": {"period": "20"}}"#)
+                    .expect("filter should parse"),
+            })
+            .await
+            .map_err(|_| "subscribe failed")
+            .expect("subscribe should succeed");
+        // The latest value is notified periodically, even if unchanged
+        assert_eq!(next_value(&mut stream).await, "60");
+        assert_eq!(next_value(&mut stream).await, "60");
+    }
+
+    #[tokio::test]
+    async fn test_history_filter() {
+        let broker = broker::DataBroker::default().with_history(
+            vec![glob::Matcher::new("Vehicle.Speed").expect("pattern should be valid")],
+            10,
+        );
+        let id = add_speed(&broker).await;
+        for speed in [10.0, 11.0, 30.0] {
+            set_speed(&broker, id, speed).await;
         }
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        let get_values = |filter: &str| {
+            let request = GetRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(filter).expect("filter should parse"),
+            };
+            let server = &server;
+            async move {
+                let response = server
+                    .get(request)
+                    .await
+                    .map_err(|_| "get failed")
+                    .expect("get should succeed");
+                serde_json::to_value(response).expect("response should serialize")["data"]["dp"]
+                    .as_array()
+                    .expect("dp should be an array")
+                    .iter()
+                    .map(|dp| dp["value"].clone())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            get_values(r#"{"type": "history", "parameter": "PT1M"}"#).await,
+            ["10", "11", "30"]
+        );
+        assert_eq!(
+            get_values(
+                r#"[{"type": "history", "parameter": "PT1M"},
+                    {"type": "curvelog", "parameter": {"maxerr": "100"}}]"#
+            )
+            .await,
+            ["10", "30"]
+        );
+
+        // Curvelog compresses a history only
+        assert!(server
+            .get(GetRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(r#"{"type": "curvelog", "parameter": {"maxerr": "1"}}"#)
+                    .expect("filter should parse"),
+            })
+            .await
+            .is_err());
+    }
+
+    async fn history_len(broker: &broker::DataBroker) -> usize {
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .get_history_by_path("Vehicle.Speed", SystemTime::UNIX_EPOCH)
+            .await
+            .expect("reading history should succeed")
+            .len()
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_history() {
+        let broker = broker::DataBroker::default();
+        let id = add_speed(&broker).await;
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        let (_, stream) = server
+            .subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: Vec::new(),
+            })
+            .await
+            .map_err(|_| "subscribe failed")
+            .expect("subscribe should succeed");
+        for speed in [10.0, 11.0] {
+            set_speed(&broker, id, speed).await;
+        }
+        assert_eq!(history_len(&broker).await, 2);
+
+        // Only the current value is kept once unsubscribed
+        drop(stream);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while history_len(&broker).await > 1 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("recording should stop");
+    }
+
+    struct MockProvider {
+        sender: mpsc::UnboundedSender<Vec<broker::ActuationChange>>,
+    }
+
+    #[async_trait::async_trait]
+    impl broker::ActuationProvider for MockProvider {
+        async fn actuate(
+            &self,
+            actuation_changes: Vec<broker::ActuationChange>,
+        ) -> Result<(), (broker::ActuationError, String)> {
+            self.sender.send(actuation_changes).map_err(|_| {
+                (
+                    broker::ActuationError::TransmissionFailure,
+                    "Provider is gone".to_owned(),
+                )
+            })
+        }
+
+        fn is_available(&self) -> bool {
+            !self.sender.is_closed()
+        }
+    }
+
+    async fn set(
+        server: &Server,
+        path: &str,
+        api_key: &str,
+    ) -> Result<SetSuccessResponse, SetErrorResponse> {
+        server
+            .set(SetRequest {
+                path: Path::from(path.to_owned()),
+                value: Value::Scalar("true".to_owned()),
+                request_id: request_id(),
+                authorization: Some(api_key.to_owned()),
+                filter: Vec::new(),
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_set_actuation() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        let access = broker.authorized_access(&permissions::ALLOW_ALL);
+        let left_id = access
+            .get_id_by_path("Vehicle.Cabin.Door.Row1.Left.IsOpen")
+            .await
+            .expect("entry should exist");
+        let (sender, mut actuations) = mpsc::unbounded_channel();
+        access
+            .provide_actuation(vec![left_id], Box::new(MockProvider { sender }))
+            .await
+            .map_err(|(_, msg)| msg)
+            .expect("providing actuation should succeed");
+
+        let policy = Policy::from_json(
+            r#"{"clients": [
+                {"name": "app", "api_keys": ["app-key"], "read": ["*"], "actuate": ["*"]},
+                {"name": "provider", "api_keys": ["provider-key"], "read": ["*"], "provide": ["*"]}
+            ]}"#,
+        )
+        .expect("policy should be valid");
+        let server = Server::new(
+            broker.clone(),
+            Authorization::Enabled {
+                token_decoder: None,
+                policy: Some(policy),
+            },
+        );
+        let set_error = |result: Result<SetSuccessResponse, SetErrorResponse>| {
+            result.err().expect("set should fail").error.number()
+        };
+
+        // Forwarded to the provider rather than setting the actuator target
+        assert!(
+            set(&server, "Vehicle.Cabin.Door.Row1.Left.IsOpen", "app-key")
+                .await
+                .is_ok()
+        );
+        let changes = actuations.recv().await.expect("provider should be called");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].id, left_id);
+        assert_eq!(changes[0].data_value, DataValue::Bool(true));
+        let entry = access
+            .get_entry_by_path("Vehicle.Cabin.Door.Row1.Left.IsOpen")
+            .await
+            .expect("entry should exist");
+        assert!(entry.actuator_target.is_none());
+
+        // Without a provider, the actuator target is set unless configured
+        // otherwise
+        assert!(
+            set(&server, "Vehicle.Cabin.Door.Row1.Right.IsOpen", "app-key")
+                .await
+                .is_ok()
+        );
+        let server = server.with_actuation_fallback(ActuationFallback::Error);
+        assert_eq!(
+            set_error(set(&server, "Vehicle.Cabin.Door.Row1.Right.IsOpen", "app-key").await),
+            503
+        );
+
+        // Sensors can only be set with provide permission
+        assert_eq!(
+            set_error(set(&server, "Vehicle.Cabin.Door.Row1.Left.IsLocked", "app-key").await),
+            401
+        );
+        assert!(set(
+            &server,
+            "Vehicle.Cabin.Door.Row1.Left.IsLocked",
+            "provider-key"
+        )
+        .await
+        .is_ok());
+        let entry = access
+            .get_entry_by_path("Vehicle.Cabin.Door.Row1.Left.IsLocked")
+            .await
+            .expect("entry should exist");
+        assert_eq!(entry.datapoint.value, DataValue::Bool(true));
+
+        // The provider has gone away
+        drop(actuations);
+        assert_eq!(
+            set_error(set(&server, "Vehicle.Cabin.Door.Row1.Left.IsOpen", "app-key").await),
+            503
+        );
+    }
+
+    #[tokio::test]
+    async fn test_static_metadata_filter() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        broker
+            .authorized_access(&permissions::ALLOW_ALL)
+            .add_branch("Vehicle.Cabin.Door".to_owned(), "All doors".to_owned())
+            .await
+            .expect("branch should be added");
+        let server = Server::new(broker.clone(), Authorization::Disabled);
+
+        let get_metadata = |path: &str, filter: &str| {
+            let request = GetRequest {
+                path: Path::from(path.to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(filter).expect("filter should parse"),
+            };
+            let server = &server;
+            async move {
+                let response = server
+                    .get(request)
+                    .await
+                    .map_err(|_| "get failed")
+                    .expect("get should succeed");
+                serde_json::to_value(response).expect("response should serialize")["metadata"]
+                    .clone()
+            }
+        };
+
+        assert_eq!(
+            get_metadata(
+                "Vehicle.Cabin",
+                r#"{"type": "static-metadata", "parameter": {"depth": "1", "fields": "description"}}"#
+            )
+            .await,
+            serde_json::json!({
+                "Cabin": {
+                    "type": "branch",
+                    "description": "",
+                    "children": {
+                        "Door": {"type": "branch", "description": "All doors"}
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            get_metadata(
+                "Vehicle.Cabin.Door.Row1.Left.IsOpen",
+                r#"{"type": "static-metadata", "parameter": {"fields": ["datatype"]}}"#
+            )
+            .await,
+            serde_json::json!({"IsOpen": {"type": "actuator", "datatype": "boolean"}})
+        );
+        assert_eq!(
+            get_metadata(
+                "Vehicle.Cabin.Door.Row1.Left",
+                r#"{"type": "static-metadata"}"#
+            )
+            .await["Left"]["children"]["IsLocked"]["description"],
+            "Door"
+        );
+
+        assert!(server
+            .get(GetRequest {
+                path: Path::from("Vehicle.Cabin".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: filters_from_str(
+                    r#"{"type": "static-metadata", "parameter": {"fields": "colour"}}"#
+                )
+                .expect("filter should parse"),
+            })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_metadata_authorization() {
+        let broker = broker::DataBroker::default();
+        add_doors(&broker).await;
+        let policy = Policy::from_json(
+            r#"{"clients": [
+                {"name": "app", "api_keys": ["app-key"], "read": ["Vehicle.Cabin.Door.Row1.Left.*"]}
+            ]}"#,
+        )
+        .expect("policy should be valid");
+        let server = Server::new(
+            broker,
+            Authorization::Enabled {
+                token_decoder: None,
+                policy: Some(policy),
+            },
+        );
+        let get_doors = |authorization: Option<&str>| {
+            let request = GetRequest {
+                path: Path::from("Vehicle.Cabin.Door.Row1".to_owned()),
+                request_id: request_id(),
+                authorization: authorization.map(str::to_owned),
+                filter: filters_from_str(r#"{"type": "static-metadata"}"#)
+                    .expect("filter should parse"),
+            };
+            let server = &server;
+            async move {
+                let response = server
+                    .get(request)
+                    .await
+                    .map_err(|_| "get failed")
+                    .expect("get should succeed");
+                let metadata = serde_json::to_value(response).expect("response should serialize")
+                    ["metadata"]["Row1"]["children"]
+                    .clone();
+                let mut doors = metadata
+                    .as_object()
+                    .expect("children should be an object")
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                doors.sort();
+                doors
+            }
+        };
+
+        // Metadata can be browsed without a token, a token narrows it down
+        assert_eq!(get_doors(None).await, ["Left", "Right"]);
+        assert_eq!(get_doors(Some("app-key")).await, ["Left"]);
+    }
+
+    #[tokio::test]
+    async fn test_authorize_connection() {
+        let broker = broker::DataBroker::default();
+        add_speed(&broker).await;
+        let policy = Policy::from_json(
+            r#"{"clients": [{"name": "app", "api_keys": ["app-key"], "read": ["*"]}]}"#,
+        )
+        .expect("policy should be valid");
+        let server = Server::new(
+            broker,
+            Authorization::Enabled {
+                token_decoder: None,
+                policy: Some(policy.clone()),
+            },
+        )
+        .with_authorization_mode(AuthorizationMode::PerConnection);
+        let get = |authorization: Option<&str>| {
+            server.get(GetRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: authorization.map(str::to_owned),
+                filter: Vec::new(),
+            })
+        };
+        let get_error = |result: Result<GetSuccessResponse, GetErrorResponse>| {
+            ErrorSpec::from(result.err().expect("get should fail").error).reason
+        };
+
+        assert_eq!(get_error(get(None).await), "token_missing");
+        assert_eq!(get_error(get(Some("other-key")).await), "user_unknown");
+        // The first token authorizes the following requests
+        assert!(get(Some("app-key")).await.is_ok());
+        assert!(get(None).await.is_ok());
+
+        // A revoked token is rejected once the policy is reloaded, also for
+        // requests relying on the authorization of the connection
+        policy
+            .reload(r#"{"clients": []}"#)
+            .expect("policy should be valid");
+        assert_eq!(get_error(get(Some("app-key")).await), "user_unknown");
+        assert_eq!(get_error(get(None).await), "user_unknown");
+        assert_eq!(get_error(get(None).await), "token_missing");
+        policy
+            .reload(r#"{"clients": [{"name": "app", "api_keys": ["app-key"], "read": ["*"]}]}"#)
+            .expect("policy should be valid");
+        assert!(get(Some("app-key")).await.is_ok());
+
+        // Rejected once too often, even if the token is valid
+        assert!(server.authorize_connection("other-key").is_err());
+        assert!(server.authorize_connection("other-key").is_err());
+        assert!(server.authorize_connection("other-key").is_err());
+        assert_eq!(get_error(get(Some("app-key")).await), "too_many_attempts");
+        assert!(get(None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_connection_expiry() {
+        let broker = broker::DataBroker::default();
+        add_speed(&broker).await;
+        let server = Server::new(
+            broker,
+            Authorization::Enabled {
+                token_decoder: None,
+                policy: None,
+            },
+        )
+        .with_authorization_mode(AuthorizationMode::PerConnection);
+        server.connection.lock().unwrap().permissions = Some(
+            Permissions::builder()
+                .add_read_permission(permissions::Permission::All)
+                .expires_at(SystemTime::now() + Duration::from_millis(200))
+                .build()
+                .expect("permissions should be valid"),
+        );
+
+        let (response, mut stream) = server
+            .subscribe(SubscribeRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: Vec::new(),
+            })
+            .await
+            .ok()
+            .expect("subscribe should succeed");
+
+        // The subscription is closed once the token expired, after a warning
+        let mut warned = false;
+        let mut expired = false;
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(SubscriptionNotification::TokenExpiryWarning(_)) => {
+                    assert!(!expired);
+                    warned = true;
+                }
+                Ok(SubscriptionNotification::Event(_)) => {}
+                Err(event) => {
+                    assert_eq!(ErrorSpec::from(event.error).reason, "token_expired");
+                    expired = true;
+                }
+            }
+        }
+        assert!(warned);
+        assert!(expired);
+        assert!(server
+            .unsubscribe(UnsubscribeRequest {
+                request_id: request_id(),
+                subscription_id: response.subscription_id,
+            })
+            .await
+            .is_err());
+        let result = server
+            .get(GetRequest {
+                path: Path::from("Vehicle.Speed".to_owned()),
+                request_id: request_id(),
+                authorization: None,
+                filter: Vec::new(),
+            })
+            .await;
+        assert_eq!(
+            ErrorSpec::from(result.err().expect("get should fail").error).reason,
+            "token_expired"
+        );
     }
 }
@@ -46,6 +46,7 @@ impl Response for UnsubscribeErrorResponse {}
 
 impl Response for SubscriptionEvent {}
 impl Response for SubscriptionErrorEvent {}
+impl Response for SubscriptionNotification {}
 
 impl Response for GenericErrorResponse {}
 
@@ -55,7 +56,8 @@ pub struct GetRequest {
     pub path: Path,
     pub request_id: RequestId,
     pub authorization: Option<String>,
-    pub filter: Option<Filter>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub filter: Vec<Filter>,
 }
 
 #[derive(Serialize)]
@@ -94,6 +96,8 @@ pub struct SetRequest {
     pub value: Value,
     pub request_id: RequestId,
     pub authorization: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub filter: Vec<Filter>,
 }
 
 #[derive(Serialize)]
@@ -117,7 +121,8 @@ pub struct SubscribeRequest {
     pub path: Path,
     pub request_id: RequestId,
     pub authorization: Option<String>,
-    // filter: Option<Filter>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub filter: Vec<Filter>,
 }
 
 #[derive(Serialize)]
@@ -144,6 +149,22 @@ pub struct SubscriptionEvent {
     pub ts: Timestamp,
 }
 
+// Sent on a subscription shortly before the token it was set up with expires
+#[derive(Serialize)]
+#[serde(tag = "action", rename = "subscription", rename_all = "camelCase")]
+pub struct TokenExpiryWarningEvent {
+    pub subscription_id: SubscriptionId,
+    pub token_expires_at: Timestamp,
+    pub ts: Timestamp,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SubscriptionNotification {
+    Event(SubscriptionEvent),
+    TokenExpiryWarning(TokenExpiryWarningEvent),
+}
+
 #[derive(Serialize)]
 #[serde(tag = "action", rename = "subscription", rename_all = "camelCase")]
 pub struct SubscriptionErrorEvent {
@@ -196,12 +217,119 @@ pub struct GenericErrorResponse {
 pub enum Filter {
     #[serde(rename = "static-metadata")]
     StaticMetadata(StaticMetadataFilter),
+    #[serde(rename = "paths")]
+    Paths(PathsFilter),
+    #[serde(rename = "timebased")]
+    Timebased(TimebasedFilter),
+    #[serde(rename = "range")]
+    Range(RangeFilter),
+    #[serde(rename = "change")]
+    Change(ChangeFilter),
+    #[serde(rename = "history")]
+    History(HistoryFilter),
+    #[serde(rename = "curvelog")]
+    Curvelog(CurvelogFilter),
+}
+
+// Paths relative to the path of the request, which may contain wildcards.
+// For example, the path "Vehicle.Cabin.Door" and the parameter "*.*.IsOpen"
+// match the IsOpen signals of all doors.
+#[derive(Deserialize)]
+pub struct PathsFilter {
+    #[serde(deserialize_with = "one_or_many")]
+    pub parameter: Vec<String>,
+}
+
+// Notify the values of a subscription periodically rather than on change.
+#[derive(Deserialize)]
+pub struct TimebasedFilter {
+    pub parameter: TimebasedParameter,
+}
+
+#[derive(Deserialize)]
+pub struct TimebasedParameter {
+    // Milliseconds
+    pub period: String,
+}
+
+// Notify values only if they are within the boundaries, which must all be
+// satisfied.
+#[derive(Deserialize)]
+pub struct RangeFilter {
+    #[serde(deserialize_with = "one_or_many")]
+    pub parameter: Vec<RangeParameter>,
+}
+
+#[derive(Deserialize)]
+pub struct RangeParameter {
+    #[serde(rename = "logic-op")]
+    pub logic_op: LogicOp,
+    pub boundary: String,
+}
+
+// Notify values only if their difference to the previously notified value
+// satisfies the condition.
+#[derive(Deserialize)]
+pub struct ChangeFilter {
+    pub parameter: ChangeParameter,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeParameter {
+    #[serde(rename = "logic-op")]
+    pub logic_op: LogicOp,
+    pub diff: String,
+}
+
+// Get the values over a past period, given as an ISO 8601 duration
+// (e.g. "PT2H30M").
+#[derive(Deserialize)]
+pub struct HistoryFilter {
+    pub parameter: String,
+}
+
+// Compress the values of a history by leaving out those that can be
+// linearly interpolated from the remaining ones within the maximum error.
+#[derive(Deserialize)]
+pub struct CurvelogFilter {
+    pub parameter: CurvelogParameter,
+}
+
+#[derive(Deserialize)]
+pub struct CurvelogParameter {
+    pub maxerr: String,
+    // The maximum number of values, keeping the most recent
+    pub bufsize: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogicOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StaticMetadataFilter {
-    // pub parameters: Option<StaticMetadataParameters>,
+    #[serde(default)]
+    pub parameter: StaticMetadataParameter,
+}
+
+// Restrict the returned tree, e.g. `{"depth": "1", "fields": ["description"]}`
+// to browse the tree one level at a time.
+#[derive(Deserialize, Default)]
+pub struct StaticMetadataParameter {
+    // Levels of the tree below the requested node. Branches at the last
+    // level are returned without their children.
+    pub depth: Option<String>,
+    // The fields of the nodes, besides their type. All fields if empty.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub fields: Vec<String>,
 }
 
 // Unique id value specified by the client. Returned by the server in the
@@ -246,7 +374,6 @@ pub struct Timestamp {
 #[serde(untagged)]
 pub enum Data {
     Object(DataObject),
-    #[allow(dead_code)]
     Array(Vec<DataObject>),
 }
 
@@ -254,7 +381,15 @@ pub enum Data {
 #[serde(rename_all = "camelCase")]
 pub struct DataObject {
     pub path: Path,
-    pub dp: DataPoint,
+    pub dp: DataPoints,
+}
+
+// A single value, or the values of a history
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum DataPoints {
+    One(DataPoint),
+    Many(Vec<DataPoint>),
 }
 
 #[derive(Serialize)]
@@ -283,14 +418,18 @@ pub enum MetadataEntry {
 
 #[derive(Serialize)]
 pub struct BranchEntry {
-    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub children: HashMap<String, MetadataEntry>,
 }
 
 #[derive(Serialize)]
 pub struct SensorEntry {
-    pub datatype: DataType,
-    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datatype: Option<DataType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -305,8 +444,10 @@ pub struct SensorEntry {
 
 #[derive(Serialize)]
 pub struct AttributeEntry {
-    pub datatype: DataType,
-    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datatype: Option<DataType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unit: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -317,8 +458,10 @@ pub struct AttributeEntry {
 
 #[derive(Serialize)]
 pub struct ActuatorEntry {
-    pub datatype: DataType,
-    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datatype: Option<DataType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comment: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -385,16 +528,28 @@ pub struct ErrorSpec {
 #[allow(dead_code, clippy::enum_variant_names)]
 pub enum Error {
     BadRequest { msg: Option<String> },
+    BadRequestInvalidValue { msg: Option<String> },
     UnauthorizedTokenExpired,
     UnauthorizedTokenInvalid,
     UnauthorizedTokenMissing,
+    UnauthorizedTooManyAttempts,
     UnauthorizedReadOnly,
     Forbidden,
+    ForbiddenUserUnknown,
     NotFoundInvalidPath,
     NotFoundUnavailableData,
     NotFoundInvalidSubscriptionId,
     InternalServerError,
     NotImplemented,
+    BadGateway,
+    ServiceUnavailable,
+}
+
+impl Error {
+    /// The HTTP status code of the error.
+    pub fn number(&self) -> i32 {
+        ErrorSpec::from(self.clone()).number
+    }
 }
 
 impl From<Error> for ErrorSpec {
@@ -411,6 +566,11 @@ impl From<Error> for ErrorSpec {
             // BadRequest          400  filter_invalid            Filter requested on non-primitive type.
             // BadRequest          400  invalid_duration          Time duration is invalid.
             // BadRequest          400  invalid_value             The requested set value is invalid.
+            Error::BadRequestInvalidValue{ msg: custom_msg } => ErrorSpec {
+                number: 400,
+                reason: "invalid_value".into(),
+                message: custom_msg.unwrap_or("The requested set value is invalid.".into()),
+            },
             // Unauthorized        401  token_expired             Access token has expired.
             Error::UnauthorizedTokenExpired => ErrorSpec {
                 number: 401,
@@ -430,6 +590,11 @@ impl From<Error> for ErrorSpec {
                 message: "Access token is missing.".into(),
             },
             // Unauthorized        401  too_many_attempts         The client has failed to authenticate too many times.
+            Error::UnauthorizedTooManyAttempts => ErrorSpec {
+                number: 401,
+                reason: "too_many_attempts".into(),
+                message: "The client has failed to authenticate too many times.".into(),
+            },
             // Unauthorized        401  read_only                 The desired signal cannot be set since it is a read only signal.
             Error::UnauthorizedReadOnly => ErrorSpec {
                 number: 401,
@@ -443,6 +608,11 @@ impl From<Error> for ErrorSpec {
                 message: "The user is not permitted to access the requested resource. Retrying does not help.".into(),
             },
             // Forbidden           403  user_unknown              The user is unknown. Retrying does not help.
+            Error::ForbiddenUserUnknown => ErrorSpec {
+                number: 403,
+                reason: "user_unknown".into(),
+                message: "The user is unknown. Retrying does not help.".into(),
+            },
             // Forbidden           403  device_forbidden          The device is not permitted to access the requested resource. Retrying does not help.
             // Forbidden           403  device_unknown            The device is unknown. Retrying does not help.
             // NotFound            404  invalid_path              The specified data path does not exist.
@@ -480,12 +650,56 @@ impl From<Error> for ErrorSpec {
                 message: "The server does not support the functionality required to fulfill the request.".into(),
             },
             // BadGateway          502  bad_gateway               The server was acting as a gateway or proxy and received an invalid response from an upstream server.
+            Error::BadGateway => ErrorSpec {
+                number: 502,
+                reason: "bad_gateway".into(),
+                message: "The server was acting as a gateway or proxy and received an invalid response from an upstream server.".into(),
+            },
             // ServiceUnavailable  503  service_unavailable       The server is currently unable to handle the request due to a temporary overload or scheduled maintenance (which may be alleviated after some delay).
+            Error::ServiceUnavailable => ErrorSpec {
+                number: 503,
+                reason: "service_unavailable".into(),
+                message: "The server is currently unable to handle the request due to a temporary overload or scheduled maintenance (which may be alleviated after some delay).".into(),
+            },
             // GatewayTimeout      504  gateway_timeout           The server did not receive a timely response from an upstream server it needed to access in order to complete the request.
         }
     }
 }
 
+/// Parse a filter, or an array of filters.
+pub fn filters_from_str(json: &str) -> Result<Vec<Filter>, serde_json::Error> {
+    one_or_many(&mut serde_json::Deserializer::from_str(json))
+}
+
+// A single value may be given instead of an array with one value
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+impl Data {
+    // A single data object, or an array if there are several
+    pub fn from_objects(mut objects: Vec<DataObject>) -> Self {
+        if objects.len() == 1 {
+            Data::Object(objects.remove(0))
+        } else {
+            Data::Array(objects)
+        }
+    }
+}
+
 impl AsRef<str> for Path {
     fn as_ref(&self) -> &str {
         &self.0
@@ -540,6 +754,20 @@ impl Serialize for Timestamp {
     }
 }
 
+impl RequestId {
+    // Requests sent over HTTP are identified by the connection rather than
+    // an id provided by the client
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SubscriptionId {
     pub fn new() -> Self {
         Self(uuid::Uuid::new_v4().to_string())
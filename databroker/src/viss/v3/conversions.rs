@@ -0,0 +1,166 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use crate::viss::v2;
+
+use super::types::{Capabilities, CapabilitiesRequest, CapabilitiesResponse, Request, SUBPROTOCOL};
+
+// Filters are distinguished by their "variant" rather than their "type", and
+// the "metadata" filter takes the place of the "static-metadata" filter, where
+// a number as parameter limits the depth of the returned tree.
+fn filter_from_v3(filter: &mut Value) {
+    let Some(filter) = filter.as_object_mut() else {
+        return;
+    };
+    if let Some(variant) = filter.remove("variant") {
+        filter.insert("type".to_owned(), variant);
+    }
+    if filter.get("type").and_then(Value::as_str) == Some("metadata") {
+        filter.insert("type".to_owned(), "static-metadata".into());
+        if let Some(depth) = filter.get("parameter").and_then(Value::as_str) {
+            let parameter = serde_json::json!({ "depth": depth });
+            filter.insert("parameter".to_owned(), parameter);
+        }
+    }
+}
+
+fn is_capabilities_filter(filter: &Value) -> bool {
+    filter.get("type").and_then(Value::as_str) == Some("capabilities")
+}
+
+/// Parse a VISSv3 request.
+pub fn parse_msg(msg: &str) -> Result<Request, v2::GenericErrorResponse> {
+    let bad_request = |request: Option<&Value>| {
+        let request = request
+            .and_then(|request| serde_json::from_value::<v2::GenericRequest>(request.clone()).ok());
+        v2::GenericErrorResponse {
+            action: request.as_ref().and_then(|request| request.action.clone()),
+            request_id: request.and_then(|request| request.request_id),
+            error: v2::Error::BadRequest { msg: None },
+        }
+    };
+
+    let mut request: Value = serde_json::from_str(msg).map_err(|_| bad_request(None))?;
+    let mut capabilities = false;
+    match request.get_mut("filter") {
+        Some(Value::Array(filters)) => {
+            for filter in filters.iter_mut() {
+                filter_from_v3(filter);
+                capabilities |= is_capabilities_filter(filter);
+            }
+        }
+        Some(filter) => {
+            filter_from_v3(filter);
+            capabilities |= is_capabilities_filter(filter);
+        }
+        None => {}
+    }
+
+    if capabilities && request.get("action").and_then(Value::as_str) == Some("get") {
+        return serde_json::from_value::<CapabilitiesRequest>(request.clone())
+            .map(Request::Capabilities)
+            .map_err(|_| bad_request(Some(&request)));
+    }
+    serde_json::from_value::<v2::Request>(request.clone())
+        .map(Request::V2)
+        .map_err(|_| bad_request(Some(&request)))
+}
+
+/// Translate a serialized VISSv2 response to VISSv3, where errors are
+/// explained by a "description" rather than a "message".
+pub fn response_from_v2(mut response: Value) -> Value {
+    if let Some(error) = response.get_mut("error").and_then(Value::as_object_mut) {
+        if let Some(message) = error.remove("message") {
+            error.insert("description".to_owned(), message);
+        }
+    }
+    response
+}
+
+pub fn capabilities(request: CapabilitiesRequest) -> CapabilitiesResponse {
+    CapabilitiesResponse {
+        request_id: request.request_id,
+        capabilities: Capabilities {
+            protocols: vec!["VISSv2", SUBPROTOCOL],
+            transports: vec!["websocket", "http"],
+            filters: vec![
+                "paths",
+                "timebased",
+                "range",
+                "change",
+                "history",
+                "curvelog",
+                "metadata",
+                "capabilities",
+            ],
+        },
+        ts: SystemTime::now().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_msg() {
+        let request = parse_msg(
+            r#"{"action": "get", "path": "Vehicle", "requestId": "1",
+                "filter": {"variant": "metadata", "parameter": "1"}}"#,
+        );
+        match request {
+            Ok(Request::V2(v2::Request::Get(request))) => match request.filter.as_slice() {
+                [v2::Filter::StaticMetadata(filter)] => {
+                    assert_eq!(filter.parameter.depth.as_deref(), Some("1"))
+                }
+                _ => panic!("expected a static-metadata filter"),
+            },
+            _ => panic!("expected a get request"),
+        }
+
+        assert!(matches!(
+            parse_msg(
+                r#"{"action": "get", "path": "Vehicle", "requestId": "1",
+                    "filter": [{"variant": "capabilities"}]}"#
+            ),
+            Ok(Request::Capabilities(_))
+        ));
+
+        match parse_msg(r#"{"action": "get", "requestId": "1"}"#) {
+            Err(error_response) => assert_eq!(error_response.action.as_deref(), Some("get")),
+            Ok(_) => panic!("request without path should be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_response_from_v2() {
+        let response = serde_json::to_value(v2::GenericErrorResponse {
+            action: None,
+            request_id: None,
+            error: v2::Error::NotFoundInvalidPath,
+        })
+        .expect("response should serialize");
+        let error = &response_from_v2(response)["error"];
+        assert_eq!(error["number"], 404);
+        assert_eq!(error["reason"], "invalid_path");
+        assert_eq!(
+            error["description"],
+            "The specified data path does not exist."
+        );
+        assert!(error.get("message").is_none());
+    }
+}
@@ -0,0 +1,21 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+// VISSv3 is served by translating its messages from and to VISSv2, for the
+// parts it has in common with VISSv2.
+
+mod conversions;
+mod types;
+
+pub(crate) use conversions::{capabilities, parse_msg, response_from_v2};
+pub(crate) use types::*;
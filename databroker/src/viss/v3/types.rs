@@ -0,0 +1,51 @@
+/********************************************************************************
+* Copyright (c) 2024 Contributors to the Eclipse Foundation
+*
+* See the NOTICE file(s) distributed with this work for additional
+* information regarding copyright ownership.
+*
+* This program and the accompanying materials are made available under the
+* terms of the Apache License 2.0 which is available at
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* SPDX-License-Identifier: Apache-2.0
+********************************************************************************/
+
+use serde::{Deserialize, Serialize};
+
+use crate::viss::v2;
+
+pub const SUBPROTOCOL: &str = "VISS-v3.0";
+
+pub enum Request {
+    // A request VISSv2 has in common
+    V2(v2::Request),
+    Capabilities(CapabilitiesRequest),
+}
+
+// A get request with the filter `{"variant": "capabilities"}`, for clients to
+// discover what the server supports.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesRequest {
+    pub request_id: v2::RequestId,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "action", rename = "get", rename_all = "camelCase")]
+pub struct CapabilitiesResponse {
+    pub request_id: v2::RequestId,
+    pub capabilities: Capabilities,
+    pub ts: v2::Timestamp,
+}
+
+impl v2::Response for CapabilitiesResponse {}
+
+#[derive(Serialize)]
+pub struct Capabilities {
+    // Websocket subprotocols
+    pub protocols: Vec<&'static str>,
+    pub transports: Vec<&'static str>,
+    // Filter variants
+    pub filters: Vec<&'static str>,
+}
@@ -61,6 +61,13 @@ pub struct Entry {
     default: Option<serde_json::Value>,
 }
 
+/// The data entries of a VSS tree and the descriptions of its branches, both
+/// by path.
+pub struct Tree {
+    pub entries: BTreeMap<String, DataEntry>,
+    pub branches: BTreeMap<String, String>,
+}
+
 pub struct DataEntry {
     pub data_type: types::DataType,
     pub entry_type: types::EntryType,
@@ -397,26 +404,27 @@ fn try_from_json_single_value(
     }
 }
 
-fn flatten_vss_tree(root: RootEntry) -> Result<BTreeMap<String, DataEntry>, Error> {
-    let mut entries = BTreeMap::new();
+fn flatten_vss_tree(root: RootEntry) -> Result<Tree, Error> {
+    let mut tree = Tree {
+        entries: BTreeMap::new(),
+        branches: BTreeMap::new(),
+    };
 
     for (path, entry) in root.0 {
-        add_entry(&mut entries, path, entry)?;
+        add_entry(&mut tree, path, entry)?;
     }
-    Ok(entries)
+    Ok(tree)
 }
 
-fn add_entry(
-    entries: &mut BTreeMap<String, DataEntry>,
-    path: String,
-    entry: Entry,
-) -> Result<(), Error> {
+fn add_entry(tree: &mut Tree, path: String, entry: Entry) -> Result<(), Error> {
+    let entries = &mut tree.entries;
     match entry.entry_type {
         EntryType::Branch => match entry.children {
             Some(children) => {
                 for (name, child) in children {
-                    add_entry(entries, format!("{path}.{name}"), child)?;
+                    add_entry(tree, format!("{path}.{name}"), child)?;
                 }
+                tree.branches.insert(path, entry.description);
                 Ok(())
             }
             None => Err(Error::ParseError(
@@ -530,7 +538,7 @@ fn determine_change_type(
     }
 }
 
-pub fn parse_vss_from_reader<R>(reader: R) -> Result<BTreeMap<String, DataEntry>, Error>
+pub fn parse_vss_from_reader<R>(reader: R) -> Result<Tree, Error>
 where
     R: std::io::Read,
 {
@@ -542,7 +550,7 @@ where
     flatten_vss_tree(root_entry)
 }
 
-pub fn parse_vss_from_str(data: &str) -> Result<BTreeMap<String, DataEntry>, Error> {
+pub fn parse_vss_from_str(data: &str) -> Result<Tree, Error> {
     let root_entry = match serde_json::from_str::<RootEntry>(data) {
         Ok(root_entry) => root_entry,
         Err(err) => return Err(err.into()),
@@ -634,8 +642,13 @@ fn test_parse_vss() {
     };
 
     match flatten_vss_tree(root_entry) {
-        Ok(entries) => {
+        Ok(Tree { entries, branches }) => {
             assert_eq!(entries.len(), 5);
+            assert_eq!(
+                branches.get("Vehicle.ADAS.ESC").map(String::as_str),
+                Some("Electronic Stability Control System signals.")
+            );
+            assert_eq!(branches.len(), 4);
             match entries.get("Vehicle.ADAS.ESC.IsEnabled") {
                 Some(entry) => {
                     assert_eq!(entry.data_type, types::DataType::Bool);